@@ -0,0 +1,2 @@
+// MapLibre GL JS has not been downloaded yet, run "helpers/update_maplibre.sh" and rebuild.
+throw new Error('MapLibre GL JS is missing, run "helpers/update_maplibre.sh" and rebuild');
@@ -0,0 +1,15 @@
+#!/usr/bin/env bash
+cd "$(dirname "$0")"
+cd ..
+
+# MapLibre GL JS is embedded into the binary for the map preview, so that it works offline.
+VERSION="2.4.0"
+URL="https://unpkg.com/maplibre-gl@$VERSION"
+FOLDER="assets/maplibre-gl"
+
+set -e
+
+echo "download MapLibre GL JS $VERSION"
+curl -sSfL "$URL/dist/maplibre-gl.js" -o "$FOLDER/maplibre-gl.js"
+curl -sSfL "$URL/dist/maplibre-gl.css" -o "$FOLDER/maplibre-gl.css"
+curl -sSfL "$URL/LICENSE.txt" -o "$FOLDER/LICENSE.txt"
@@ -85,7 +85,7 @@ impl TileConverterTrait for TileConverter {
 				let tile_vec = reader.get_bbox_tile_vec(level, &row_bbox).await;
 				tile_vec.into_iter().par_bridge().for_each(|(coord, blob)| {
 					mutex_bar.lock().unwrap().inc(1);
-					let result = tile_converter.run(blob);
+					let result = tile_converter.run_at(blob, &coord.with_zoom(level));
 
 					if let Ok(blob) = result {
 						let filename = format!("./{}/{}/{}{}{}", level, coord.y, coord.x, ext_form, ext_comp);
@@ -111,3 +111,55 @@ impl TileConverterTrait for TileConverter {
 		self.builder.finish().unwrap();
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		containers::dummy::{ReaderProfile, TileReader},
+		shared::{decompress_gzip, Blob, TileBBoxPyramide},
+	};
+	use assert_fs::NamedTempFile;
+	use std::io::Read;
+	use tar::Archive;
+
+	#[tokio::test]
+	async fn convert_to_geojson() {
+		let file = NamedTempFile::new("temp.tar").unwrap();
+		let mut reader = TileReader::new_dummy(ReaderProfile::PbfFast, 1);
+		let config = TileConverterConfig::new(
+			Some(TileFormat::GEOJSON),
+			Some(Compression::Gzip),
+			TileBBoxPyramide::new_full(),
+			false,
+		);
+		let mut converter = TileConverter::new(file.path(), config);
+		converter.convert_from(&mut reader).await;
+
+		let mut archive = Archive::new(File::open(file.path()).unwrap());
+		let mut names: Vec<String> = Vec::new();
+		for entry in archive.entries().unwrap() {
+			let mut entry = entry.unwrap();
+			let name = entry.path().unwrap().to_str().unwrap().to_owned();
+			if name.ends_with(".geojson.gz") {
+				let mut data = Vec::new();
+				entry.read_to_end(&mut data).unwrap();
+				let geojson = decompress_gzip(Blob::from(data)).unwrap();
+				assert!(geojson.as_str().starts_with("{\"type\":\"FeatureCollection\""));
+			}
+			names.push(name);
+		}
+		names.sort();
+		assert_eq!(
+			names,
+			vec![
+				"0/0/0.geojson.gz",
+				"1/0/0.geojson.gz",
+				"1/0/1.geojson.gz",
+				"1/1/0.geojson.gz",
+				"1/1/1.geojson.gz",
+				"tiles.json.gz"
+			]
+		);
+	}
+}
@@ -7,6 +7,19 @@ use std::{fmt::Debug, path::Path};
 pub type TileConverterBox = Box<dyn TileConverterTrait>;
 pub type TileReaderBox = Box<dyn TileReaderTrait>;
 
+/// Counters of a reader, e.g. for monitoring a server.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TileReaderStats {
+	/// lookups answered by the reader's cache
+	pub cache_hits: u64,
+	/// lookups that had to read from the source
+	pub cache_misses: u64,
+	/// range requests sent to a remote source
+	pub range_requests: u64,
+	/// bytes received from a remote source
+	pub range_bytes: u64,
+}
+
 #[allow(clippy::new_ret_no_self)]
 #[async_trait]
 pub trait TileConverterTrait {
@@ -61,6 +74,11 @@ pub trait TileReaderTrait: Debug + Send + Sync {
 		return vec;
 	}
 
+	/// counters for monitoring, e.g. cache hits
+	fn get_stats(&self) -> TileReaderStats {
+		TileReaderStats::default()
+	}
+
 	/// verify container and output data to output_folder
 	async fn deep_verify(&self, _output_folder: &Path) {
 		todo!()
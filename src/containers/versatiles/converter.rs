@@ -127,7 +127,10 @@ impl TileConverter {
 			if !tile_converter.is_empty() {
 				blobs = blobs
 					.par_iter()
-					.map(|(coord, blob)| (coord.clone(), tile_converter.run(blob.clone()).unwrap()))
+					.map(|(coord, blob)| {
+						let tile = tile_converter.run_at(blob.clone(), &coord.with_zoom(block.z)).unwrap();
+						(coord.clone(), tile)
+					})
 					.collect();
 			}
 
@@ -1,6 +1,6 @@
 use super::types::*;
 use crate::{
-	containers::{TileReaderBox, TileReaderTrait},
+	containers::{TileReaderBox, TileReaderStats, TileReaderTrait},
 	shared::{
 		Blob, DataConverter, ProgressBar, Result, StatusImagePyramide, TileCoord2, TileCoord3, TileReaderParameters,
 	},
@@ -8,7 +8,13 @@ use crate::{
 use async_trait::async_trait;
 use itertools::Itertools;
 use log::debug;
-use std::{collections::HashMap, fmt::Debug, ops::Shr, path::Path};
+use std::{
+	collections::HashMap,
+	fmt::Debug,
+	ops::Shr,
+	path::Path,
+	sync::atomic::{AtomicU64, Ordering},
+};
 use tokio::sync::RwLock;
 
 pub struct TileReader {
@@ -17,6 +23,8 @@ pub struct TileReader {
 	parameters: TileReaderParameters,
 	block_index: BlockIndex,
 	tile_index_cache: RwLock<HashMap<TileCoord3, TileIndex>>,
+	cache_hits: AtomicU64,
+	cache_misses: AtomicU64,
 }
 
 impl TileReader {
@@ -41,6 +49,8 @@ impl TileReader {
 			parameters,
 			block_index,
 			tile_index_cache: RwLock::new(HashMap::new()),
+			cache_hits: AtomicU64::new(0),
+			cache_misses: AtomicU64::new(0),
 		}
 	}
 }
@@ -107,8 +117,10 @@ impl TileReaderTrait for TileReader {
 			tile_range = *tile_index.get(tile_id);
 
 			drop(cache_reader);
+			self.cache_hits.fetch_add(1, Ordering::Relaxed);
 		} else {
 			drop(cache_reader);
+			self.cache_misses.fetch_add(1, Ordering::Relaxed);
 			let blob = self.reader.read_range(&block.index_range).await.unwrap();
 			let mut tile_index = TileIndex::from_brotli_blob(blob);
 			tile_index.add_offset(block.tiles_range.offset);
@@ -131,6 +143,15 @@ impl TileReaderTrait for TileReader {
 	fn get_name(&self) -> &str {
 		self.reader.get_name()
 	}
+	fn get_stats(&self) -> TileReaderStats {
+		let (range_requests, range_bytes) = self.reader.get_request_stats();
+		TileReaderStats {
+			cache_hits: self.cache_hits.load(Ordering::Relaxed),
+			cache_misses: self.cache_misses.load(Ordering::Relaxed),
+			range_requests,
+			range_bytes,
+		}
+	}
 	async fn deep_verify(&self, output_folder: &Path) {
 		let block_count = self.block_index.len() as u64;
 
@@ -183,7 +204,7 @@ mod tests {
 	use super::TileReader;
 	use crate::{
 		containers::{tests::make_test_file, TileReaderTrait},
-		shared::{Compression, TileFormat},
+		shared::{Compression, TileCoord3, TileFormat},
 	};
 	use assert_fs::TempDir;
 
@@ -194,4 +215,19 @@ mod tests {
 		let reader = TileReader::new(temp_file.to_str().unwrap()).await.unwrap();
 		reader.deep_verify(temp_dir.path()).await;
 	}
+
+	#[tokio::test]
+	async fn test_stats() {
+		let temp_file = make_test_file(TileFormat::PBF, Compression::Gzip, 3, "versatiles").await;
+		let reader = TileReader::new(temp_file.to_str().unwrap()).await.unwrap();
+
+		reader.get_tile_data(&TileCoord3::new(0, 0, 1)).await.unwrap();
+		reader.get_tile_data(&TileCoord3::new(1, 0, 1)).await.unwrap();
+		reader.get_tile_data(&TileCoord3::new(0, 0, 2)).await.unwrap();
+
+		let stats = reader.get_stats();
+		assert_eq!(stats.cache_hits, 1);
+		assert_eq!(stats.cache_misses, 2);
+		assert_eq!(stats.range_requests, 0);
+	}
 }
@@ -7,6 +7,7 @@ use std::{
 	fs::File,
 	io::{BufReader, Read, Seek, SeekFrom},
 	path::Path,
+	sync::atomic::{AtomicU64, Ordering},
 	time::Duration,
 };
 use tokio::sync::Mutex;
@@ -18,6 +19,11 @@ pub trait VersaTilesSrcTrait: Send + Sync {
 		Self: Sized;
 	async fn read_range(&self, range: &ByteRange) -> Result<Blob>;
 	fn get_name(&self) -> &str;
+
+	/// number of range requests and bytes read from a remote source
+	fn get_request_stats(&self) -> (u64, u64) {
+		(0, 0)
+	}
 }
 
 pub fn new_versatiles_src(source: &str) -> Result<Box<dyn VersaTilesSrcTrait>> {
@@ -109,6 +115,8 @@ struct VersaTilesSrcHttp {
 	name: String,
 	url: Url,
 	client: Client,
+	request_count: AtomicU64,
+	byte_count: AtomicU64,
 }
 #[async_trait]
 impl VersaTilesSrcTrait for VersaTilesSrcHttp {
@@ -122,6 +130,8 @@ impl VersaTilesSrcTrait for VersaTilesSrcHttp {
 				name: source.to_string(),
 				url: Url::parse(source)?,
 				client,
+				request_count: AtomicU64::new(0),
+				byte_count: AtomicU64::new(0),
 			})
 		} else {
 			Err(Error::new(&format!(
@@ -141,6 +151,9 @@ impl VersaTilesSrcTrait for VersaTilesSrcHttp {
 
 		let bytes = result.bytes().await?;
 
+		self.request_count.fetch_add(1, Ordering::Relaxed);
+		self.byte_count.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+
 		//let range = result.headers().get("content-range");
 		//println!("range {:#?}", range);
 
@@ -149,4 +162,10 @@ impl VersaTilesSrcTrait for VersaTilesSrcHttp {
 	fn get_name(&self) -> &str {
 		&self.name
 	}
+	fn get_request_stats(&self) -> (u64, u64) {
+		(
+			self.request_count.load(Ordering::Relaxed),
+			self.byte_count.load(Ordering::Relaxed),
+		)
+	}
 }
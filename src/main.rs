@@ -28,6 +28,7 @@ pub struct Cli {
 
 // Define subcommands for the command-line interface
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Commands {
 	/// Convert between different tile containers
 	Convert(tools::convert::Subcommand),
@@ -43,21 +44,25 @@ pub enum Commands {
 fn main() {
 	let cli = Cli::parse();
 
-	// Initialize logger and set log level based on verbosity flag
-	env_logger::Builder::new()
-		.filter_level(cli.verbose.log_level_filter())
-		.init();
+	// Initialize logger and set log level based on verbosity flag.
+	// The level is set globally, so that subcommands can change it later, e.g. from a config file.
+	env_logger::Builder::new().filter_level(log::LevelFilter::Trace).init();
+	log::set_max_level(cli.verbose.log_level_filter());
 
-	run(cli);
+	if let Err(err) = run(cli) {
+		eprintln!("error: {err}");
+		std::process::exit(1);
+	}
 }
 
 // Helper function for running subcommands
-fn run(cli: Cli) {
+fn run(cli: Cli) -> shared::Result<()> {
 	match &cli.command {
 		Commands::Convert(arguments) => tools::convert::run(arguments),
 		Commands::Probe(arguments) => tools::probe::run(arguments),
-		Commands::Serve(arguments) => tools::serve::run(arguments),
+		Commands::Serve(arguments) => tools::serve::run(arguments)?,
 	}
+	Ok(())
 }
 
 // Unit tests for the command-line interface
@@ -72,7 +77,7 @@ mod tests {
 		match Cli::try_parse_from(arg_vec) {
 			Ok(cli) => {
 				let msg = format!("{:?}", cli);
-				run(cli);
+				run(cli).map_err(|err| err.to_string())?;
 				Ok(msg)
 			}
 			Err(error) => Err(error.render().to_string()),
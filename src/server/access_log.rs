@@ -0,0 +1,318 @@
+use crate::shared::{json_string, Error, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::{
+	fs::{metadata, rename, File, OpenOptions},
+	io::{stdout, Write},
+	net::IpAddr,
+	path::{Path, PathBuf},
+	sync::Mutex,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// number of rotated files that are kept, e.g. "access.log.1" to "access.log.5"
+const ROTATED_FILES: usize = 5;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+	/// common log format, extended by content encoding, duration and source
+	#[default]
+	Common,
+	/// one JSON object per line
+	Json,
+}
+
+/// Access log settings of the tile server.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct AccessLogConfig {
+	#[serde(default)]
+	pub format: AccessLogFormat,
+	/// log file, stdout if not set or "-"
+	pub path: Option<PathBuf>,
+	/// rotate the log file when it grows beyond this number of bytes
+	pub max_size: Option<u64>,
+}
+
+impl AccessLogConfig {
+	pub fn get_file(&self) -> Option<&Path> {
+		self.path.as_deref().filter(|path| path != &Path::new("-"))
+	}
+
+	pub fn validate(&self) -> Result<()> {
+		if let Some(path) = self.get_file() {
+			let folder = path.parent().filter(|folder| !folder.as_os_str().is_empty());
+			if folder.is_some_and(|folder| !folder.is_dir()) {
+				return Err(Error::new(&format!("folder of access log {path:?} does not exist")));
+			}
+		}
+		if self.max_size == Some(0) {
+			return Err(Error::new("max size of access log must be greater than 0"));
+		}
+		Ok(())
+	}
+}
+
+/// A served request, as written to the access log.
+#[derive(Clone, Debug)]
+pub struct AccessLogEntry {
+	pub time: SystemTime,
+	pub remote: Option<IpAddr>,
+	pub method: String,
+	pub path: String,
+	pub version: String,
+	pub status: u16,
+	pub bytes: u64,
+	pub encoding: String,
+	pub duration: Duration,
+	pub source: String,
+}
+
+enum Output {
+	Stdout,
+	File { file: File, size: u64 },
+}
+
+/// Writes access log lines to stdout or a file.
+///
+/// Files are rotated when they exceed the maximum size. `reopen` reopens the file,
+/// e.g. after it was moved by an external tool like logrotate.
+pub struct AccessLog {
+	config: AccessLogConfig,
+	output: Mutex<Output>,
+}
+
+impl AccessLog {
+	pub fn new(config: &AccessLogConfig) -> Result<AccessLog> {
+		Ok(AccessLog {
+			config: config.clone(),
+			output: Mutex::new(open(config)?),
+		})
+	}
+
+	pub fn reopen(&self) -> Result<()> {
+		*self.output.lock().unwrap() = open(&self.config)?;
+		Ok(())
+	}
+
+	pub fn log(&self, entry: &AccessLogEntry) {
+		let line = match self.config.format {
+			AccessLogFormat::Common => format_common(entry),
+			AccessLogFormat::Json => format_json(entry),
+		};
+
+		let mut output = self.output.lock().unwrap();
+
+		if let Output::File { size, .. } = &*output {
+			if self
+				.config
+				.max_size
+				.is_some_and(|max_size| *size > 0 && size + line.len() as u64 > max_size)
+			{
+				match self.rotate() {
+					Ok(new_output) => *output = new_output,
+					Err(err) => log::error!("can not rotate access log: {err}"),
+				}
+			}
+		}
+
+		let result = match &mut *output {
+			Output::Stdout => stdout().lock().write_all(line.as_bytes()),
+			Output::File { file, size } => {
+				*size += line.len() as u64;
+				file.write_all(line.as_bytes())
+			}
+		};
+		if let Err(err) = result {
+			log::error!("can not write access log: {err}");
+		}
+	}
+
+	/// Shifts "access.log.1" to "access.log.2" and so on, moves the current file to "access.log.1"
+	/// and opens a new file.
+	fn rotate(&self) -> Result<Output> {
+		let path = self.config.get_file().unwrap();
+		let rotated = |index: usize| PathBuf::from(format!("{}.{index}", path.to_str().unwrap()));
+
+		for index in (1..ROTATED_FILES).rev() {
+			if rotated(index).exists() {
+				rename(rotated(index), rotated(index + 1))?;
+			}
+		}
+		rename(path, rotated(1))?;
+
+		open(&self.config)
+	}
+}
+
+fn open(config: &AccessLogConfig) -> Result<Output> {
+	Ok(match config.get_file() {
+		None => Output::Stdout,
+		Some(path) => {
+			let file = OpenOptions::new()
+				.create(true)
+				.append(true)
+				.open(path)
+				.map_err(|e| Error::new(&format!("can not open access log {path:?}: {e}")))?;
+			let size = metadata(path)?.len();
+			Output::File { file, size }
+		}
+	})
+}
+
+fn format_common(entry: &AccessLogEntry) -> String {
+	let remote = entry.remote.map_or("-".to_owned(), |ip| ip.to_string());
+	let (year, month, day, hour, minute, second) = get_utc(entry.time);
+	let month = [
+		"Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+	][month as usize - 1];
+
+	format!(
+		"{remote} - - [{day:02}/{month}/{year}:{hour:02}:{minute:02}:{second:02} +0000] \"{} {} {}\" {} {} \"{}\" {:.6} \"{}\"\n",
+		entry.method,
+		entry.path,
+		entry.version,
+		entry.status,
+		entry.bytes,
+		entry.encoding,
+		entry.duration.as_secs_f64(),
+		entry.source,
+	)
+}
+
+fn format_json(entry: &AccessLogEntry) -> String {
+	let remote = entry.remote.map_or("null".to_owned(), |ip| format!("\"{ip}\""));
+	let (year, month, day, hour, minute, second) = get_utc(entry.time);
+
+	format!(
+		"{{\"time\":\"{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z\",\"remote\":{remote},\"method\":{},\"path\":{},\"version\":{},\"status\":{},\"bytes\":{},\"encoding\":{},\"duration\":{:.6},\"source\":{}}}\n",
+		json_string(&entry.method),
+		json_string(&entry.path),
+		json_string(&entry.version),
+		entry.status,
+		entry.bytes,
+		json_string(&entry.encoding),
+		entry.duration.as_secs_f64(),
+		json_string(&entry.source),
+	)
+}
+
+/// Converts a time into UTC year, month, day, hour, minute and second.
+fn get_utc(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+	let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+	let time_of_day = seconds.rem_euclid(86400) as u32;
+
+	// civil from days, see: http://howardhinnant.github.io/date_algorithms.html
+	let days = seconds.div_euclid(86400) + 719468;
+	let era = days.div_euclid(146097);
+	let day_of_era = days.rem_euclid(146097);
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+	(
+		year,
+		month,
+		day,
+		time_of_day / 3600,
+		time_of_day / 60 % 60,
+		time_of_day % 60,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assert_fs::TempDir;
+	use std::fs::read_to_string;
+
+	fn make_entry() -> AccessLogEntry {
+		AccessLogEntry {
+			time: UNIX_EPOCH + Duration::from_secs(1_681_000_000),
+			remote: Some("127.0.0.1".parse().unwrap()),
+			method: "GET".to_owned(),
+			path: "/tiles/osm/0/0/0".to_owned(),
+			version: "HTTP/1.1".to_owned(),
+			status: 200,
+			bytes: 1234,
+			encoding: "br".to_owned(),
+			duration: Duration::from_micros(2500),
+			source: "/tiles/osm/".to_owned(),
+		}
+	}
+
+	#[test]
+	fn test_get_utc() {
+		assert_eq!(get_utc(UNIX_EPOCH), (1970, 1, 1, 0, 0, 0));
+		assert_eq!(
+			get_utc(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+			(2000, 2, 29, 0, 0, 0)
+		);
+		assert_eq!(
+			get_utc(UNIX_EPOCH + Duration::from_secs(1_681_000_000)),
+			(2023, 4, 9, 0, 26, 40)
+		);
+	}
+
+	#[test]
+	fn test_formats() {
+		let entry = make_entry();
+		assert_eq!(
+			format_common(&entry),
+			"127.0.0.1 - - [09/Apr/2023:00:26:40 +0000] \"GET /tiles/osm/0/0/0 HTTP/1.1\" 200 1234 \"br\" 0.002500 \"/tiles/osm/\"\n"
+		);
+		assert_eq!(
+			format_json(&entry),
+			"{\"time\":\"2023-04-09T00:26:40Z\",\"remote\":\"127.0.0.1\",\"method\":\"GET\",\"path\":\"/tiles/osm/0/0/0\",\"version\":\"HTTP/1.1\",\"status\":200,\"bytes\":1234,\"encoding\":\"br\",\"duration\":0.002500,\"source\":\"/tiles/osm/\"}\n"
+		);
+	}
+
+	#[test]
+	fn test_rotation() {
+		let dir = TempDir::new().unwrap();
+		let path = dir.path().join("access.log");
+		let config = AccessLogConfig {
+			format: AccessLogFormat::Json,
+			path: Some(path.clone()),
+			max_size: Some(500),
+		};
+		config.validate().unwrap();
+
+		let access_log = AccessLog::new(&config).unwrap();
+		for _ in 0..5 {
+			access_log.log(&make_entry());
+		}
+
+		let line_count = |path: &Path| read_to_string(path).unwrap().lines().count();
+		// every line has about 200 bytes, so every file holds 2 lines
+		assert_eq!(line_count(&path), 1);
+		assert_eq!(line_count(&dir.path().join("access.log.1")), 2);
+		assert_eq!(line_count(&dir.path().join("access.log.2")), 2);
+
+		// reopen after the file was moved away
+		rename(&path, dir.path().join("moved.log")).unwrap();
+		access_log.reopen().unwrap();
+		access_log.log(&make_entry());
+		assert_eq!(line_count(&path), 1);
+	}
+
+	#[test]
+	fn test_validate() {
+		let config = AccessLogConfig {
+			path: Some(PathBuf::from("missing/folder/access.log")),
+			..Default::default()
+		};
+		assert!(config.validate().is_err());
+		assert!(AccessLogConfig::default().validate().is_ok());
+		assert!(AccessLogConfig {
+			max_size: Some(0),
+			..Default::default()
+		}
+		.validate()
+		.is_err());
+	}
+}
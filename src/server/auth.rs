@@ -0,0 +1,158 @@
+use axum::http::{header::AUTHORIZATION, HeaderMap, HeaderName, Uri};
+use std::collections::HashMap;
+
+/// header for API keys
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+/// query parameter for API keys
+pub const API_KEY_PARAMETER: &str = "api_key";
+
+/// Result of an access check.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Access {
+	Granted,
+	/// no token or an unknown token, answered with 401
+	Unauthorized,
+	/// a valid token, but not for this source, answered with 403
+	Forbidden,
+}
+
+/// Maps access tokens to the prefixes of the private sources they may access.
+/// The prefix "*" grants access to all sources.
+#[derive(Clone, Debug, Default)]
+pub struct AccessTokens {
+	tokens: HashMap<String, Vec<String>>,
+}
+
+impl AccessTokens {
+	pub fn new() -> AccessTokens {
+		AccessTokens::default()
+	}
+
+	pub fn add(&mut self, token: &str, prefixes: &[String]) {
+		self
+			.tokens
+			.entry(token.to_owned())
+			.or_default()
+			.extend_from_slice(prefixes);
+	}
+
+	/// Checks whether a token may access the private source with this prefix.
+	pub fn check(&self, token: Option<&str>, prefix: &str) -> Access {
+		let prefixes = match token.and_then(|token| self.tokens.get(token)) {
+			Some(prefixes) => prefixes,
+			None => return Access::Unauthorized,
+		};
+
+		if prefixes.iter().any(|allowed| allowed == "*" || allowed == prefix) {
+			Access::Granted
+		} else {
+			Access::Forbidden
+		}
+	}
+}
+
+/// Reads the token from "Authorization: Bearer <token>", "X-API-Key: <token>" or "?api_key=<token>".
+pub fn get_token(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+	if let Some(value) = headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()) {
+		if let Some(token) = value.strip_prefix("Bearer ") {
+			return Some(token.trim().to_owned());
+		}
+	}
+
+	if let Some(value) = headers.get(API_KEY_HEADER).and_then(|value| value.to_str().ok()) {
+		return Some(value.trim().to_owned());
+	}
+
+	uri.query()?.split('&').find_map(|pair| {
+		let (key, value) = pair.split_once('=')?;
+		(key == API_KEY_PARAMETER).then(|| decode_percent(value))
+	})
+}
+
+/// Replaces the API key in a path with query, so that it doesn't end up in logs.
+pub fn redact_token(path_and_query: &str) -> String {
+	let (path, query) = match path_and_query.split_once('?') {
+		Some(parts) => parts,
+		None => return path_and_query.to_owned(),
+	};
+
+	let query: Vec<String> = query
+		.split('&')
+		.map(|pair| match pair.split_once('=') {
+			Some((API_KEY_PARAMETER, _)) => format!("{API_KEY_PARAMETER}=REDACTED"),
+			_ => pair.to_owned(),
+		})
+		.collect();
+
+	format!("{path}?{}", query.join("&"))
+}
+
+/// Decodes percent-encoded bytes and "+" in a query value.
+pub fn decode_percent(value: &str) -> String {
+	let bytes = value.as_bytes();
+	let mut result: Vec<u8> = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+
+	while i < bytes.len() {
+		let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+		match (bytes[i], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+			(b'%', Some(byte)) => {
+				result.push(byte);
+				i += 3;
+			}
+			(b'+', _) => {
+				result.push(b' ');
+				i += 1;
+			}
+			(byte, _) => {
+				result.push(byte);
+				i += 1;
+			}
+		}
+	}
+
+	String::from_utf8_lossy(&result).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_check() {
+		let mut tokens = AccessTokens::new();
+		tokens.add("partner", &["/tiles/a/".to_owned()]);
+		tokens.add("admin", &["*".to_owned()]);
+
+		assert_eq!(tokens.check(Some("partner"), "/tiles/a/"), Access::Granted);
+		assert_eq!(tokens.check(Some("partner"), "/tiles/b/"), Access::Forbidden);
+		assert_eq!(tokens.check(Some("admin"), "/tiles/b/"), Access::Granted);
+		assert_eq!(tokens.check(Some("unknown"), "/tiles/a/"), Access::Unauthorized);
+		assert_eq!(tokens.check(None, "/tiles/a/"), Access::Unauthorized);
+	}
+
+	#[test]
+	fn test_get_token() {
+		let test = |header: Option<(&str, &str)>, uri: &str| {
+			let mut headers = HeaderMap::new();
+			if let Some((key, value)) = header {
+				headers.insert(HeaderName::from_bytes(key.as_bytes()).unwrap(), value.parse().unwrap());
+			}
+			get_token(&headers, &uri.parse().unwrap())
+		};
+
+		assert_eq!(test(Some(("authorization", "Bearer abc")), "/"), Some("abc".to_owned()));
+		assert_eq!(test(Some(("authorization", "Basic abc")), "/"), None);
+		assert_eq!(test(Some(("x-api-key", "abc")), "/"), Some("abc".to_owned()));
+		assert_eq!(test(None, "/tiles/0/0/0?x=1&api_key=a%2Bb"), Some("a+b".to_owned()));
+		assert_eq!(test(None, "/tiles/0/0/0?api_keys=abc"), None);
+		assert_eq!(test(None, "/tiles/0/0/0"), None);
+	}
+
+	#[test]
+	fn test_redact_token() {
+		assert_eq!(redact_token("/a/b"), "/a/b");
+		assert_eq!(redact_token("/a?x=1&api_key=secret"), "/a?x=1&api_key=REDACTED");
+		assert_eq!(decode_percent("a%20b+c%zz"), "a b c%zz");
+	}
+}
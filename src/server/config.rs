@@ -0,0 +1,1217 @@
+use super::{
+	clean_prefix, prefixes_overlap, source, source::MAX_OVERZOOM, AccessLogConfig, CorsConfig, IpRange, RateLimitConfig,
+	ServerSourceTrait, TileFilterConfig, TileServer, TlsConfig,
+};
+#[cfg(unix)]
+use super::{parse_socket_mode, UnixSocketConfig};
+use crate::{
+	containers::get_reader,
+	shared::{Error, Result},
+};
+use log::LevelFilter;
+use serde::Deserialize;
+use std::{
+	collections::HashMap,
+	fs::{read_dir, read_to_string},
+	net::IpAddr,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+const CONTAINER_EXTENSIONS: [&str; 3] = ["mbtiles", "tar", "versatiles"];
+
+/// Declarative configuration of the tile server, read from a YAML or TOML file.
+///
+/// ```yaml
+/// ip: 0.0.0.0
+/// port: 8080
+/// sources:
+///   - path: data/osm.versatiles
+///     name: osm
+///     cache_max_age: 86400
+///     filter: {layers: [water, roads], fields: [name, class]}
+///     overzoom: 4
+///   - path: https://example.org/satellite.versatiles
+///     prefix: /satellite/
+///     flip_y: true
+///     private: true
+///     rate_limit: {rate: 10, burst: 50}
+///     expose_file: true
+/// source_dirs:
+///   - path: data/regions/
+///     recursive: true
+///     glob: "*.versatiles"
+/// composites:
+///   - name: city
+///     sources:
+///       - path: data/osm.versatiles
+///       - path: data/transit.versatiles
+///         rename: {poi: transit_poi}
+/// static:
+///   - public/
+/// hosts:
+///   - hostnames: [maps.customer.org]
+///     sources:
+///       - path: data/customer.versatiles
+///     static: [customer/]
+/// cors:
+///   origins: ["https://*.example.org"]
+/// logging:
+///   level: info
+///   access:
+///     format: json
+///     path: access.log
+///     max_size: 100000000
+/// watch: true
+/// admin_token: secret
+/// metrics: true
+/// preview: true
+/// wmts: true
+/// ogc_api: true
+/// base_url: https://tiles.example.org
+/// drain_period: 5
+/// shutdown_timeout: 30
+/// tokens:
+///   - token: partner-secret
+///     sources: [satellite]
+/// rate_limit: {rate: 100}
+/// trusted_proxies: [10.0.0.0/8]
+/// ```
+///
+/// Instead of `ip` and `port` the server can listen on a unix domain socket:
+///
+/// ```yaml
+/// socket: /run/versatiles.sock
+/// socket_mode: "660"
+/// ```
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+	pub ip: Option<String>,
+	pub port: Option<u16>,
+	/// unix domain socket, replaces ip and port
+	pub socket: Option<String>,
+	/// file permissions of the socket in octal notation, e.g. "660"
+	pub socket_mode: Option<String>,
+	pub tls: Option<TlsConfig>,
+	pub cors: Option<CorsConfig>,
+	pub logging: LoggingConfig,
+	pub sources: Vec<SourceConfig>,
+	/// directories, whose containers are all served
+	pub source_dirs: Vec<SourceDirConfig>,
+	/// vector sources combining the layers of several containers
+	pub composites: Vec<CompositeConfig>,
+	#[serde(rename = "static")]
+	pub static_sources: Vec<String>,
+	/// sources and static content of other host names, unmatched hosts are served from the lists above
+	pub hosts: Vec<HostConfig>,
+	/// reload sources automatically when their files are modified
+	pub watch: bool,
+	/// enables the admin API, secured with this bearer token
+	pub admin_token: Option<String>,
+	/// enables the endpoint "/metrics" in the Prometheus text format
+	pub metrics: bool,
+	/// enables a map preview of every source at "/preview/{name}"
+	pub preview: bool,
+	/// enables the OGC WMTS endpoint at "/wmts"
+	pub wmts: bool,
+	/// enables OGC API - Tiles at "/ogc"
+	pub ogc_api: bool,
+	/// public url of the server for absolute urls in WMTS and OGC API, e.g. "https://tiles.example.org"
+	pub base_url: Option<String>,
+	/// seconds to keep accepting requests on shutdown, while "/status" responds with 503
+	pub drain_period: Option<u64>,
+	/// seconds to wait for active requests on shutdown, 10 if not set
+	pub shutdown_timeout: Option<u64>,
+	/// access tokens for private sources
+	pub tokens: Vec<TokenConfig>,
+	/// rate limit per client for the whole server
+	pub rate_limit: Option<RateLimitConfig>,
+	/// IP addresses or networks of reverse proxies, whose "X-Forwarded-For" header identifies the client
+	pub trusted_proxies: Vec<String>,
+}
+
+/// A tile container served by the tile server.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+	/// file path or url of the container
+	pub path: String,
+	/// name of the source, derived from the filename if not set
+	pub name: Option<String>,
+	/// url prefix, "/tiles/{name}/" if not set
+	pub prefix: Option<String>,
+	/// flip the y coordinate of the container
+	#[serde(default)]
+	pub flip_y: bool,
+	/// clients and proxies may cache tiles for this many seconds
+	pub cache_max_age: Option<u64>,
+	/// only accessible with an access token
+	#[serde(default)]
+	pub private: bool,
+	/// rate limit per client for this source
+	pub rate_limit: Option<RateLimitConfig>,
+	/// serves the container file at "/files/{name}.{extension}"
+	#[serde(default)]
+	pub expose_file: bool,
+	/// layers and fields of vector tiles, that clients may select with "?layers=…&fields=…"
+	pub filter: Option<TileFilterConfig>,
+	/// serves tiles up to this many zoom levels beyond the maximum zoom level of the container
+	pub overzoom: Option<u8>,
+}
+
+/// A directory of containers. Every readable container is served with a name derived from its filename.
+/// With `watch` enabled, containers added later are served, too.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SourceDirConfig {
+	pub path: String,
+	/// include subdirectories
+	#[serde(default)]
+	pub recursive: bool,
+	/// only filenames matching this pattern, e.g. "osm-*.versatiles", supports "*" and "?"
+	pub glob: Option<String>,
+}
+
+/// Vector tiles that combine the layers of several containers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CompositeConfig {
+	pub name: String,
+	/// url prefix, "/tiles/{name}/" if not set
+	pub prefix: Option<String>,
+	/// containers in the order their layers are added
+	pub sources: Vec<CompositePartConfig>,
+	/// clients and proxies may cache tiles for this many seconds
+	pub cache_max_age: Option<u64>,
+	/// only accessible with an access token
+	#[serde(default)]
+	pub private: bool,
+}
+
+/// A container of a composite source.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CompositePartConfig {
+	/// file path or url of the container
+	pub path: String,
+	/// renames layers of this container, e.g. {poi: transit_poi}
+	#[serde(default)]
+	pub rename: HashMap<String, String>,
+}
+
+/// Tile sources and static content served for requests to some host names.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+	pub hostnames: Vec<String>,
+	#[serde(default)]
+	pub sources: Vec<SourceConfig>,
+	#[serde(default, rename = "static")]
+	pub static_sources: Vec<String>,
+}
+
+/// An access token and the names of the private sources it grants access to, "*" for all sources.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct TokenConfig {
+	pub token: String,
+	pub sources: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+	/// one of: off, error, warn, info, debug, trace
+	pub level: Option<String>,
+	/// logs every request
+	pub access: Option<AccessLogConfig>,
+}
+
+impl ServerConfig {
+	/// Reads a config file. The format is chosen by the extension: ".yaml", ".yml" or ".toml".
+	/// Relative paths in the file are resolved relative to the directory of the file.
+	pub fn from_file(filename: &str) -> Result<ServerConfig> {
+		let path = Path::new(filename);
+		let text = read_to_string(path).map_err(|e| Error::new(&format!("can not read config file {path:?}: {e}")))?;
+
+		let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+		let mut config: ServerConfig = match extension {
+			"yaml" | "yml" => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+			"toml" => toml::from_str(&text).map_err(|e| e.to_string()),
+			_ => {
+				return Err(Error::new(&format!(
+					"config file {path:?} must have the extension .yaml, .yml or .toml"
+				)))
+			}
+		}
+		.map_err(|e| Error::new(&format!("can not parse config file {path:?}: {e}")))?;
+
+		if let Some(folder) = path.parent() {
+			config.resolve_paths(folder);
+		}
+
+		Ok(config)
+	}
+
+	fn resolve_paths(&mut self, folder: &Path) {
+		let resolve = |filename: &str| -> String {
+			if is_url(filename) || Path::new(filename).is_absolute() {
+				filename.to_owned()
+			} else {
+				folder.join(filename).to_str().unwrap().to_owned()
+			}
+		};
+
+		for source in self.sources.iter_mut() {
+			source.path = resolve(&source.path);
+		}
+		for source_dir in self.source_dirs.iter_mut() {
+			source_dir.path = resolve(&source_dir.path);
+		}
+		for composite in self.composites.iter_mut() {
+			for part in composite.sources.iter_mut() {
+				part.path = resolve(&part.path);
+			}
+		}
+		for filename in self.static_sources.iter_mut() {
+			*filename = resolve(filename);
+		}
+		for host in self.hosts.iter_mut() {
+			for source in host.sources.iter_mut() {
+				source.path = resolve(&source.path);
+			}
+			for filename in host.static_sources.iter_mut() {
+				*filename = resolve(filename);
+			}
+		}
+		if let Some(tls) = &self.tls {
+			let cert = resolve(tls.get_cert_path().to_str().unwrap());
+			let key = resolve(tls.get_key_path().to_str().unwrap());
+			self.tls = Some(TlsConfig::new(&cert, &key));
+		}
+		if let Some(socket) = &mut self.socket {
+			*socket = resolve(socket);
+		}
+		if let Some(access_log) = &mut self.logging.access {
+			if let Some(path) = access_log.get_file() {
+				access_log.path = Some(PathBuf::from(resolve(path.to_str().unwrap())));
+			}
+		}
+	}
+
+	pub fn get_ip(&self) -> &str {
+		self.ip.as_deref().unwrap_or("127.0.0.1")
+	}
+
+	pub fn get_port(&self) -> u16 {
+		self.port.unwrap_or(8080)
+	}
+
+	#[cfg(unix)]
+	pub fn get_socket(&self) -> Result<Option<UnixSocketConfig>> {
+		let mode = self.socket_mode.as_deref().map(parse_socket_mode).transpose()?;
+		Ok(self.socket.as_deref().map(|path| UnixSocketConfig::new(path, mode)))
+	}
+
+	pub fn get_trusted_proxies(&self) -> Result<Vec<IpRange>> {
+		self.trusted_proxies.iter().map(|proxy| IpRange::parse(proxy)).collect()
+	}
+
+	pub fn get_drain_period(&self) -> Duration {
+		Duration::from_secs(self.drain_period.unwrap_or(0))
+	}
+
+	pub fn get_shutdown_timeout(&self) -> Duration {
+		Duration::from_secs(self.shutdown_timeout.unwrap_or(10))
+	}
+
+	/// Checks the whole configuration, so that the server does not fail after startup.
+	pub fn validate(&self) -> Result<()> {
+		if self.get_ip().parse::<IpAddr>().is_err() {
+			return Err(Error::new(&format!("invalid ip address \"{}\"", self.get_ip())));
+		}
+
+		if self.socket.is_some() && self.tls.is_some() {
+			return Err(Error::new("TLS is not supported on unix sockets"));
+		}
+		if self.socket.is_none() && self.socket_mode.is_some() {
+			return Err(Error::new("socket_mode requires a socket"));
+		}
+		#[cfg(unix)]
+		self.get_socket()?;
+		#[cfg(not(unix))]
+		if self.socket.is_some() {
+			return Err(Error::new("unix sockets are not supported on this platform"));
+		}
+
+		if self.sources.is_empty()
+			&& self.source_dirs.is_empty()
+			&& self.composites.is_empty()
+			&& self.static_sources.is_empty()
+			&& self.hosts.is_empty()
+		{
+			return Err(Error::new("no sources defined"));
+		}
+
+		let mut prefixes: Vec<String> = Vec::new();
+		let mut file_names: Vec<String> = Vec::new();
+		for source in self.sources.iter() {
+			source.validate()?;
+
+			if source.expose_file {
+				let file_name = source.get_file_name();
+				if file_names.contains(&file_name) {
+					return Err(Error::new(&format!(
+						"multiple sources with the file name '{file_name}' are defined"
+					)));
+				}
+				file_names.push(file_name);
+			}
+
+			let prefix = source.get_prefix();
+			if let Some(other) = prefixes.iter().find(|other| prefixes_overlap(&prefix, other)) {
+				return Err(Error::new(&format!(
+					"multiple sources with the prefix '{prefix}' and '{other}' are defined"
+				)));
+			}
+			prefixes.push(prefix);
+		}
+
+		for source_dir in self.source_dirs.iter() {
+			source_dir.validate()?;
+		}
+
+		for composite in self.composites.iter() {
+			composite.validate()?;
+
+			let prefix = composite.get_prefix();
+			if let Some(other) = prefixes.iter().find(|other| prefixes_overlap(&prefix, other)) {
+				return Err(Error::new(&format!(
+					"multiple sources with the prefix '{prefix}' and '{other}' are defined"
+				)));
+			}
+			prefixes.push(prefix);
+		}
+
+		for filename in self.static_sources.iter() {
+			validate_static_source(filename)?;
+		}
+
+		let mut hostnames: Vec<String> = Vec::new();
+		for host in self.hosts.iter() {
+			host.validate()?;
+			for hostname in host.hostnames.iter() {
+				let hostname = hostname.to_lowercase();
+				if hostnames.contains(&hostname) {
+					return Err(Error::new(&format!("host \"{hostname}\" is defined multiple times")));
+				}
+				hostnames.push(hostname);
+			}
+		}
+
+		if let Some(tls) = &self.tls {
+			for path in [tls.get_cert_path(), tls.get_key_path()] {
+				if !path.is_file() {
+					return Err(Error::new(&format!("TLS file {path:?} does not exist")));
+				}
+			}
+		}
+
+		if let Some(cors) = &self.cors {
+			cors.validate()?;
+		}
+
+		self.logging.get_level_filter()?;
+
+		if let Some(access_log) = &self.logging.access {
+			access_log.validate()?;
+		}
+
+		if let Some(token) = &self.admin_token {
+			if token.trim().is_empty() {
+				return Err(Error::new("admin token must not be empty"));
+			}
+		}
+
+		if let Some(base_url) = &self.base_url {
+			if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+				return Err(Error::new(&format!(
+					"base url \"{base_url}\" must start with \"http://\" or \"https://\""
+				)));
+			}
+		}
+
+		if let Some(rate_limit) = &self.rate_limit {
+			rate_limit.validate()?;
+		}
+		self.get_trusted_proxies()?;
+
+		let names: Vec<String> = self
+			.sources
+			.iter()
+			.map(|source| source.get_name())
+			.chain(self.composites.iter().map(|composite| composite.name.clone()))
+			.collect();
+		for token in self.tokens.iter() {
+			if token.token.trim().is_empty() {
+				return Err(Error::new("access token must not be empty"));
+			}
+			for name in token.sources.iter() {
+				if name != "*" && !names.contains(name) {
+					return Err(Error::new(&format!("access token refers to unknown source \"{name}\"")));
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Opens all containers and sets up the tile server.
+	pub async fn build_server(&self) -> Result<TileServer> {
+		let mut server = TileServer::new(self.get_ip(), self.get_port());
+
+		for source_config in self.sources.iter() {
+			let container = source_config.open().await?;
+			server.add_tile_source(&source_config.get_prefix(), container);
+			if source_config.private {
+				server.set_private(&source_config.get_prefix());
+			}
+			if let Some(rate_limit) = &source_config.rate_limit {
+				server.set_source_rate_limit(&source_config.get_prefix(), rate_limit);
+			}
+			if source_config.expose_file {
+				server.expose_file(&source_config.get_prefix(), &source_config.get_file_name());
+			}
+		}
+
+		for composite_config in self.composites.iter() {
+			server.add_tile_source(&composite_config.get_prefix(), composite_config.open().await?);
+			if composite_config.private {
+				server.set_private(&composite_config.get_prefix());
+			}
+		}
+
+		// containers of directories are skipped if they can't be opened or their prefix is already in use
+		let mut prefixes: Vec<String> = server.iter_url_mapping().map(|(prefix, _)| prefix).collect();
+		for source_dir in self.source_dirs.iter() {
+			for source_config in source_dir.scan()? {
+				let prefix = source_config.get_prefix();
+				if let Some(other) = prefixes.iter().find(|other| prefixes_overlap(&prefix, other)) {
+					log::warn!(
+						"skipping {}: prefix '{prefix}' overlaps with '{other}'",
+						source_config.path
+					);
+					continue;
+				}
+				match source_config.open_isolated().await {
+					Ok(container) => {
+						server.add_tile_source(&prefix, container);
+						prefixes.push(prefix);
+					}
+					Err(err) => log::warn!("skipping {}: {err}", source_config.path),
+				}
+			}
+			server.add_source_dir(source_dir.clone());
+		}
+
+		if let Some(rate_limit) = &self.rate_limit {
+			server.set_rate_limit(rate_limit);
+		}
+		server.set_trusted_proxies(&self.get_trusted_proxies()?);
+
+		for token in self.tokens.iter() {
+			let prefixes: Vec<String> = token
+				.sources
+				.iter()
+				.map(
+					|name| match self.sources.iter().find(|source| &source.get_name() == name) {
+						Some(source) => source.get_prefix(),
+						None => match self.composites.iter().find(|composite| &composite.name == name) {
+							Some(composite) => composite.get_prefix(),
+							None => name.to_owned(),
+						},
+					},
+				)
+				.collect();
+			server.add_access_token(&token.token, &prefixes);
+		}
+
+		for filename in self.static_sources.iter() {
+			server.add_static_source(open_static_source(filename));
+		}
+
+		for host in self.hosts.iter() {
+			let hostnames: Vec<&str> = host.hostnames.iter().map(String::as_str).collect();
+			server.add_host(&hostnames);
+			for source_config in host.sources.iter() {
+				server.add_host_tile_source(hostnames[0], &source_config.get_prefix(), source_config.open().await?);
+			}
+			for filename in host.static_sources.iter() {
+				server.add_host_static_source(hostnames[0], open_static_source(filename));
+			}
+		}
+
+		if let Some(cors) = &self.cors {
+			server.set_cors(cors.clone());
+		}
+
+		if let Some(tls) = &self.tls {
+			server.set_tls(tls.clone());
+		}
+
+		#[cfg(unix)]
+		if let Some(socket) = self.get_socket()? {
+			server.set_socket(socket);
+		}
+
+		if let Some(token) = &self.admin_token {
+			server.set_admin_token(token);
+		}
+
+		if let Some(access_log) = &self.logging.access {
+			server.set_access_log(access_log.clone());
+		}
+
+		server.set_watch(self.watch);
+		server.set_metrics(self.metrics);
+		server.set_preview(self.preview);
+		server.set_wmts(self.wmts);
+		server.set_ogc_api(self.ogc_api);
+		if let Some(base_url) = &self.base_url {
+			server.set_base_url(base_url);
+		}
+		server.set_drain_period(self.get_drain_period());
+
+		Ok(server)
+	}
+}
+
+impl SourceConfig {
+	pub fn new(path: &str, name: Option<&str>) -> SourceConfig {
+		SourceConfig {
+			path: path.to_owned(),
+			name: name.map(|name| name.to_owned()),
+			prefix: None,
+			flip_y: false,
+			cache_max_age: None,
+			private: false,
+			rate_limit: None,
+			expose_file: false,
+			filter: None,
+			overzoom: None,
+		}
+	}
+
+	/// Returns the configured name, or the filename without extensions,
+	/// e.g. ".../ukraine.versatiles" becomes "ukraine".
+	pub fn get_name(&self) -> String {
+		match &self.name {
+			Some(name) => name.to_owned(),
+			None => {
+				let filename = self.path.split(&['/', '\\']).next_back().unwrap();
+				filename.split('.').next().unwrap().to_owned()
+			}
+		}
+	}
+
+	/// Returns the name under which the container file is exposed, e.g. "ukraine.versatiles".
+	pub fn get_file_name(&self) -> String {
+		let filename = self.path.split(['?', '#']).next().unwrap();
+		let filename = filename.split(&['/', '\\']).next_back().unwrap();
+		match filename.rsplit_once('.') {
+			Some((_, extension)) => format!("{}.{extension}", self.get_name()),
+			None => self.get_name(),
+		}
+	}
+
+	pub fn get_prefix(&self) -> String {
+		match &self.prefix {
+			Some(prefix) => clean_prefix(prefix),
+			None => clean_prefix(&format!("/tiles/{}/", self.get_name())),
+		}
+	}
+
+	/// Opens the container.
+	pub async fn open(&self) -> Result<Box<source::TileContainer>> {
+		let mut reader = get_reader(&self.path).await?;
+		reader.get_parameters_mut().set_vertical_flip(self.flip_y);
+
+		let mut container = source::TileContainer::from(reader);
+		container.set_url(&self.path);
+		if let Some(max_age) = self.cache_max_age {
+			container.set_cache_max_age(max_age);
+		}
+		if let Some(filter) = &self.filter {
+			container.set_filter(filter.clone());
+		}
+		if let Some(levels) = self.overzoom {
+			container.set_max_overzoom(levels);
+		}
+
+		Ok(container)
+	}
+
+	/// Opens the container in its own task, because readers panic on broken containers.
+	pub async fn open_isolated(&self) -> Result<Box<source::TileContainer>> {
+		let source_config = self.clone();
+		match tokio::spawn(async move { source_config.open().await.map_err(|err| err.to_string()) }).await {
+			Ok(Ok(container)) => Ok(container),
+			Ok(Err(err)) => Err(Error::new(&err)),
+			Err(_) => Err(Error::new(&format!("container \"{}\" is invalid", self.path))),
+		}
+	}
+
+	pub fn validate(&self) -> Result<()> {
+		let extension = self.path.split('.').next_back().unwrap_or("");
+		if !CONTAINER_EXTENSIONS.contains(&extension) {
+			return Err(Error::new(&format!(
+				"source \"{}\" has an unknown container format, supported are: *.{}",
+				self.path,
+				CONTAINER_EXTENSIONS.join(", *.")
+			)));
+		}
+
+		if is_url(&self.path) {
+			if extension != "versatiles" {
+				return Err(Error::new(&format!(
+					"source \"{}\": only VersaTiles containers can be served from a url",
+					self.path
+				)));
+			}
+		} else if !PathBuf::from(&self.path).is_file() {
+			return Err(Error::new(&format!("source file \"{}\" does not exist", self.path)));
+		}
+
+		if self.get_name().is_empty() {
+			return Err(Error::new(&format!("source \"{}\" has an empty name", self.path)));
+		}
+
+		if let Some(rate_limit) = &self.rate_limit {
+			rate_limit.validate()?;
+		}
+
+		if let Some(filter) = &self.filter {
+			filter.validate()?;
+		}
+
+		if self.overzoom.is_some_and(|levels| levels > MAX_OVERZOOM) {
+			return Err(Error::new(&format!(
+				"source \"{}\": overzoom must not exceed {MAX_OVERZOOM} zoom levels",
+				self.path
+			)));
+		}
+
+		Ok(())
+	}
+}
+
+impl SourceDirConfig {
+	pub fn new(path: &str, recursive: bool, glob: Option<&str>) -> SourceDirConfig {
+		SourceDirConfig {
+			path: path.to_owned(),
+			recursive,
+			glob: glob.map(|glob| glob.to_owned()),
+		}
+	}
+
+	pub fn validate(&self) -> Result<()> {
+		if !Path::new(&self.path).is_dir() {
+			return Err(Error::new(&format!(
+				"source directory \"{}\" does not exist",
+				self.path
+			)));
+		}
+		if self.glob.as_ref().is_some_and(|glob| glob.is_empty()) {
+			return Err(Error::new(&format!(
+				"source directory \"{}\" has an empty glob pattern",
+				self.path
+			)));
+		}
+		Ok(())
+	}
+
+	/// Finds all containers in the directory, sorted by path. Hidden files and folders are skipped.
+	pub fn scan(&self) -> Result<Vec<SourceConfig>> {
+		let mut paths: Vec<PathBuf> = Vec::new();
+		let mut folders: Vec<PathBuf> = vec![PathBuf::from(&self.path)];
+
+		while let Some(folder) = folders.pop() {
+			let entries =
+				read_dir(&folder).map_err(|e| Error::new(&format!("can not read source directory {folder:?}: {e}")))?;
+			for entry in entries.flatten() {
+				let path = entry.path();
+				let filename = entry.file_name().to_string_lossy().to_string();
+				if filename.starts_with('.') {
+					continue;
+				}
+				if path.is_dir() {
+					if self.recursive {
+						folders.push(path);
+					}
+					continue;
+				}
+
+				let extension = filename.split('.').next_back().unwrap_or("");
+				if !CONTAINER_EXTENSIONS.contains(&extension) {
+					continue;
+				}
+				if let Some(glob) = &self.glob {
+					if !glob_matches(glob, &filename) {
+						continue;
+					}
+				}
+				paths.push(path);
+			}
+		}
+
+		paths.sort();
+		Ok(paths
+			.iter()
+			.map(|path| SourceConfig::new(path.to_str().unwrap(), None))
+			.collect())
+	}
+}
+
+impl CompositeConfig {
+	pub fn get_prefix(&self) -> String {
+		match &self.prefix {
+			Some(prefix) => clean_prefix(prefix),
+			None => clean_prefix(&format!("/tiles/{}/", self.name)),
+		}
+	}
+
+	/// Opens all containers and combines them.
+	pub async fn open(&self) -> Result<Box<source::Composite>> {
+		let mut readers = Vec::new();
+		for part in self.sources.iter() {
+			readers.push((get_reader(&part.path).await?, part.rename.clone()));
+		}
+
+		let mut composite = source::Composite::new(&self.name, readers).await?;
+		if let Some(max_age) = self.cache_max_age {
+			composite.set_cache_max_age(max_age);
+		}
+
+		Ok(composite)
+	}
+
+	pub fn validate(&self) -> Result<()> {
+		if self.name.is_empty() {
+			return Err(Error::new("composite source has an empty name"));
+		}
+		if self.sources.is_empty() {
+			return Err(Error::new(&format!(
+				"composite source \"{}\" has no sources",
+				self.name
+			)));
+		}
+		for part in self.sources.iter() {
+			SourceConfig::new(&part.path, Some(&self.name)).validate()?;
+		}
+
+		Ok(())
+	}
+}
+
+impl HostConfig {
+	pub fn validate(&self) -> Result<()> {
+		if self.hostnames.is_empty() {
+			return Err(Error::new("host has no host names"));
+		}
+		if self.hostnames.iter().any(|hostname| hostname.trim().is_empty()) {
+			return Err(Error::new("host name must not be empty"));
+		}
+		let hostname = &self.hostnames[0];
+
+		let mut prefixes: Vec<String> = Vec::new();
+		for source in self.sources.iter() {
+			source.validate()?;
+
+			// access tokens, rate limits and files are defined for the whole server
+			if source.private || source.rate_limit.is_some() || source.expose_file {
+				return Err(Error::new(&format!(
+					"source \"{}\" of host \"{hostname}\": private, rate_limit and expose_file are not supported for hosts",
+					source.path
+				)));
+			}
+
+			let prefix = source.get_prefix();
+			if let Some(other) = prefixes.iter().find(|other| prefixes_overlap(&prefix, other)) {
+				return Err(Error::new(&format!(
+					"host \"{hostname}\" has multiple sources with the prefix '{prefix}' and '{other}'"
+				)));
+			}
+			prefixes.push(prefix);
+		}
+
+		for filename in self.static_sources.iter() {
+			validate_static_source(filename)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl LoggingConfig {
+	pub fn get_level_filter(&self) -> Result<Option<LevelFilter>> {
+		match &self.level {
+			None => Ok(None),
+			Some(level) => match level.parse::<LevelFilter>() {
+				Ok(filter) => Ok(Some(filter)),
+				Err(_) => Err(Error::new(&format!(
+					"invalid log level \"{level}\", use one of: off, error, warn, info, debug, trace"
+				))),
+			},
+		}
+	}
+}
+
+fn validate_static_source(filename: &str) -> Result<()> {
+	let path = Path::new(filename);
+	if !path.exists() {
+		return Err(Error::new(&format!("static source {path:?} does not exist")));
+	}
+	if !path.is_dir() && !filename.ends_with(".tar") {
+		return Err(Error::new(&format!(
+			"static source {path:?} must be a folder or a .tar file"
+		)));
+	}
+	Ok(())
+}
+
+fn open_static_source(filename: &str) -> Box<dyn ServerSourceTrait> {
+	if filename.ends_with(".tar") {
+		source::TarFile::from(filename)
+	} else {
+		source::Folder::from(filename)
+	}
+}
+
+/// Matches a filename against a pattern, where "*" matches any text and "?" any single character.
+fn glob_matches(pattern: &str, filename: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	let filename: Vec<char> = filename.chars().collect();
+
+	let (mut p, mut f) = (0, 0);
+	// position of the last "*" and the filename position it was tried at
+	let mut star: Option<(usize, usize)> = None;
+
+	while f < filename.len() {
+		if p < pattern.len() && (pattern[p] == '?' || pattern[p] == filename[f]) {
+			p += 1;
+			f += 1;
+		} else if p < pattern.len() && pattern[p] == '*' {
+			star = Some((p, f));
+			p += 1;
+		} else if let Some((star_p, star_f)) = star {
+			// let the last "*" match one more character
+			p = star_p + 1;
+			f = star_f + 1;
+			star = Some((star_p, star_f + 1));
+		} else {
+			return false;
+		}
+	}
+
+	pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn is_url(path: &str) -> bool {
+	path.starts_with("http://") || path.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{glob_matches, ServerConfig, SourceConfig, SourceDirConfig};
+	use crate::{
+		containers::tests::make_test_file,
+		server::AccessLogFormat,
+		shared::{Compression, TileFormat},
+	};
+	use assert_fs::TempDir;
+	use std::{
+		fs::{create_dir, write},
+		time::Duration,
+	};
+
+	fn from_yaml(dir: &TempDir, yaml: &str) -> Result<ServerConfig, String> {
+		let filename = dir.path().join("server.yaml");
+		write(&filename, yaml).unwrap();
+		let config = ServerConfig::from_file(filename.to_str().unwrap()).map_err(|e| e.to_string())?;
+		config.validate().map_err(|e| e.to_string())?;
+		Ok(config)
+	}
+
+	#[test]
+	fn source_names() {
+		let source = SourceConfig::new("data/ukraine.versatiles", None);
+		assert_eq!(source.get_name(), "ukraine");
+		assert_eq!(source.get_prefix(), "/tiles/ukraine/");
+		assert_eq!(source.get_file_name(), "ukraine.versatiles");
+
+		let mut source = SourceConfig::new("data/ukraine.versatiles", Some("kyiv"));
+		assert_eq!(source.get_prefix(), "/tiles/kyiv/");
+		assert_eq!(source.get_file_name(), "kyiv.versatiles");
+
+		source.prefix = Some("maps".to_owned());
+		assert_eq!(source.get_prefix(), "/maps/");
+
+		let source = SourceConfig::new("https://example.org/sat.mbtiles?v=2", None);
+		assert_eq!(source.get_file_name(), "sat.mbtiles");
+	}
+
+	#[tokio::test]
+	async fn yaml_and_toml() {
+		let dir = TempDir::new().unwrap();
+		let file = make_test_file(TileFormat::PBF, Compression::Gzip, 3, "versatiles").await;
+		write(dir.path().join("osm.versatiles"), std::fs::read(file.path()).unwrap()).unwrap();
+		create_dir(dir.path().join("public")).unwrap();
+
+		let config = from_yaml(
+			&dir,
+			"port: 8081\nsources:\n  - path: osm.versatiles\n    cache_max_age: 60\n    filter: {layers: [water]}\n    overzoom: 2\n  - path: https://example.org/sat.versatiles\n    name: sat\n    flip_y: true\nstatic: [public]\ncors:\n  origins: ['*']\nlogging:\n  level: debug\n  access: {format: json, path: access.log}\n",
+		)
+		.unwrap();
+		assert_eq!(config.get_ip(), "127.0.0.1");
+		assert_eq!(config.get_port(), 8081);
+		assert_eq!(config.sources.len(), 2);
+		assert_eq!(config.sources[0].get_prefix(), "/tiles/osm/");
+		assert_eq!(config.sources[0].cache_max_age, Some(60));
+		assert_eq!(
+			config.sources[0].filter.as_ref().unwrap().layers,
+			vec!["water".to_owned()]
+		);
+		assert_eq!(config.sources[0].overzoom, Some(2));
+		assert_eq!(config.sources[1].get_prefix(), "/tiles/sat/");
+		assert!(config.sources[1].flip_y);
+		assert!(config.static_sources[0].ends_with("public"));
+		let access_log = config.logging.access.as_ref().unwrap();
+		assert_eq!(access_log.format, AccessLogFormat::Json);
+		assert_eq!(access_log.get_file().unwrap(), dir.path().join("access.log"));
+
+		let server = ServerConfig {
+			sources: vec![config.sources[0].clone()],
+			..Default::default()
+		}
+		.build_server()
+		.await
+		.unwrap();
+		let mapping: Vec<(String, String)> = server.iter_url_mapping().collect();
+		assert_eq!(mapping[0].0, "/tiles/osm/");
+
+		let filename = dir.path().join("server.toml");
+		write(
+			&filename,
+			"ip = \"0.0.0.0\"\n\n[[sources]]\npath = \"osm.versatiles\"\n",
+		)
+		.unwrap();
+		let config = ServerConfig::from_file(filename.to_str().unwrap()).unwrap();
+		config.validate().unwrap();
+		assert_eq!(config.get_ip(), "0.0.0.0");
+		assert_eq!(config.get_port(), 8080);
+		assert_eq!(config.get_shutdown_timeout(), Duration::from_secs(10));
+		assert_eq!(config.get_drain_period(), Duration::ZERO);
+	}
+
+	#[tokio::test]
+	async fn composites() {
+		let dir = TempDir::new().unwrap();
+		let file = make_test_file(TileFormat::PBF, Compression::Gzip, 3, "versatiles").await;
+		write(dir.path().join("osm.versatiles"), std::fs::read(file.path()).unwrap()).unwrap();
+
+		let config = from_yaml(
+			&dir,
+			"composites:\n  - name: city\n    private: true\n    sources:\n      - path: osm.versatiles\n      - path: osm.versatiles\n        rename: {ocean: sea}\ntokens: [{token: abc, sources: [city]}]\n",
+		)
+		.unwrap();
+		let composite = &config.composites[0];
+		assert_eq!(composite.get_prefix(), "/tiles/city/");
+		assert_eq!(composite.sources[1].rename["ocean"], "sea");
+		assert!(composite.sources[0].path.ends_with("osm.versatiles"));
+
+		let server = config.build_server().await.unwrap();
+		let mapping: Vec<(String, String)> = server.iter_url_mapping().collect();
+		assert_eq!(mapping, vec![("/tiles/city/".to_owned(), "city".to_owned())]);
+	}
+
+	#[test]
+	fn glob_patterns() {
+		assert!(glob_matches("*", "osm.versatiles"));
+		assert!(glob_matches("*.versatiles", "osm.versatiles"));
+		assert!(glob_matches("osm-*.versatiles", "osm-2023.versatiles"));
+		assert!(glob_matches("osm-????.*", "osm-2023.mbtiles"));
+		assert!(glob_matches("*a*b*", "xxaxxbxx"));
+		assert!(!glob_matches("*.versatiles", "osm.mbtiles"));
+		assert!(!glob_matches("osm-????.*", "osm-23.mbtiles"));
+		assert!(!glob_matches("osm", "osm.versatiles"));
+	}
+
+	#[tokio::test]
+	async fn source_dirs() {
+		let dir = TempDir::new().unwrap();
+		let file = make_test_file(TileFormat::PBF, Compression::Gzip, 3, "versatiles").await;
+		let data = std::fs::read(file.path()).unwrap();
+		std::fs::create_dir_all(dir.path().join("data/europe")).unwrap();
+		std::fs::create_dir_all(dir.path().join("data/.hidden")).unwrap();
+		write(dir.path().join("data/osm.versatiles"), &data).unwrap();
+		write(dir.path().join("data/broken.versatiles"), "broken").unwrap();
+		write(dir.path().join("data/readme.txt"), "").unwrap();
+		write(dir.path().join("data/europe/berlin.versatiles"), &data).unwrap();
+		write(dir.path().join("data/europe/osm.versatiles"), &data).unwrap();
+		write(dir.path().join("data/.hidden/secret.versatiles"), &data).unwrap();
+
+		let names = |source_dir: SourceDirConfig| -> Vec<String> {
+			let sources = source_dir.scan().unwrap();
+			sources.iter().map(|source| source.get_name()).collect()
+		};
+		let path = dir.path().join("data");
+		let path = path.to_str().unwrap();
+		assert_eq!(names(SourceDirConfig::new(path, false, None)), ["broken", "osm"]);
+		assert_eq!(
+			names(SourceDirConfig::new(path, true, None)),
+			["broken", "berlin", "osm", "osm"]
+		);
+		assert_eq!(
+			names(SourceDirConfig::new(path, true, Some("b*"))),
+			["broken", "berlin"]
+		);
+
+		// broken containers and duplicate names are skipped
+		let config = from_yaml(&dir, "source_dirs: [{path: data, recursive: true}]\n").unwrap();
+		let server = config.build_server().await.unwrap();
+		let mut mapping: Vec<String> = server.iter_url_mapping().map(|(prefix, _)| prefix).collect();
+		mapping.sort();
+		assert_eq!(mapping, ["/tiles/berlin/", "/tiles/osm/"]);
+	}
+
+	#[tokio::test]
+	async fn hosts() {
+		let dir = TempDir::new().unwrap();
+		let file = make_test_file(TileFormat::PBF, Compression::Gzip, 3, "versatiles").await;
+		write(dir.path().join("osm.versatiles"), std::fs::read(file.path()).unwrap()).unwrap();
+		create_dir(dir.path().join("customer")).unwrap();
+
+		let config = from_yaml(
+			&dir,
+			"hosts:\n  - hostnames: [a.example.org, www.a.example.org]\n    sources: [{path: osm.versatiles}]\n    static: [customer]\n  - hostnames: [b.example.org]\n    sources: [{path: osm.versatiles, name: b}]\n",
+		)
+		.unwrap();
+		assert_eq!(config.hosts.len(), 2);
+		assert_eq!(config.hosts[0].hostnames, ["a.example.org", "www.a.example.org"]);
+		assert!(config.hosts[0].sources[0].path.ends_with("osm.versatiles"));
+		assert!(config.hosts[0].static_sources[0].ends_with("customer"));
+
+		let server = config.build_server().await.unwrap();
+		assert_eq!(server.iter_url_mapping().count(), 0);
+	}
+
+	#[tokio::test]
+	async fn validation_errors() {
+		let dir = TempDir::new().unwrap();
+		let file = make_test_file(TileFormat::PBF, Compression::Gzip, 3, "versatiles").await;
+		write(dir.path().join("osm.versatiles"), std::fs::read(file.path()).unwrap()).unwrap();
+
+		let test = |yaml: &str, error: &str| {
+			let message = from_yaml(&dir, yaml).unwrap_err();
+			assert!(message.contains(error), "'{message}' should contain '{error}'");
+		};
+
+		test("sources: []", "no sources defined");
+		test("sourcse: []", "unknown field `sourcse`");
+		test(
+			"ip: localhorst\nsources: [{path: osm.versatiles}]",
+			"invalid ip address",
+		);
+		test("sources: [{path: missing.versatiles}]", "does not exist");
+		test("sources: [{path: osm.pmtiles}]", "unknown container format");
+		test("sources: [{path: http://example.org/osm.mbtiles}]", "only VersaTiles");
+		test(
+			"sources: [{path: osm.versatiles}, {path: osm.versatiles, prefix: /tiles/}]",
+			"multiple sources with the prefix",
+		);
+		test("sources: [{path: osm.versatiles}]\nstatic: [public]", "does not exist");
+		test(
+			"sources: [{path: osm.versatiles}]\ntls: {cert: cert.pem, key: key.pem}",
+			"TLS file",
+		);
+		test(
+			"sources: [{path: osm.versatiles}]\ncors: {origins: ['*'], methods: ['GE T']}",
+			"invalid CORS method",
+		);
+		test(
+			"sources: [{path: osm.versatiles}]\nlogging: {level: loud}",
+			"invalid log level",
+		);
+		test(
+			"sources: [{path: osm.versatiles}]\nlogging: {access: {path: logs/access.log}}",
+			"folder of access log",
+		);
+		test(
+			"sources: [{path: osm.versatiles, private: true}]\ntokens: [{token: abc, sources: [sat]}]",
+			"unknown source \"sat\"",
+		);
+		test(
+			"sources: [{path: osm.versatiles, rate_limit: {rate: 0}}]",
+			"rate limit must be greater than 0",
+		);
+		test(
+			"sources: [{path: osm.versatiles}]\nbase_url: tiles.example.org",
+			"must start with \"http://\" or \"https://\"",
+		);
+		test(
+			"sources: [{path: osm.versatiles}]\ntrusted_proxies: [10.0.0.0/40]",
+			"invalid IP address or network",
+		);
+		test(
+			"sources: [{path: osm.versatiles, expose_file: true}, {path: osm.versatiles, prefix: /x/, expose_file: true}]",
+			"multiple sources with the file name 'osm.versatiles'",
+		);
+		test(
+			"socket: versatiles.sock\nsocket_mode: '999'\nsources: [{path: osm.versatiles}]",
+			"invalid socket mode",
+		);
+		test(
+			"sources: [{path: osm.versatiles, filter: {layers: []}}]",
+			"tile filter must allow at least one layer or field",
+		);
+		test(
+			"sources: [{path: osm.versatiles, overzoom: 9}]",
+			"overzoom must not exceed 8 zoom levels",
+		);
+		test("composites: [{name: city, sources: []}]", "has no sources");
+		test("source_dirs: [{path: missing}]", "source directory");
+		test("source_dirs: [{path: ., glob: ''}]", "empty glob pattern");
+		test("hosts: [{hostnames: []}]", "host has no host names");
+		test(
+			"hosts: [{hostnames: [a.org]}, {hostnames: [b.org, A.org]}]",
+			"host \"a.org\" is defined multiple times",
+		);
+		test(
+			"hosts: [{hostnames: [a.org], sources: [{path: osm.versatiles, private: true}]}]",
+			"not supported for hosts",
+		);
+		test(
+			"hosts: [{hostnames: [a.org], sources: [{path: osm.versatiles}, {path: osm.versatiles}]}]",
+			"host \"a.org\" has multiple sources",
+		);
+		test("hosts: [{hostnames: [a.org], static: [public]}]", "does not exist");
+		test(
+			"composites: [{name: city, sources: [{path: osm.pmtiles}]}]",
+			"unknown container format",
+		);
+		test(
+			"sources: [{path: osm.versatiles, name: city}]\ncomposites: [{name: city, sources: [{path: osm.versatiles}]}]",
+			"multiple sources with the prefix",
+		);
+		test(
+			"socket_mode: '660'\nsources: [{path: osm.versatiles}]",
+			"socket_mode requires a socket",
+		);
+
+		assert!(ServerConfig::from_file("server.json")
+			.unwrap_err()
+			.to_string()
+			.contains("can not read config file"));
+	}
+}
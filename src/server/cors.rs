@@ -0,0 +1,150 @@
+use crate::shared::{Error, Result};
+use axum::http::{HeaderName, HeaderValue, Method};
+use serde::Deserialize;
+use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
+
+/// CORS settings of the tile server.
+///
+/// Origins can be given as exact origins (`https://example.org`), as wildcard (`*`)
+/// or as subdomain wildcard (`https://*.example.org`).
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CorsConfig {
+	origins: Vec<String>,
+	#[serde(default)]
+	methods: Vec<String>,
+	#[serde(default)]
+	headers: Vec<String>,
+}
+
+impl CorsConfig {
+	pub fn new(origins: &[String]) -> CorsConfig {
+		CorsConfig {
+			origins: origins.iter().map(|origin| origin.trim().to_owned()).collect(),
+			methods: Vec::new(),
+			headers: Vec::new(),
+		}
+	}
+	pub fn set_methods(&mut self, methods: &[String]) {
+		self.methods = methods.iter().map(|method| method.trim().to_uppercase()).collect();
+	}
+	pub fn set_headers(&mut self, headers: &[String]) {
+		self.headers = headers.iter().map(|header| header.trim().to_lowercase()).collect();
+	}
+	pub fn is_enabled(&self) -> bool {
+		!self.origins.is_empty()
+	}
+	pub fn get_origins(&self) -> &[String] {
+		&self.origins
+	}
+
+	/// Checks that all methods and headers are valid.
+	pub fn validate(&self) -> Result<()> {
+		for method in self.methods.iter() {
+			if Method::from_bytes(method.as_bytes()).is_err() {
+				return Err(Error::new(&format!("invalid CORS method \"{method}\"")));
+			}
+		}
+		for header in self.headers.iter() {
+			if header != "*" && HeaderName::from_bytes(header.as_bytes()).is_err() {
+				return Err(Error::new(&format!("invalid CORS header \"{header}\"")));
+			}
+		}
+		Ok(())
+	}
+
+	/// Builds the tower layer that answers preflight requests and adds the CORS headers to all responses.
+	/// Fails on invalid methods or headers.
+	pub fn get_layer(&self) -> Result<CorsLayer> {
+		let mut layer = CorsLayer::new();
+
+		if self.origins.iter().any(|origin| origin == "*") {
+			layer = layer.allow_origin(Any);
+		} else {
+			let origins = self.origins.clone();
+			layer = layer.allow_origin(AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+				let origin = origin.to_str().unwrap_or("");
+				origins.iter().any(|pattern| origin_matches(pattern, origin))
+			}));
+		}
+
+		if self.methods.is_empty() {
+			layer = layer.allow_methods(vec![Method::GET, Method::HEAD, Method::OPTIONS]);
+		} else {
+			let methods: Vec<Method> = self
+				.methods
+				.iter()
+				.map(|method| {
+					Method::from_bytes(method.to_uppercase().as_bytes())
+						.map_err(|_| Error::new(&format!("invalid CORS method \"{method}\"")))
+				})
+				.collect::<std::result::Result<_, _>>()?;
+			layer = layer.allow_methods(methods);
+		}
+
+		if self.headers.is_empty() || self.headers.iter().any(|header| header == "*") {
+			layer = layer.allow_headers(AllowHeaders::any());
+		} else {
+			let headers: Vec<HeaderName> = self
+				.headers
+				.iter()
+				.map(|header| {
+					HeaderName::from_bytes(header.as_bytes())
+						.map_err(|_| Error::new(&format!("invalid CORS header \"{header}\"")))
+				})
+				.collect::<std::result::Result<_, _>>()?;
+			layer = layer.allow_headers(headers);
+		}
+
+		Ok(layer)
+	}
+}
+
+/// Checks whether an origin matches a configured pattern, e.g. `https://*.example.org`.
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+	if let Some((prefix, suffix)) = pattern.split_once('*') {
+		origin.len() > prefix.len() + suffix.len() && origin.starts_with(prefix) && origin.ends_with(suffix)
+	} else {
+		pattern == origin
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{origin_matches, CorsConfig};
+
+	#[test]
+	fn test_origin_matches() {
+		assert!(origin_matches("https://example.org", "https://example.org"));
+		assert!(!origin_matches("https://example.org", "http://example.org"));
+		assert!(origin_matches("https://*.example.org", "https://maps.example.org"));
+		assert!(!origin_matches("https://*.example.org", "https://example.org"));
+		assert!(!origin_matches("https://*.example.org", "https://maps.example.com"));
+		assert!(origin_matches("*", "https://example.org"));
+	}
+
+	#[test]
+	fn test_config() {
+		let mut config = CorsConfig::default();
+		assert!(!config.is_enabled());
+
+		config = CorsConfig::new(&[" https://example.org ".to_owned()]);
+		config.set_methods(&["get".to_owned()]);
+		config.set_headers(&["X-Custom".to_owned()]);
+		assert!(config.is_enabled());
+		assert_eq!(config.get_origins(), ["https://example.org"]);
+		assert!(config.validate().is_ok());
+		assert!(config.get_layer().is_ok());
+
+		config.set_headers(&["no header".to_owned()]);
+		assert!(config.validate().is_err());
+		assert_eq!(
+			config.get_layer().unwrap_err().to_string(),
+			"invalid CORS header \"no header\""
+		);
+
+		config.set_headers(&[]);
+		config.set_methods(&["GET POST".to_owned()]);
+		assert!(config.get_layer().is_err());
+	}
+}
@@ -0,0 +1,307 @@
+use crate::containers::TileReaderStats;
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+/// upper bounds of the latency histogram buckets in seconds
+const LATENCY_BUCKETS: [f64; 12] = [
+	0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// name, help text and value of a metric taken from the reader counters
+type ReaderMetric = (&'static str, &'static str, fn(&TileReaderStats) -> u64);
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+	buckets: [u64; LATENCY_BUCKETS.len()],
+	count: u64,
+	sum: f64,
+}
+
+impl Histogram {
+	fn observe(&mut self, seconds: f64) {
+		for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+			if seconds <= *bound {
+				*bucket += 1;
+			}
+		}
+		self.count += 1;
+		self.sum += seconds;
+	}
+}
+
+/// Reader counters of a source, summed over all its readers, because a reload replaces the reader
+/// and the counters of a new reader start at 0.
+#[derive(Debug, Default)]
+struct ReaderTotals {
+	/// identifies the current reader
+	reader_id: usize,
+	/// counters of the current reader at the last rendering
+	current: TileReaderStats,
+	/// sum of the counters of the previous readers
+	previous: TileReaderStats,
+}
+
+impl ReaderTotals {
+	fn update(&mut self, reader_id: usize, stats: &TileReaderStats) -> TileReaderStats {
+		let current = &self.current;
+		let is_new_reader = reader_id != self.reader_id
+			|| stats.cache_hits < current.cache_hits
+			|| stats.cache_misses < current.cache_misses
+			|| stats.range_requests < current.range_requests
+			|| stats.range_bytes < current.range_bytes;
+		if is_new_reader {
+			self.previous = add_stats(&self.previous, current);
+			self.reader_id = reader_id;
+		}
+		self.current = stats.clone();
+		add_stats(&self.previous, &self.current)
+	}
+}
+
+fn add_stats(a: &TileReaderStats, b: &TileReaderStats) -> TileReaderStats {
+	TileReaderStats {
+		cache_hits: a.cache_hits + b.cache_hits,
+		cache_misses: a.cache_misses + b.cache_misses,
+		range_requests: a.range_requests + b.range_requests,
+		range_bytes: a.range_bytes + b.range_bytes,
+	}
+}
+
+#[derive(Debug, Default)]
+struct MetricsData {
+	requests: BTreeMap<(String, u16, String), u64>,
+	latencies: BTreeMap<String, Histogram>,
+	bytes: BTreeMap<String, u64>,
+	readers: BTreeMap<String, ReaderTotals>,
+}
+
+/// Request metrics of the tile server, rendered in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+	data: Mutex<MetricsData>,
+}
+
+impl Metrics {
+	pub fn new() -> Metrics {
+		Metrics::default()
+	}
+
+	/// Records a finished request.
+	pub fn record(&self, source: &str, status: u16, encoding: &str, bytes: u64, duration: Duration) {
+		let mut data = self.data.lock().unwrap();
+
+		*data
+			.requests
+			.entry((source.to_owned(), status, encoding.to_owned()))
+			.or_insert(0) += 1;
+
+		data
+			.latencies
+			.entry(source.to_owned())
+			.or_default()
+			.observe(duration.as_secs_f64());
+
+		*data.bytes.entry(source.to_owned()).or_insert(0) += bytes;
+	}
+
+	/// Renders all metrics. `readers` contains the prefix, an id of the current reader, e.g. its address,
+	/// and the reader counters of every tile source. Counters of replaced readers are added up,
+	/// except for what they counted since the last rendering.
+	pub fn render(&self, readers: &[(String, usize, TileReaderStats)]) -> String {
+		let mut data = self.data.lock().unwrap();
+		let mut text = String::new();
+
+		let readers: Vec<(&String, TileReaderStats)> = readers
+			.iter()
+			.map(|(source, reader_id, stats)| {
+				let totals = data.readers.entry(source.clone()).or_default();
+				(source, totals.update(*reader_id, stats))
+			})
+			.collect();
+
+		write_header(
+			&mut text,
+			"versatiles_requests_total",
+			"counter",
+			"Number of HTTP requests by source, status and content encoding.",
+		);
+		for ((source, status, encoding), count) in data.requests.iter() {
+			writeln!(
+				text,
+				"versatiles_requests_total{{source=\"{}\",status=\"{}\",encoding=\"{}\"}} {}",
+				escape(source),
+				status,
+				escape(encoding),
+				count
+			)
+			.unwrap();
+		}
+
+		write_header(
+			&mut text,
+			"versatiles_request_duration_seconds",
+			"histogram",
+			"Latency of HTTP requests by source.",
+		);
+		for (source, histogram) in data.latencies.iter() {
+			let source = escape(source);
+			for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+				writeln!(
+					text,
+					"versatiles_request_duration_seconds_bucket{{source=\"{source}\",le=\"{bound}\"}} {count}"
+				)
+				.unwrap();
+			}
+			writeln!(
+				text,
+				"versatiles_request_duration_seconds_bucket{{source=\"{source}\",le=\"+Inf\"}} {}",
+				histogram.count
+			)
+			.unwrap();
+			writeln!(
+				text,
+				"versatiles_request_duration_seconds_sum{{source=\"{source}\"}} {}",
+				histogram.sum
+			)
+			.unwrap();
+			writeln!(
+				text,
+				"versatiles_request_duration_seconds_count{{source=\"{source}\"}} {}",
+				histogram.count
+			)
+			.unwrap();
+		}
+
+		write_header(
+			&mut text,
+			"versatiles_response_bytes_total",
+			"counter",
+			"Number of response body bytes served by source.",
+		);
+		for (source, bytes) in data.bytes.iter() {
+			writeln!(
+				text,
+				"versatiles_response_bytes_total{{source=\"{}\"}} {}",
+				escape(source),
+				bytes
+			)
+			.unwrap();
+		}
+
+		drop(data);
+
+		let reader_metrics: [ReaderMetric; 4] = [
+			(
+				"versatiles_reader_cache_hits_total",
+				"Number of reader lookups answered by the cache.",
+				|stats| stats.cache_hits,
+			),
+			(
+				"versatiles_reader_cache_misses_total",
+				"Number of reader lookups that had to read from the container.",
+				|stats| stats.cache_misses,
+			),
+			(
+				"versatiles_upstream_range_requests_total",
+				"Number of range requests sent to remote containers.",
+				|stats| stats.range_requests,
+			),
+			(
+				"versatiles_upstream_bytes_total",
+				"Number of bytes received from remote containers.",
+				|stats| stats.range_bytes,
+			),
+		];
+
+		for (name, help, value) in reader_metrics {
+			write_header(&mut text, name, "counter", help);
+			for (source, stats) in readers.iter() {
+				writeln!(text, "{name}{{source=\"{}\"}} {}", escape(source), value(stats)).unwrap();
+			}
+		}
+
+		text
+	}
+}
+
+fn write_header(text: &mut String, name: &str, kind: &str, help: &str) {
+	writeln!(text, "# HELP {name} {help}").unwrap();
+	writeln!(text, "# TYPE {name} {kind}").unwrap();
+}
+
+/// Escapes a label value as required by the Prometheus text format.
+fn escape(value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{escape, Metrics};
+	use crate::containers::TileReaderStats;
+	use std::time::Duration;
+
+	#[test]
+	fn test_escape() {
+		assert_eq!(escape("/tiles/osm/"), "/tiles/osm/");
+		assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+	}
+
+	#[test]
+	fn test_render() {
+		let metrics = Metrics::new();
+		metrics.record("/tiles/osm/", 200, "br", 100, Duration::from_millis(3));
+		metrics.record("/tiles/osm/", 200, "br", 50, Duration::from_millis(30));
+		metrics.record("/tiles/osm/", 404, "identity", 9, Duration::from_secs(5));
+
+		let stats = TileReaderStats {
+			cache_hits: 7,
+			cache_misses: 3,
+			range_requests: 4,
+			range_bytes: 1024,
+		};
+		let text = metrics.render(&[("/tiles/osm/".to_owned(), 1, stats)]);
+		let lines: Vec<&str> = text.lines().collect();
+
+		let contains = |line: &str| assert!(lines.contains(&line), "missing line: {line}\n{text}");
+
+		contains("# TYPE versatiles_requests_total counter");
+		contains("versatiles_requests_total{source=\"/tiles/osm/\",status=\"200\",encoding=\"br\"} 2");
+		contains("versatiles_requests_total{source=\"/tiles/osm/\",status=\"404\",encoding=\"identity\"} 1");
+		contains("# TYPE versatiles_request_duration_seconds histogram");
+		contains("versatiles_request_duration_seconds_bucket{source=\"/tiles/osm/\",le=\"0.001\"} 0");
+		contains("versatiles_request_duration_seconds_bucket{source=\"/tiles/osm/\",le=\"0.005\"} 1");
+		contains("versatiles_request_duration_seconds_bucket{source=\"/tiles/osm/\",le=\"2.5\"} 2");
+		contains("versatiles_request_duration_seconds_bucket{source=\"/tiles/osm/\",le=\"+Inf\"} 3");
+		contains("versatiles_request_duration_seconds_count{source=\"/tiles/osm/\"} 3");
+		contains("versatiles_response_bytes_total{source=\"/tiles/osm/\"} 159");
+		contains("versatiles_reader_cache_hits_total{source=\"/tiles/osm/\"} 7");
+		contains("versatiles_reader_cache_misses_total{source=\"/tiles/osm/\"} 3");
+		contains("versatiles_upstream_range_requests_total{source=\"/tiles/osm/\"} 4");
+		contains("versatiles_upstream_bytes_total{source=\"/tiles/osm/\"} 1024");
+	}
+
+	#[test]
+	fn test_reloaded_readers() {
+		let metrics = Metrics::new();
+		let stats = |cache_hits: u64| TileReaderStats {
+			cache_hits,
+			..TileReaderStats::default()
+		};
+		let render = |reader_id: usize, cache_hits: u64| {
+			let text = metrics.render(&[("/tiles/osm/".to_owned(), reader_id, stats(cache_hits))]);
+			let line = text
+				.lines()
+				.find(|line| line.starts_with("versatiles_reader_cache_hits_total{"))
+				.unwrap()
+				.to_owned();
+			line.rsplit(' ').next().unwrap().parse::<u64>().unwrap()
+		};
+
+		assert_eq!(render(1, 5), 5);
+		assert_eq!(render(1, 7), 7);
+		// the source was reloaded, the counters continue
+		assert_eq!(render(2, 2), 9);
+		assert_eq!(render(2, 3), 10);
+		// a new reader at the same address is detected by its lower counters
+		assert_eq!(render(2, 1), 11);
+	}
+}
@@ -1,6 +1,36 @@
+mod access_log;
+mod auth;
+mod config;
+mod cors;
+mod metrics;
+mod ogc_api;
+mod preview;
+mod range;
+mod rate_limit;
+mod raw_file;
 pub mod source;
+mod tile_filter;
 mod tile_server;
+mod tls;
 mod traits;
+#[cfg(unix)]
+mod unix_socket;
+mod wmts;
 
+pub use access_log::*;
+pub use auth::*;
+pub use config::*;
+pub use cors::*;
+pub use metrics::*;
+pub use ogc_api::*;
+pub use preview::*;
+pub use range::*;
+pub use rate_limit::*;
+pub use raw_file::*;
+pub use tile_filter::*;
 pub use tile_server::*;
+pub use tls::*;
 pub use traits::*;
+#[cfg(unix)]
+pub use unix_socket::*;
+pub use wmts::*;
@@ -0,0 +1,342 @@
+use super::{source::get_tile_mime, SCALE_DENOMINATOR_0, WEB_MERCATOR_MAX};
+use crate::shared::{json_string, TileFormat, TileReaderParameters};
+
+/// identifier of the only supported tile matrix set
+pub const OGC_TILE_MATRIX_SET: &str = "WebMercatorQuad";
+/// the definition of WebMercatorQuad ends at this zoom level
+const OGC_ZOOM_MAX: u8 = 24;
+/// size of a pixel at zoom level 0 in meters
+const CELL_SIZE_0: f64 = 156543.03392804097;
+
+const TILE_MATRIX_SET_URI: &str = "http://www.opengis.net/def/tilematrixset/OGC/1.0/WebMercatorQuad";
+const CRS_URI: &str = "http://www.opengis.net/def/crs/EPSG/0/3857";
+const CRS84_URI: &str = "http://www.opengis.net/def/crs/OGC/1.3/CRS84";
+const REL_TILING_SCHEME: &str = "http://www.opengis.net/def/rel/ogc/1.0/tiling-scheme";
+const REL_TILING_SCHEMES: &str = "http://www.opengis.net/def/rel/ogc/1.0/tiling-schemes";
+
+const CONFORMANCE_CLASSES: [&str; 11] = [
+	"http://www.opengis.net/spec/ogcapi-common-1/1.0/conf/core",
+	"http://www.opengis.net/spec/ogcapi-common-1/1.0/conf/landing-page",
+	"http://www.opengis.net/spec/ogcapi-common-1/1.0/conf/json",
+	"http://www.opengis.net/spec/ogcapi-common-2/1.0/conf/collections",
+	"http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/core",
+	"http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tileset",
+	"http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tilesets-list",
+	"http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/geodata-tilesets",
+	"http://www.opengis.net/spec/tms/2.0/conf/tilematrixset",
+	"http://www.opengis.net/spec/tms/2.0/conf/tilesetmetadata",
+	"http://www.opengis.net/spec/tms/2.0/conf/json-tilematrixset",
+];
+
+/// A tile source, published as a collection.
+pub struct OgcCollection {
+	pub id: String,
+	pub title: String,
+	pub parameters: TileReaderParameters,
+}
+
+impl OgcCollection {
+	/// "vector" for vector tiles and "map" for images
+	fn get_data_type(&self) -> &'static str {
+		match self.parameters.get_tile_format() {
+			TileFormat::PBF | TileFormat::GEOJSON | TileFormat::TOPOJSON => "vector",
+			_ => "map",
+		}
+	}
+}
+
+/// All documents are built relative to `root_url`, the url of the landing page, e.g. "https://example.org/ogc".
+pub fn make_landing_page(root_url: &str) -> String {
+	format!(
+		"{{\"title\":\"VersaTiles\",\"description\":\"OGC API - Tiles\",\"links\":[{},{},{},{}]}}",
+		make_link(root_url, "self", "application/json", "this document"),
+		make_link(
+			&format!("{root_url}/conformance"),
+			"conformance",
+			"application/json",
+			"conformance classes"
+		),
+		make_link(
+			&format!("{root_url}/collections"),
+			"data",
+			"application/json",
+			"tile sources"
+		),
+		make_link(
+			&format!("{root_url}/tileMatrixSets"),
+			REL_TILING_SCHEMES,
+			"application/json",
+			"tile matrix sets"
+		),
+	)
+}
+
+pub fn make_conformance() -> String {
+	let classes: Vec<String> = CONFORMANCE_CLASSES.iter().map(|class| json_string(class)).collect();
+	format!("{{\"conformsTo\":[{}]}}", classes.join(","))
+}
+
+pub fn make_collections(root_url: &str, collections: &[OgcCollection]) -> String {
+	let entries: Vec<String> = collections
+		.iter()
+		.map(|collection| make_collection(root_url, collection))
+		.collect();
+	format!(
+		"{{\"links\":[{}],\"collections\":[{}]}}",
+		make_link(
+			&format!("{root_url}/collections"),
+			"self",
+			"application/json",
+			"tile sources"
+		),
+		entries.join(",")
+	)
+}
+
+pub fn make_collection(root_url: &str, collection: &OgcCollection) -> String {
+	let url = format!("{root_url}/collections/{}", collection.id);
+	let bbox_pyramide = collection.parameters.get_bbox_pyramide();
+	let extent = if bbox_pyramide.is_empty() {
+		String::new()
+	} else {
+		format!(
+			",\"extent\":{{\"spatial\":{{\"bbox\":[{:?}],\"crs\":\"{CRS84_URI}\"}}}}",
+			bbox_pyramide.get_geo_bbox()
+		)
+	};
+
+	format!(
+		"{{\"id\":{},\"title\":{}{extent},\"links\":[{},{}]}}",
+		json_string(&collection.id),
+		json_string(&collection.title),
+		make_link(&url, "self", "application/json", &collection.title),
+		make_link(
+			&format!("{url}/tiles"),
+			&format!(
+				"http://www.opengis.net/def/rel/ogc/1.0/tilesets-{}",
+				collection.get_data_type()
+			),
+			"application/json",
+			"tilesets"
+		),
+	)
+}
+
+/// The list of tilesets of a collection. There is only one per collection, in WebMercatorQuad.
+pub fn make_tilesets(root_url: &str, collection: &OgcCollection) -> String {
+	let url = format!("{root_url}/collections/{}/tiles", collection.id);
+	format!(
+		"{{\"links\":[{}],\"tilesets\":[{{\"title\":{},\"dataType\":\"{}\",\"crs\":\"{CRS_URI}\",\"tileMatrixSetURI\":\"{TILE_MATRIX_SET_URI}\",\"links\":[{},{}]}}]}}",
+		make_link(&url, "self", "application/json", "tilesets"),
+		json_string(&collection.title),
+		collection.get_data_type(),
+		make_link(
+			&format!("{url}/{OGC_TILE_MATRIX_SET}"),
+			"self",
+			"application/json",
+			&collection.title
+		),
+		make_tiling_scheme_link(root_url),
+	)
+}
+
+pub fn make_tileset(root_url: &str, collection: &OgcCollection) -> String {
+	let url = format!("{root_url}/collections/{}/tiles/{OGC_TILE_MATRIX_SET}", collection.id);
+	let bbox_pyramide = collection.parameters.get_bbox_pyramide();
+	let mime = get_tile_mime(collection.parameters.get_tile_format());
+
+	let limits: Vec<String> = bbox_pyramide
+		.iter_levels()
+		.map(|(level, bbox)| {
+			format!(
+				"{{\"tileMatrix\":\"{level}\",\"minTileRow\":{},\"maxTileRow\":{},\"minTileCol\":{},\"maxTileCol\":{}}}",
+				bbox.y_min, bbox.y_max, bbox.x_min, bbox.x_max
+			)
+		})
+		.collect();
+
+	let bounding_box = if bbox_pyramide.is_empty() {
+		String::new()
+	} else {
+		let [west, south, east, north] = bbox_pyramide.get_geo_bbox();
+		format!(
+			",\"boundingBox\":{{\"lowerLeft\":[{west},{south}],\"upperRight\":[{east},{north}],\"crs\":\"{CRS84_URI}\"}}"
+		)
+	};
+
+	format!(
+		"{{\"title\":{},\"dataType\":\"{}\",\"crs\":\"{CRS_URI}\",\"tileMatrixSetURI\":\"{TILE_MATRIX_SET_URI}\",\"tileMatrixSetLimits\":[{}]{bounding_box},\"links\":[{},{},{{\"href\":{},\"rel\":\"item\",\"type\":\"{mime}\",\"templated\":true}}]}}",
+		json_string(&collection.title),
+		collection.get_data_type(),
+		limits.join(","),
+		make_link(&url, "self", "application/json", &collection.title),
+		make_tiling_scheme_link(root_url),
+		json_string(&format!("{url}/{{tileMatrix}}/{{tileRow}}/{{tileCol}}")),
+	)
+}
+
+pub fn make_tile_matrix_sets(root_url: &str) -> String {
+	format!(
+		"{{\"tileMatrixSets\":[{{\"id\":\"{OGC_TILE_MATRIX_SET}\",\"title\":\"Google Maps Compatible for the World\",\"uri\":\"{TILE_MATRIX_SET_URI}\",\"links\":[{}]}}]}}",
+		make_tiling_scheme_link(root_url)
+	)
+}
+
+/// The definition of WebMercatorQuad, as in the OGC Two Dimensional Tile Matrix Set standard.
+pub fn make_tile_matrix_set() -> String {
+	let matrices: Vec<String> = (0..=OGC_ZOOM_MAX)
+		.map(|level| {
+			let size = 2u64.pow(level as u32);
+			format!(
+				"{{\"id\":\"{level}\",\"scaleDenominator\":{},\"cellSize\":{},\"cornerOfOrigin\":\"topLeft\",\"pointOfOrigin\":[{},{WEB_MERCATOR_MAX}],\"tileWidth\":256,\"tileHeight\":256,\"matrixWidth\":{size},\"matrixHeight\":{size}}}",
+				SCALE_DENOMINATOR_0 / size as f64,
+				CELL_SIZE_0 / size as f64,
+				-WEB_MERCATOR_MAX,
+			)
+		})
+		.collect();
+
+	format!(
+		"{{\"id\":\"{OGC_TILE_MATRIX_SET}\",\"title\":\"Google Maps Compatible for the World\",\"uri\":\"{TILE_MATRIX_SET_URI}\",\"crs\":\"{CRS_URI}\",\"orderedAxes\":[\"E\",\"N\"],\"wellKnownScaleSet\":\"http://www.opengis.net/def/wkss/OGC/1.0/GoogleMapsCompatible\",\"tileMatrices\":[{}]}}",
+		matrices.join(",")
+	)
+}
+
+/// An error as defined by OGC API - Common.
+pub fn make_exception(code: &str, description: &str) -> String {
+	format!(
+		"{{\"code\":{},\"description\":{}}}",
+		json_string(code),
+		json_string(description)
+	)
+}
+
+fn make_tiling_scheme_link(root_url: &str) -> String {
+	make_link(
+		&format!("{root_url}/tileMatrixSets/{OGC_TILE_MATRIX_SET}"),
+		REL_TILING_SCHEME,
+		"application/json",
+		OGC_TILE_MATRIX_SET,
+	)
+}
+
+fn make_link(href: &str, rel: &str, mime: &str, title: &str) -> String {
+	format!(
+		"{{\"href\":{},\"rel\":{},\"type\":\"{mime}\",\"title\":{}}}",
+		json_string(href),
+		json_string(rel),
+		json_string(title)
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::shared::{Compression, TileBBox, TileBBoxPyramide};
+	use serde_yaml::Value;
+
+	const ROOT: &str = "http://localhost:8080/ogc";
+
+	fn get_collection() -> OgcCollection {
+		let mut bbox_pyramide = TileBBoxPyramide::new_empty();
+		bbox_pyramide.include_bbox(2, &TileBBox::new(1, 1, 2, 3));
+		OgcCollection {
+			id: "osm".to_owned(),
+			title: "OpenStreetMap \"Shortbread\"".to_owned(),
+			parameters: TileReaderParameters::new(TileFormat::PBF, Compression::Gzip, bbox_pyramide),
+		}
+	}
+
+	fn parse(json: &str) -> Value {
+		serde_yaml::from_str(json).unwrap()
+	}
+
+	fn get_link<'a>(value: &'a Value, rel: &str) -> &'a Value {
+		value["links"]
+			.as_sequence()
+			.unwrap()
+			.iter()
+			.find(|link| link["rel"] == rel)
+			.unwrap_or_else(|| panic!("link {rel} is missing"))
+	}
+
+	#[test]
+	fn test_landing_page() {
+		let value = parse(&make_landing_page(ROOT));
+		assert_eq!(get_link(&value, "self")["href"], ROOT);
+		assert_eq!(
+			get_link(&value, "conformance")["href"],
+			"http://localhost:8080/ogc/conformance"
+		);
+		assert_eq!(
+			get_link(&value, "data")["href"],
+			"http://localhost:8080/ogc/collections"
+		);
+		assert_eq!(
+			get_link(&value, REL_TILING_SCHEMES)["href"],
+			"http://localhost:8080/ogc/tileMatrixSets"
+		);
+
+		let value = parse(&make_conformance());
+		assert_eq!(value["conformsTo"].as_sequence().unwrap().len(), 11);
+	}
+
+	#[test]
+	fn test_collections() {
+		let value = parse(&make_collections(ROOT, &[get_collection()]));
+		let collection = &value["collections"][0];
+		assert_eq!(collection["id"], "osm");
+		assert_eq!(collection["title"], "OpenStreetMap \"Shortbread\"");
+		assert_eq!(collection["extent"]["spatial"]["bbox"][0][0].as_f64().unwrap(), -90.0);
+		assert_eq!(
+			get_link(collection, "http://www.opengis.net/def/rel/ogc/1.0/tilesets-vector")["href"],
+			"http://localhost:8080/ogc/collections/osm/tiles"
+		);
+	}
+
+	#[test]
+	fn test_tilesets() {
+		let value = parse(&make_tilesets(ROOT, &get_collection()));
+		let tileset = &value["tilesets"][0];
+		assert_eq!(tileset["dataType"], "vector");
+		assert_eq!(tileset["tileMatrixSetURI"], TILE_MATRIX_SET_URI);
+		assert_eq!(
+			get_link(tileset, "self")["href"],
+			"http://localhost:8080/ogc/collections/osm/tiles/WebMercatorQuad"
+		);
+
+		let value = parse(&make_tileset(ROOT, &get_collection()));
+		assert_eq!(value["tileMatrixSetLimits"].as_sequence().unwrap().len(), 1);
+		assert_eq!(value["tileMatrixSetLimits"][0]["tileMatrix"], "2");
+		assert_eq!(value["tileMatrixSetLimits"][0]["maxTileRow"], 3);
+		assert_eq!(value["boundingBox"]["crs"], CRS84_URI);
+		let item = get_link(&value, "item");
+		assert_eq!(
+			item["href"],
+			"http://localhost:8080/ogc/collections/osm/tiles/WebMercatorQuad/{tileMatrix}/{tileRow}/{tileCol}"
+		);
+		assert_eq!(item["type"], "application/x-protobuf");
+		assert_eq!(item["templated"], true);
+		assert_eq!(
+			get_link(&value, REL_TILING_SCHEME)["href"],
+			"http://localhost:8080/ogc/tileMatrixSets/WebMercatorQuad"
+		);
+	}
+
+	#[test]
+	fn test_tile_matrix_set() {
+		let value = parse(&make_tile_matrix_sets(ROOT));
+		assert_eq!(value["tileMatrixSets"][0]["id"], "WebMercatorQuad");
+
+		let value = parse(&make_tile_matrix_set());
+		let matrices = value["tileMatrices"].as_sequence().unwrap();
+		assert_eq!(matrices.len(), 25);
+		assert_eq!(matrices[0]["scaleDenominator"].as_f64().unwrap(), 559082264.0287178);
+		assert_eq!(matrices[0]["pointOfOrigin"][0].as_f64().unwrap(), -20037508.342789244);
+		assert_eq!(matrices[3]["matrixWidth"], 8);
+		assert_eq!(matrices[3]["cellSize"].as_f64().unwrap(), 19567.87924100512);
+
+		let value = parse(&make_exception("NotFound", "unknown collection \"x\""));
+		assert_eq!(value["description"], "unknown collection \"x\"");
+	}
+}
@@ -0,0 +1,218 @@
+use serde::Deserialize;
+
+/// MapLibre GL JS, embedded so that the preview works offline. See "helpers/update_maplibre.sh".
+pub const PREVIEW_ASSETS: [(&str, &str, &[u8]); 3] = [
+	(
+		"maplibre-gl.js",
+		"application/javascript",
+		include_bytes!("../../assets/maplibre-gl/maplibre-gl.js"),
+	),
+	(
+		"maplibre-gl.css",
+		"text/css",
+		include_bytes!("../../assets/maplibre-gl/maplibre-gl.css"),
+	),
+	(
+		"LICENSE.txt",
+		"text/plain",
+		include_bytes!("../../assets/maplibre-gl/LICENSE.txt"),
+	),
+];
+
+/// The part of the source info, see `ServerSourceTrait::get_info_as_json`, that is needed for a preview.
+#[derive(Debug, Deserialize)]
+struct SourceInfo {
+	format: String,
+	zoom_min: u8,
+	zoom_max: u8,
+	bbox: [f64; 4],
+}
+
+#[derive(Debug, Deserialize)]
+struct Meta {
+	#[serde(default)]
+	vector_layers: Vec<VectorLayer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VectorLayer {
+	id: String,
+}
+
+/// Builds the html page, that shows a tile source with MapLibre.
+/// An "api_key" in the url of the page is also added to all tile requests.
+pub fn make_preview_html(name: &str, info_json: &str) -> String {
+	let bounds = serde_json::from_str::<SourceInfo>(info_json).map_or([-180.0, -85.0, 180.0, 85.0], |info| info.bbox);
+	let title = escape_html(name);
+	let name_js = escape_script(name);
+
+	format!(
+		r#"<!DOCTYPE html>
+<html>
+<head>
+	<meta charset="utf-8">
+	<meta name="viewport" content="width=device-width, initial-scale=1">
+	<title>{title} - VersaTiles preview</title>
+	<link rel="stylesheet" href="/preview/assets/maplibre-gl.css">
+	<script src="/preview/assets/maplibre-gl.js"></script>
+	<style>html, body, #map {{ width: 100%; height: 100%; margin: 0; padding: 0 }}</style>
+</head>
+<body>
+	<div id="map"></div>
+	<script>
+		const apiKey = new URLSearchParams(location.search).get('api_key');
+		fetch('/preview/' + encodeURIComponent({name_js}) + '/style.json' + location.search)
+			.then(response => response.json())
+			.then(style => {{
+				for (const source of Object.values(style.sources)) {{
+					source.tiles = source.tiles.map(url => location.origin + url);
+				}}
+				const map = new maplibregl.Map({{
+					container: 'map',
+					style,
+					hash: true,
+					bounds: location.hash ? undefined : {bounds:?},
+					transformRequest: url => {{
+						if (!apiKey || !url.startsWith(location.origin)) return {{ url }};
+						return {{ url: url + (url.includes('?') ? '&' : '?') + 'api_key=' + encodeURIComponent(apiKey) }};
+					}},
+				}});
+				map.addControl(new maplibregl.NavigationControl());
+			}});
+	</script>
+</body>
+</html>
+"#
+	)
+}
+
+/// Builds a MapLibre style for a tile source: an inspect style with a color per layer for vector tiles,
+/// or a raster layer for images. `meta` is the content of "meta.json".
+pub fn make_preview_style(prefix: &str, info_json: &str, meta: &[u8]) -> String {
+	let info: SourceInfo = match serde_json::from_str(info_json) {
+		Ok(info) => info,
+		Err(_) => {
+			return "{\"version\":8,\"sources\":{},\"layers\":[{\"id\":\"background\",\"type\":\"background\",\"paint\":{\"background-color\":\"#000\"}}]}".to_owned()
+		}
+	};
+
+	let is_vector = info.format == "pbf";
+	let source = format!(
+		"{{\"type\":\"{}\",\"tiles\":[\"{prefix}{{z}}/{{x}}/{{y}}\"],\"minzoom\":{},\"maxzoom\":{},\"bounds\":{:?}{}}}",
+		if is_vector { "vector" } else { "raster" },
+		info.zoom_min,
+		info.zoom_max,
+		info.bbox,
+		if is_vector { "" } else { ",\"tileSize\":256" },
+	);
+
+	let mut layers: Vec<String> =
+		vec!["{\"id\":\"background\",\"type\":\"background\",\"paint\":{\"background-color\":\"#000\"}}".to_owned()];
+
+	if is_vector {
+		let meta: Meta = serde_json::from_slice(meta).unwrap_or(Meta { vector_layers: vec![] });
+		for layer in meta.vector_layers.iter() {
+			let id = layer.id.replace(['"', '\\'], "");
+			let color = get_layer_color(&id);
+			let base = format!("\"source\":\"tiles\",\"source-layer\":\"{id}\"");
+			layers.push(format!(
+				"{{\"id\":\"{id}-fill\",\"type\":\"fill\",{base},\"filter\":[\"==\",\"$type\",\"Polygon\"],\"paint\":{{\"fill-color\":\"{color}\",\"fill-opacity\":0.2}}}}"
+			));
+			layers.push(format!(
+				"{{\"id\":\"{id}-line\",\"type\":\"line\",{base},\"filter\":[\"in\",\"$type\",\"LineString\",\"Polygon\"],\"paint\":{{\"line-color\":\"{color}\",\"line-width\":1}}}}"
+			));
+			layers.push(format!(
+				"{{\"id\":\"{id}-circle\",\"type\":\"circle\",{base},\"filter\":[\"==\",\"$type\",\"Point\"],\"paint\":{{\"circle-color\":\"{color}\",\"circle-radius\":2}}}}"
+			));
+		}
+	} else {
+		layers.push("{\"id\":\"tiles\",\"type\":\"raster\",\"source\":\"tiles\"}".to_owned());
+	}
+
+	format!(
+		"{{\"version\":8,\"sources\":{{\"tiles\":{source}}},\"layers\":[{}]}}",
+		layers.join(",")
+	)
+}
+
+/// Escapes text for html content and attributes.
+fn escape_html(text: &str) -> String {
+	text
+		.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&#39;")
+}
+
+/// Returns text as a JavaScript string literal, that can't close the surrounding script element.
+fn escape_script(text: &str) -> String {
+	serde_json::to_string(text)
+		.unwrap()
+		.replace('<', "\\u003c")
+		.replace('>', "\\u003e")
+}
+
+/// A stable color per layer, so that a layer looks the same in every preview.
+fn get_layer_color(id: &str) -> String {
+	let hash = id
+		.bytes()
+		.fold(2166136261u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(16777619));
+	format!("hsl({},80%,60%)", hash % 360)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const INFO: &str = "{ \"container\":\"versatiles\", \"format\":\"pbf\", \"compression\":\"gzip\", \"zoom_min\":0, \"zoom_max\":14, \"bbox\":[-180.0, -85.0, 180.0, 85.0] }";
+
+	#[test]
+	fn test_vector_style() {
+		let meta = b"{\"vector_layers\":[{\"id\":\"water\",\"fields\":{}},{\"id\":\"roads\"}]}";
+		let style = make_preview_style("/tiles/osm/", INFO, meta);
+		assert!(style.starts_with("{\"version\":8,\"sources\":{\"tiles\":{\"type\":\"vector\",\"tiles\":[\"/tiles/osm/{z}/{x}/{y}\"],\"minzoom\":0,\"maxzoom\":14,"));
+		for id in ["water-fill", "water-line", "water-circle", "roads-fill"] {
+			assert!(style.contains(&format!("\"id\":\"{id}\"")), "{id} is missing");
+		}
+		assert!(style.contains(&get_layer_color("water")));
+		assert_ne!(get_layer_color("water"), get_layer_color("roads"));
+
+		// the style is valid json
+		let value: serde_json::Value = serde_json::from_str(&style).unwrap();
+		assert_eq!(value["layers"].as_array().unwrap().len(), 7);
+	}
+
+	#[test]
+	fn test_raster_style() {
+		let info = INFO.replace("pbf", "png");
+		let style = make_preview_style("/tiles/sat/", &info, b"");
+		assert!(style.contains("\"type\":\"raster\",\"tiles\":[\"/tiles/sat/{z}/{x}/{y}\"]"));
+		assert!(style.contains("{\"id\":\"tiles\",\"type\":\"raster\",\"source\":\"tiles\"}"));
+	}
+
+	#[test]
+	fn test_html() {
+		let html = make_preview_html("osm", INFO);
+		assert!(html.contains("<title>osm - VersaTiles preview</title>"));
+		assert!(html.contains("fetch('/preview/' + encodeURIComponent(\"osm\") + '/style.json'"));
+		assert!(html.contains("bounds: location.hash ? undefined : [-180.0, -85.0, 180.0, 85.0],"));
+		assert!(!html.contains("http"));
+
+		let html = make_preview_html("</script><b>\"'", INFO);
+		assert!(html.contains("<title>&lt;/script&gt;&lt;b&gt;&quot;&#39; - VersaTiles preview</title>"));
+		assert!(html.contains("encodeURIComponent(\"\\u003c/script\\u003e\\u003cb\\u003e\\\"'\")"));
+		assert!(!html.contains("</script><b>"));
+	}
+
+	#[test]
+	fn test_assets() {
+		let js = String::from_utf8_lossy(PREVIEW_ASSETS[0].2);
+		assert!(
+			!js.contains("MapLibre GL JS is missing") && js.contains("maplibregl"),
+			"the embedded MapLibre GL JS is a placeholder, run \"helpers/update_maplibre.sh\""
+		);
+		let license = String::from_utf8_lossy(PREVIEW_ASSETS[2].2);
+		assert!(license.contains("Copyright"), "the MapLibre license is missing");
+	}
+}
@@ -0,0 +1,188 @@
+use crate::shared::{decompress, Blob, Compression};
+use axum::{
+	body::{Bytes, Full},
+	http::{
+		header::{ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, ETAG},
+		HeaderValue, StatusCode,
+	},
+	response::Response,
+};
+
+/// Result of parsing a "Range" header against a body of a known length.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+	/// no range or an invalid header, the whole body is served
+	Full,
+	/// first and last byte, both inclusive
+	Partial(u64, u64),
+	/// the range is outside of the body or has multiple parts, answered with 416
+	Unsatisfiable,
+}
+
+/// Parses a "Range" header like "bytes=0-499", "bytes=500-" or "bytes=-500".
+/// Multiple ranges are not supported and rejected.
+pub fn parse_range(header: &str, length: u64) -> ByteRange {
+	let ranges = match header.trim().strip_prefix("bytes=") {
+		Some(ranges) => ranges,
+		None => return ByteRange::Full,
+	};
+
+	if ranges.contains(',') {
+		return ByteRange::Unsatisfiable;
+	}
+
+	let (start, end) = match ranges.trim().split_once('-') {
+		Some(parts) => parts,
+		None => return ByteRange::Full,
+	};
+	let parse = |value: &str| -> Option<Option<u64>> {
+		if value.is_empty() {
+			Some(None)
+		} else {
+			value.parse::<u64>().ok().map(Some)
+		}
+	};
+	let (start, end) = match (parse(start), parse(end)) {
+		(Some(start), Some(end)) => (start, end),
+		_ => return ByteRange::Full,
+	};
+
+	match (start, end) {
+		(Some(start), _) if start >= length => ByteRange::Unsatisfiable,
+		(Some(start), Some(end)) if end < start => ByteRange::Full,
+		(Some(start), end) => ByteRange::Partial(start, end.map_or(length - 1, |end| end.min(length - 1))),
+		(None, Some(0)) => ByteRange::Unsatisfiable,
+		(None, Some(_)) if length == 0 => ByteRange::Unsatisfiable,
+		(None, Some(suffix)) => ByteRange::Partial(length - suffix.min(length), length - 1),
+		(None, None) => ByteRange::Full,
+	}
+}
+
+/// Strong entity tag of a response body: its length and CRC32, which don't change between releases.
+pub fn get_etag(data: &[u8]) -> String {
+	format!("\"{:x}-{:08x}\"", data.len(), crc32fast::hash(data))
+}
+
+/// Entity tag of the uncompressed body, so that compressed responses and byte ranges,
+/// which are always uncompressed, share the same tag and "If-Range" works for both.
+fn get_content_etag(data: &Bytes, content_encoding: Option<&HeaderValue>) -> String {
+	let compression = match content_encoding.and_then(|value| value.to_str().ok()) {
+		Some("br") => Compression::Brotli,
+		Some("gzip") => Compression::Gzip,
+		_ => return get_etag(data),
+	};
+	match decompress(Blob::from(data.clone()), &compression) {
+		Ok(content) => get_etag(content.as_slice()),
+		Err(_) => get_etag(data),
+	}
+}
+
+/// Adds "Content-Length", "Accept-Ranges" and "ETag" to a static response and answers
+/// a "Range" request with the requested part of the body.
+/// If "If-Range" doesn't match the entity tag, the whole body is served.
+pub async fn respond_with_range(
+	response: Response<Full<Bytes>>, range: Option<&str>, if_range: Option<&str>,
+) -> Response<Full<Bytes>> {
+	let (mut parts, body) = response.into_parts();
+	let data = hyper::body::to_bytes(body).await.unwrap();
+	let length = data.len() as u64;
+	let etag = get_content_etag(&data, parts.headers.get(CONTENT_ENCODING));
+
+	parts.headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+	parts.headers.insert(ETAG, etag.parse().unwrap());
+
+	let range = match range {
+		Some(_) if if_range.is_some_and(|if_range| if_range.trim() != etag) => ByteRange::Full,
+		Some(range) => parse_range(range, length),
+		None => ByteRange::Full,
+	};
+
+	let body = match range {
+		ByteRange::Full => data,
+		ByteRange::Partial(start, end) => {
+			parts.status = StatusCode::PARTIAL_CONTENT;
+			parts
+				.headers
+				.insert(CONTENT_RANGE, format!("bytes {start}-{end}/{length}").parse().unwrap());
+			data.slice(start as usize..end as usize + 1)
+		}
+		ByteRange::Unsatisfiable => {
+			parts.status = StatusCode::RANGE_NOT_SATISFIABLE;
+			parts
+				.headers
+				.insert(CONTENT_RANGE, format!("bytes */{length}").parse().unwrap());
+			Bytes::from("Range Not Satisfiable")
+		}
+	};
+
+	parts.headers.insert(CONTENT_LENGTH, body.len().into());
+	Response::from_parts(parts, Full::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{body::HttpBody, http::header::CONTENT_TYPE};
+
+	#[test]
+	fn test_parse_range() {
+		assert_eq!(parse_range("bytes=0-499", 1000), ByteRange::Partial(0, 499));
+		assert_eq!(parse_range("bytes=500-", 1000), ByteRange::Partial(500, 999));
+		assert_eq!(parse_range("bytes=500-2000", 1000), ByteRange::Partial(500, 999));
+		assert_eq!(parse_range("bytes=-100", 1000), ByteRange::Partial(900, 999));
+		assert_eq!(parse_range("bytes=-2000", 1000), ByteRange::Partial(0, 999));
+		assert_eq!(parse_range("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+		assert_eq!(parse_range("bytes=-0", 1000), ByteRange::Unsatisfiable);
+		assert_eq!(parse_range("bytes=-5", 0), ByteRange::Unsatisfiable);
+		assert_eq!(parse_range("bytes=0-1,5-6", 1000), ByteRange::Unsatisfiable);
+		assert_eq!(parse_range("bytes=5-1", 1000), ByteRange::Full);
+		assert_eq!(parse_range("bytes=a-b", 1000), ByteRange::Full);
+		assert_eq!(parse_range("items=0-1", 1000), ByteRange::Full);
+	}
+
+	#[tokio::test]
+	async fn test_respond_with_range() {
+		let make_response = || {
+			Response::builder()
+				.header(CONTENT_TYPE, "text/plain")
+				.body(Full::from("0123456789"))
+				.unwrap()
+		};
+		let etag = get_etag(b"0123456789");
+		assert_eq!(etag, "\"a-a684c7c6\"");
+
+		let mut response = respond_with_range(make_response(), None, None).await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+		assert_eq!(response.headers()[CONTENT_LENGTH], "10");
+		assert_eq!(response.headers()[ETAG], etag.as_str());
+		assert_eq!(response.data().await.unwrap().unwrap(), "0123456789");
+
+		let mut response = respond_with_range(make_response(), Some("bytes=2-4"), None).await;
+		assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+		assert_eq!(response.headers()[CONTENT_RANGE], "bytes 2-4/10");
+		assert_eq!(response.headers()[CONTENT_LENGTH], "3");
+		assert_eq!(response.headers()[CONTENT_TYPE], "text/plain");
+		assert_eq!(response.data().await.unwrap().unwrap(), "234");
+
+		// If-Range
+		let response = respond_with_range(make_response(), Some("bytes=2-4"), Some(&etag)).await;
+		assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+		let response = respond_with_range(make_response(), Some("bytes=2-4"), Some("\"outdated\"")).await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(response.headers()[CONTENT_LENGTH], "10");
+
+		let response = respond_with_range(make_response(), Some("bytes=0-1,3-4"), None).await;
+		assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+		assert_eq!(response.headers()[CONTENT_RANGE], "bytes */10");
+
+		// compressed responses have the tag of the uncompressed body, like byte ranges
+		let compressed = crate::shared::compress_gzip(Blob::from("0123456789")).unwrap();
+		let response = Response::builder()
+			.header(CONTENT_ENCODING, "gzip")
+			.body(Full::from(compressed.as_vec()))
+			.unwrap();
+		let response = respond_with_range(response, None, None).await;
+		assert_eq!(response.headers()[ETAG], etag.as_str());
+	}
+}
@@ -0,0 +1,282 @@
+use crate::shared::{Error, Result};
+use axum::http::HeaderMap;
+use serde::Deserialize;
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+/// maximum number of clients a rate limiter keeps track of
+const MAX_CLIENTS: usize = 100_000;
+
+/// Rate limit per client: a token bucket that is refilled with `rate` requests per second
+/// and holds up to `burst` requests.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+	/// requests per second
+	pub rate: u32,
+	/// maximum number of requests in a burst, same as `rate` if not set
+	pub burst: Option<u32>,
+}
+
+impl RateLimitConfig {
+	pub fn new(rate: u32, burst: Option<u32>) -> RateLimitConfig {
+		RateLimitConfig { rate, burst }
+	}
+
+	pub fn get_burst(&self) -> u32 {
+		self.burst.unwrap_or(self.rate)
+	}
+
+	pub fn validate(&self) -> Result<()> {
+		if self.rate == 0 {
+			return Err(Error::new("rate limit must be greater than 0"));
+		}
+		if self.get_burst() == 0 {
+			return Err(Error::new("rate limit burst must be greater than 0"));
+		}
+		Ok(())
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+/// Token buckets per client IP. Memory is bounded: if too many clients are tracked,
+/// idle clients are forgotten first, then the least recently seen ones.
+#[derive(Debug)]
+pub struct RateLimiter {
+	rate: f64,
+	burst: f64,
+	max_clients: usize,
+	buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+	pub fn new(config: &RateLimitConfig) -> RateLimiter {
+		RateLimiter {
+			rate: config.rate as f64,
+			burst: config.get_burst() as f64,
+			max_clients: MAX_CLIENTS,
+			buckets: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Takes one request from the bucket of the client.
+	/// If the bucket is empty, returns the time until the next request is allowed.
+	pub fn check(&self, ip: IpAddr) -> std::result::Result<(), Duration> {
+		self.check_at(ip, Instant::now())
+	}
+
+	fn check_at(&self, ip: IpAddr, now: Instant) -> std::result::Result<(), Duration> {
+		let mut buckets = self.buckets.lock().unwrap();
+
+		if !buckets.contains_key(&ip) && buckets.len() >= self.max_clients {
+			self.shrink(&mut buckets, now);
+		}
+
+		let bucket = buckets.entry(ip).or_insert(Bucket {
+			tokens: self.burst,
+			updated: now,
+		});
+
+		let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+		bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+		bucket.updated = now;
+
+		if bucket.tokens >= 1.0 {
+			bucket.tokens -= 1.0;
+			Ok(())
+		} else {
+			Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+		}
+	}
+
+	/// Removes all clients whose buckets are full again. If that's not enough,
+	/// removes the least recently seen tenth of the clients.
+	fn shrink(&self, buckets: &mut HashMap<IpAddr, Bucket>, now: Instant) {
+		buckets.retain(|_, bucket| {
+			let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+			bucket.tokens + elapsed * self.rate < self.burst
+		});
+
+		if buckets.len() >= self.max_clients {
+			let mut times: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+			let index = times.len() / 10;
+			let (_, threshold, _) = times.select_nth_unstable(index);
+			let threshold = *threshold;
+			buckets.retain(|_, bucket| bucket.updated > threshold);
+		}
+	}
+
+	#[cfg(test)]
+	fn client_count(&self) -> usize {
+		self.buckets.lock().unwrap().len()
+	}
+}
+
+/// An IP address or a network in CIDR notation, e.g. "10.0.0.0/8".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpRange {
+	addr: IpAddr,
+	prefix_len: u8,
+}
+
+impl IpRange {
+	pub fn parse(text: &str) -> Result<IpRange> {
+		let error = || Error::new(&format!("invalid IP address or network \"{text}\""));
+
+		let (addr, prefix_len) = match text.trim().split_once('/') {
+			Some((addr, prefix_len)) => (addr, Some(prefix_len.parse::<u8>().map_err(|_| error())?)),
+			None => (text.trim(), None),
+		};
+		let addr: IpAddr = addr.parse().map_err(|_| error())?;
+		let max_len = if addr.is_ipv4() { 32 } else { 128 };
+		let prefix_len = prefix_len.unwrap_or(max_len);
+		if prefix_len > max_len {
+			return Err(error());
+		}
+
+		Ok(IpRange { addr, prefix_len })
+	}
+
+	pub fn contains(&self, ip: &IpAddr) -> bool {
+		let mask = |bits: u32| {
+			if self.prefix_len == 0 {
+				0
+			} else {
+				u128::MAX << (bits - self.prefix_len as u32)
+			}
+		};
+		match (self.addr, ip) {
+			(IpAddr::V4(range), IpAddr::V4(ip)) => {
+				let mask = mask(32) as u32;
+				u32::from(range) & mask == u32::from(*ip) & mask
+			}
+			(IpAddr::V6(range), IpAddr::V6(ip)) => {
+				let mask = mask(128);
+				u128::from(range) & mask == u128::from(*ip) & mask
+			}
+			_ => false,
+		}
+	}
+}
+
+/// Determines the client IP. "X-Forwarded-For" is only used if the request comes from a trusted proxy.
+/// The client is the rightmost address that is not a trusted proxy.
+pub fn get_client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpRange]) -> IpAddr {
+	let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|range| range.contains(ip));
+
+	if !is_trusted(&peer) {
+		return peer;
+	}
+
+	let mut client = peer;
+	for value in headers.get_all("x-forwarded-for").iter().rev() {
+		let value = match value.to_str() {
+			Ok(value) => value,
+			Err(_) => return client,
+		};
+		for entry in value.rsplit(',') {
+			match entry.trim().parse::<IpAddr>() {
+				Ok(ip) => {
+					client = ip;
+					if !is_trusted(&ip) {
+						return client;
+					}
+				}
+				Err(_) => return client,
+			}
+		}
+	}
+	client
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_token_bucket() {
+		let limiter = RateLimiter::new(&RateLimitConfig::new(2, Some(3)));
+		let ip: IpAddr = "10.0.0.1".parse().unwrap();
+		let other: IpAddr = "10.0.0.2".parse().unwrap();
+		let now = Instant::now();
+
+		// burst
+		for _ in 0..3 {
+			assert!(limiter.check_at(ip, now).is_ok());
+		}
+		assert_eq!(limiter.check_at(ip, now), Err(Duration::from_millis(500)));
+		assert!(limiter.check_at(other, now).is_ok());
+
+		// refill with 2 requests per second
+		assert!(limiter.check_at(ip, now + Duration::from_millis(500)).is_ok());
+		assert!(limiter.check_at(ip, now + Duration::from_millis(500)).is_err());
+		assert!(limiter.check_at(ip, now + Duration::from_millis(1000)).is_ok());
+	}
+
+	#[test]
+	fn test_bounded_memory() {
+		let mut limiter = RateLimiter::new(&RateLimitConfig::new(1, None));
+		limiter.max_clients = 100;
+		let now = Instant::now();
+
+		for i in 0..1000u32 {
+			let ip = IpAddr::from((i + 1).to_be_bytes());
+			limiter.check_at(ip, now + Duration::from_millis(i as u64)).unwrap();
+			assert!(limiter.client_count() <= 100);
+		}
+
+		// idle clients with full buckets are removed first
+		let mut buckets = limiter.buckets.lock().unwrap();
+		limiter.shrink(&mut buckets, now + Duration::from_secs(10));
+		assert!(buckets.is_empty());
+	}
+
+	#[test]
+	fn test_ip_range() {
+		let range = IpRange::parse("10.0.0.0/8").unwrap();
+		assert!(range.contains(&"10.1.2.3".parse().unwrap()));
+		assert!(!range.contains(&"11.0.0.1".parse().unwrap()));
+		assert!(!range.contains(&"::1".parse().unwrap()));
+
+		let range = IpRange::parse("127.0.0.1").unwrap();
+		assert!(range.contains(&"127.0.0.1".parse().unwrap()));
+		assert!(!range.contains(&"127.0.0.2".parse().unwrap()));
+
+		let range = IpRange::parse("fd00::/8").unwrap();
+		assert!(range.contains(&"fd12::1".parse().unwrap()));
+		assert!(IpRange::parse("0.0.0.0/0")
+			.unwrap()
+			.contains(&"1.2.3.4".parse().unwrap()));
+
+		assert!(IpRange::parse("10.0.0.0/33").is_err());
+		assert!(IpRange::parse("localhost").is_err());
+	}
+
+	#[test]
+	fn test_get_client_ip() {
+		let proxies = vec![IpRange::parse("10.0.0.0/8").unwrap()];
+		let test = |peer: &str, forwarded: Option<&str>| {
+			let mut headers = HeaderMap::new();
+			if let Some(forwarded) = forwarded {
+				headers.insert("x-forwarded-for", forwarded.parse().unwrap());
+			}
+			get_client_ip(peer.parse().unwrap(), &headers, &proxies).to_string()
+		};
+
+		assert_eq!(test("1.2.3.4", Some("5.6.7.8")), "1.2.3.4");
+		assert_eq!(test("10.0.0.1", None), "10.0.0.1");
+		assert_eq!(test("10.0.0.1", Some("5.6.7.8")), "5.6.7.8");
+		assert_eq!(test("10.0.0.1", Some("9.9.9.9, 5.6.7.8, 10.0.0.2")), "5.6.7.8");
+		assert_eq!(test("10.0.0.1", Some("10.0.0.3, 10.0.0.2")), "10.0.0.3");
+		assert_eq!(test("10.0.0.1", Some("garbage, 10.0.0.2")), "10.0.0.2");
+	}
+}
@@ -0,0 +1,240 @@
+use super::{parse_range, ByteRange};
+use axum::{
+	body::{boxed, Body},
+	http::{
+		header::{
+			ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED,
+			RANGE,
+		},
+		HeaderMap, HeaderName, StatusCode,
+	},
+	response::Response,
+};
+use bytes::Bytes;
+use std::{io::SeekFrom, path::Path, time::UNIX_EPOCH};
+use tokio::{
+	fs::File,
+	io::{AsyncReadExt, AsyncSeekExt},
+};
+
+/// size of the chunks a file is streamed in
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Guesses the content type of a container file by its extension.
+pub fn guess_container_mime(filename: &str) -> &'static str {
+	match filename.rsplit('.').next() {
+		Some("mbtiles") => "application/vnd.sqlite3",
+		Some("pmtiles") => "application/vnd.pmtiles",
+		Some("tar") => "application/x-tar",
+		_ => "application/octet-stream",
+	}
+}
+
+/// Serves a container file with support for "Range", "If-Range" and "If-None-Match".
+/// Local files are streamed from disk, requests for remote files are passed on to the origin
+/// with a shared `client`, so that connections are reused.
+pub async fn serve_raw_file(client: &reqwest::Client, url: &str, mime: &str, headers: &HeaderMap) -> Response {
+	if url.starts_with("http://") || url.starts_with("https://") {
+		serve_remote_file(client, url, mime, headers).await
+	} else {
+		serve_local_file(Path::new(url), mime, headers).await
+	}
+}
+
+async fn serve_local_file(path: &Path, mime: &str, headers: &HeaderMap) -> Response {
+	let mut file = match File::open(path).await {
+		Ok(file) => file,
+		Err(err) => {
+			log::warn!("can not open file {path:?}: {err}");
+			return error_response(StatusCode::NOT_FOUND, "Not Found");
+		}
+	};
+	let metadata = match file.metadata().await {
+		Ok(metadata) => metadata,
+		Err(_) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
+	};
+
+	let length = metadata.len();
+	let modified = metadata
+		.modified()
+		.ok()
+		.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+		.map_or(0, |duration| duration.as_nanos());
+	let etag = format!("\"{length:x}-{modified:x}\"");
+
+	let header = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+
+	let response = Response::builder()
+		.header(CONTENT_TYPE, mime)
+		.header(ACCEPT_RANGES, "bytes")
+		.header(ETAG, &etag);
+
+	if header(IF_NONE_MATCH).is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*")) {
+		return response
+			.status(StatusCode::NOT_MODIFIED)
+			.body(boxed(Body::empty()))
+			.unwrap();
+	}
+
+	let range = match header(RANGE) {
+		Some(_) if header(IF_RANGE).is_some_and(|if_range| if_range.trim() != etag) => ByteRange::Full,
+		Some(range) => parse_range(range, length),
+		None => ByteRange::Full,
+	};
+
+	let (response, start, end) = match range {
+		ByteRange::Full => (response.status(StatusCode::OK), 0, length),
+		ByteRange::Partial(start, end) => (
+			response
+				.status(StatusCode::PARTIAL_CONTENT)
+				.header(CONTENT_RANGE, format!("bytes {start}-{end}/{length}")),
+			start,
+			end + 1,
+		),
+		ByteRange::Unsatisfiable => {
+			return response
+				.status(StatusCode::RANGE_NOT_SATISFIABLE)
+				.header(CONTENT_RANGE, format!("bytes */{length}"))
+				.body(boxed(Body::from("Range Not Satisfiable")))
+				.unwrap();
+		}
+	};
+
+	if file.seek(SeekFrom::Start(start)).await.is_err() {
+		return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error");
+	}
+
+	let (mut sender, body) = Body::channel();
+	tokio::spawn(async move {
+		let mut remaining = end - start;
+		while remaining > 0 {
+			let mut buffer = vec![0; remaining.min(CHUNK_SIZE) as usize];
+			if file.read_exact(&mut buffer).await.is_err() {
+				sender.abort();
+				return;
+			}
+			remaining -= buffer.len() as u64;
+			// the client has gone away
+			if sender.send_data(Bytes::from(buffer)).await.is_err() {
+				return;
+			}
+		}
+	});
+
+	response.header(CONTENT_LENGTH, end - start).body(boxed(body)).unwrap()
+}
+
+async fn serve_remote_file(client: &reqwest::Client, url: &str, mime: &str, headers: &HeaderMap) -> Response {
+	let mut request = client.get(url);
+	for name in [RANGE, IF_RANGE, IF_NONE_MATCH] {
+		if let Some(value) = headers.get(&name) {
+			request = request.header(name.as_str(), value.as_bytes());
+		}
+	}
+
+	let mut upstream = match request.send().await {
+		Ok(upstream) => upstream,
+		Err(err) => {
+			log::warn!("can not request {url}: {err}");
+			return error_response(StatusCode::BAD_GATEWAY, "Bad Gateway");
+		}
+	};
+
+	let mut response = Response::builder()
+		.status(upstream.status().as_u16())
+		.header(CONTENT_TYPE, mime);
+	for name in [ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG, LAST_MODIFIED] {
+		if let Some(value) = upstream.headers().get(name.as_str()) {
+			response = response.header(name, value.as_bytes());
+		}
+	}
+
+	let (mut sender, body) = Body::channel();
+	tokio::spawn(async move {
+		loop {
+			match upstream.chunk().await {
+				Ok(Some(chunk)) => {
+					if sender.send_data(chunk).await.is_err() {
+						return;
+					}
+				}
+				Ok(None) => return,
+				Err(_) => {
+					sender.abort();
+					return;
+				}
+			}
+		}
+	});
+
+	response.body(boxed(body)).unwrap()
+}
+
+fn error_response(status: StatusCode, message: &'static str) -> Response {
+	Response::builder()
+		.status(status)
+		.body(boxed(Body::from(message)))
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assert_fs::NamedTempFile;
+	use std::fs::write;
+
+	async fn request(path: &str, headers: &[(HeaderName, &str)]) -> (StatusCode, HeaderMap, Bytes) {
+		let mut header_map = HeaderMap::new();
+		for (name, value) in headers {
+			header_map.insert(name, value.parse().unwrap());
+		}
+		let response = serve_raw_file(&reqwest::Client::new(), path, "application/octet-stream", &header_map).await;
+		let (parts, body) = response.into_parts();
+		(parts.status, parts.headers, hyper::body::to_bytes(body).await.unwrap())
+	}
+
+	#[test]
+	fn test_guess_container_mime() {
+		assert_eq!(guess_container_mime("osm.versatiles"), "application/octet-stream");
+		assert_eq!(guess_container_mime("osm.mbtiles"), "application/vnd.sqlite3");
+		assert_eq!(guess_container_mime("osm.pmtiles"), "application/vnd.pmtiles");
+		assert_eq!(guess_container_mime("osm.tar"), "application/x-tar");
+	}
+
+	#[tokio::test]
+	async fn test_local_file() {
+		let file = NamedTempFile::new("test.versatiles").unwrap();
+		let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+		write(file.path(), &content).unwrap();
+		let path = file.path().to_str().unwrap();
+
+		let (status, headers, body) = request(path, &[]).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(headers[ACCEPT_RANGES], "bytes");
+		assert_eq!(headers[CONTENT_LENGTH], "200000");
+		assert_eq!(body, content);
+		let etag = headers[ETAG].to_str().unwrap().to_owned();
+
+		let (status, headers, body) = request(path, &[(RANGE, "bytes=100000-165599")]).await;
+		assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+		assert_eq!(headers[CONTENT_RANGE], "bytes 100000-165599/200000");
+		assert_eq!(body, content[100000..165600]);
+
+		let (status, _, body) = request(path, &[(RANGE, "bytes=-10"), (IF_RANGE, &etag)]).await;
+		assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+		assert_eq!(body, content[199990..]);
+
+		let (status, _, _) = request(path, &[(RANGE, "bytes=-10"), (IF_RANGE, "\"old\"")]).await;
+		assert_eq!(status, StatusCode::OK);
+
+		let (status, _, _) = request(path, &[(IF_NONE_MATCH, &etag)]).await;
+		assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+		let (status, headers, _) = request(path, &[(RANGE, "bytes=0-1,4-5")]).await;
+		assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+		assert_eq!(headers[CONTENT_RANGE], "bytes */200000");
+
+		let (status, _, _) = request("missing.versatiles", &[]).await;
+		assert_eq!(status, StatusCode::NOT_FOUND);
+	}
+}
@@ -0,0 +1,432 @@
+use super::{get_tile_format_by_extension, respond_with_tile};
+use crate::{
+	containers::TileReaderBox,
+	server::{ok_error, ok_not_found, ServerSourceTrait},
+	shared::{
+		decode_vector_tile_layers, decompress, encode_vector_tile, json_string, Blob, Compression, Error, Result,
+		TileBBoxPyramide, TileCoord3, TileFormat, TileReaderParameters, VectorTileLayer,
+	},
+};
+use async_trait::async_trait;
+use axum::{
+	body::{Bytes, Full},
+	http::header::CACHE_CONTROL,
+	response::Response,
+};
+use enumset::EnumSet;
+use serde_json::Value;
+use std::{
+	collections::{HashMap, HashSet},
+	fmt::Debug,
+	sync::Mutex,
+};
+
+/// bounds of the web mercator projection, used if the containers have no tiles
+const WORLD_BOUNDS: [f32; 4] = [-180.0, -85.05113, 180.0, 85.05113];
+
+/// A container of a composite source and the rules to rename its layers.
+struct CompositePart {
+	reader: TileReaderBox,
+	compression: Compression,
+	/// old name -> new name
+	renames: HashMap<String, String>,
+}
+
+/// Serves vector tiles, that contain the layers of the same tile in several containers.
+pub struct Composite {
+	name: String,
+	parts: Vec<CompositePart>,
+	parameters: TileReaderParameters,
+	meta: Blob,
+	cache_max_age: Option<u64>,
+	/// layers that exist in several containers, but are not declared in their "vector_layers"
+	duplicate_layers: Mutex<HashSet<String>>,
+}
+
+impl Composite {
+	/// Combines the readers in this order. Each reader comes with rules to rename its layers, e.g. "poi" -> "transit_poi".
+	/// Fails if a reader has no vector tiles, or if the "vector_layers" of two readers have the same name after renaming.
+	pub async fn new(name: &str, readers: Vec<(TileReaderBox, HashMap<String, String>)>) -> Result<Box<Composite>> {
+		if readers.is_empty() {
+			return Err(Error::new(&format!("composite source \"{name}\" has no containers")));
+		}
+
+		let mut bbox_pyramide = TileBBoxPyramide::new_empty();
+		let mut vector_layers: Vec<String> = Vec::new();
+		let mut layer_sources: HashMap<String, String> = HashMap::new();
+		let mut parts: Vec<CompositePart> = Vec::new();
+
+		for (reader, renames) in readers {
+			let parameters = reader.get_parameters();
+			if parameters.get_tile_format() != &TileFormat::PBF {
+				return Err(Error::new(&format!(
+					"composite source \"{name}\": container \"{}\" does not contain vector tiles",
+					reader.get_name()
+				)));
+			}
+			for (level, bbox) in parameters.get_bbox_pyramide().iter_levels() {
+				bbox_pyramide.include_bbox(level, bbox);
+			}
+
+			let meta: Value = serde_json::from_slice(reader.get_meta().await.as_slice()).unwrap_or(Value::Null);
+			if let Some(layers) = meta.get("vector_layers").and_then(|layers| layers.as_array()) {
+				for layer in layers.iter() {
+					let id = match layer.get("id").and_then(|id| id.as_str()) {
+						Some(id) => renames.get(id).map_or(id, |new_id| new_id.as_str()).to_owned(),
+						None => continue,
+					};
+					if let Some(other) = layer_sources.insert(id.clone(), reader.get_name().to_owned()) {
+						return Err(Error::new(&format!(
+							"composite source \"{name}\": layer \"{id}\" exists in \"{other}\" and \"{}\", rename one of them",
+							reader.get_name()
+						)));
+					}
+					let mut layer = layer.clone();
+					layer["id"] = Value::from(id);
+					vector_layers.push(layer.to_string());
+				}
+			}
+
+			parts.push(CompositePart {
+				compression: *parameters.get_tile_compression(),
+				reader,
+				renames,
+			});
+		}
+
+		let zoom_min = bbox_pyramide.get_zoom_min().unwrap_or(0);
+		let zoom_max = bbox_pyramide.get_zoom_max().unwrap_or(0);
+		let bounds = get_bounds(&bbox_pyramide);
+		let meta = format!(
+			"{{\"tilejson\":\"3.0.0\",\"name\":{},\"minzoom\":{zoom_min},\"maxzoom\":{zoom_max},\"bounds\":{bounds:?},\"vector_layers\":[{}]}}",
+			json_string(name),
+			vector_layers.join(",")
+		);
+
+		Ok(Box::new(Composite {
+			name: name.to_owned(),
+			parts,
+			parameters: TileReaderParameters::new(TileFormat::PBF, Compression::None, bbox_pyramide),
+			meta: Blob::from(meta),
+			cache_max_age: None,
+			duplicate_layers: Mutex::new(HashSet::new()),
+		}))
+	}
+
+	/// Lets clients and proxies cache successful responses for this many seconds.
+	pub fn set_cache_max_age(&mut self, seconds: u64) {
+		self.cache_max_age = Some(seconds);
+	}
+
+	/// Concatenates the layers of all containers. Returns None if no container has this tile.
+	async fn get_tile(&self, coord: &TileCoord3) -> Option<Blob> {
+		let mut layers: Vec<VectorTileLayer> = Vec::new();
+		let mut found = false;
+
+		for part in self.parts.iter() {
+			let tile = match part.reader.get_tile_data(coord).await {
+				Some(tile) => tile,
+				None => continue,
+			};
+			found = true;
+
+			let part_layers = decompress(tile, &part.compression).and_then(|tile| decode_vector_tile_layers(&tile));
+			let part_layers = match part_layers {
+				Ok(part_layers) => part_layers,
+				Err(err) => {
+					log::warn!("can not decode tile {coord:?} of \"{}\": {err}", part.reader.get_name());
+					continue;
+				}
+			};
+
+			for mut layer in part_layers {
+				if let Some(name) = part.renames.get(&layer.name) {
+					if layer.rename(name).is_err() {
+						continue;
+					}
+				}
+				// layer names must be unique in a tile
+				if layers.iter().any(|other| other.name == layer.name) {
+					if self.duplicate_layers.lock().unwrap().insert(layer.name.clone()) {
+						log::warn!(
+							"composite source \"{}\": layer \"{}\" exists in several containers, only the first one is served, rename the others",
+							self.name,
+							layer.name
+						);
+					}
+					continue;
+				}
+				layers.push(layer);
+			}
+		}
+
+		found.then(|| encode_vector_tile(&layers))
+	}
+
+	async fn get_response(&self, path: &[&str], accept: EnumSet<Compression>) -> Response<Full<Bytes>> {
+		if path.len() == 3 {
+			let z = path[0].parse::<u8>();
+			let x = path[1].parse::<u64>();
+			let y: String = path[2].chars().take_while(|c| c.is_numeric()).collect();
+			let y = y.parse::<u64>();
+
+			if x.is_err() || y.is_err() || z.is_err() {
+				return ok_not_found();
+			}
+
+			let extension_format = path[2]
+				.split_once('.')
+				.and_then(|(_, extension)| get_tile_format_by_extension(extension));
+			if extension_format.is_some_and(|format| format != TileFormat::PBF) {
+				return ok_error(406, "Not Acceptable");
+			}
+
+			return match self
+				.get_tile(&TileCoord3::new(x.unwrap(), y.unwrap(), z.unwrap()))
+				.await
+			{
+				Some(tile) => respond_with_tile(tile, Compression::None, "application/x-protobuf", accept),
+				None => ok_not_found(),
+			};
+		} else if (path[0] == "meta.json") || (path[0] == "tiles.json") {
+			return respond_with_tile(self.meta.clone(), Compression::None, "application/json", accept);
+		}
+
+		ok_not_found()
+	}
+}
+
+#[async_trait]
+impl ServerSourceTrait for Composite {
+	fn get_name(&self) -> String {
+		self.name.clone()
+	}
+
+	fn get_info_as_json(&self) -> String {
+		let bbox_pyramide = self.parameters.get_bbox_pyramide();
+		format!(
+			"{{ \"container\":\"composite\", \"format\":\"pbf\", \"compression\":\"none\", \"zoom_min\":{}, \"zoom_max\":{}, \"bbox\":{:?} }}",
+			bbox_pyramide.get_zoom_min().unwrap_or(0),
+			bbox_pyramide.get_zoom_max().unwrap_or(0),
+			get_bounds(bbox_pyramide),
+		)
+	}
+
+	async fn get_data(
+		&self, path: &[&str], accept: EnumSet<Compression>, _accept_mime: Option<&str>, _query: Option<&str>,
+	) -> Response<Full<Bytes>> {
+		let mut response = self.get_response(path, accept).await;
+
+		if let Some(max_age) = self.cache_max_age {
+			if response.status() == 200 {
+				let value = format!("public, max-age={max_age}");
+				response.headers_mut().insert(CACHE_CONTROL, value.parse().unwrap());
+			}
+		}
+
+		response
+	}
+
+	fn get_parameters(&self) -> Option<TileReaderParameters> {
+		Some(self.parameters.clone())
+	}
+}
+
+impl Debug for Composite {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let readers: Vec<&str> = self.parts.iter().map(|part| part.reader.get_name()).collect();
+		f.debug_struct("Composite")
+			.field("name", &self.name)
+			.field("readers", &readers)
+			.field("cache_max_age", &self.cache_max_age)
+			.finish()
+	}
+}
+
+/// Geographic bounds of the tiles, or of the whole world if there are none.
+fn get_bounds(bbox_pyramide: &TileBBoxPyramide) -> [f32; 4] {
+	if bbox_pyramide.is_empty() {
+		WORLD_BOUNDS
+	} else {
+		bbox_pyramide.get_geo_bbox()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::containers::{
+		dummy::{ReaderProfile, TileReader},
+		TileReaderTrait,
+	};
+	use axum::{body::HttpBody, http::header::CONTENT_TYPE};
+	use enumset::enum_set;
+
+	/// a dummy reader with vector tiles and its own meta data
+	#[derive(Debug)]
+	struct MetaReader(TileReaderBox, &'static str);
+
+	#[async_trait]
+	impl TileReaderTrait for MetaReader {
+		async fn new(_path: &str) -> Result<TileReaderBox> {
+			Err(Error::new("not needed"))
+		}
+		fn get_name(&self) -> &str {
+			self.1
+		}
+		fn get_parameters(&self) -> &TileReaderParameters {
+			self.0.get_parameters()
+		}
+		fn get_parameters_mut(&mut self) -> &mut TileReaderParameters {
+			self.0.get_parameters_mut()
+		}
+		fn get_container_name(&self) -> &str {
+			"meta container"
+		}
+		async fn get_meta(&self) -> Blob {
+			Blob::from(self.1)
+		}
+		async fn get_tile_data(&self, coord: &TileCoord3) -> Option<Blob> {
+			self.0.get_tile_data(coord).await
+		}
+	}
+
+	fn get_reader(meta: &'static str) -> TileReaderBox {
+		Box::new(MetaReader(TileReader::new_dummy(ReaderProfile::PbfFast, 8), meta))
+	}
+
+	fn get_renames(renames: &[(&str, &str)]) -> HashMap<String, String> {
+		renames.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+	}
+
+	async fn get_layer_names(composite: &Composite) -> Vec<String> {
+		let mut response = composite
+			.get_data(&["3", "2", "1.pbf"], enum_set!(Compression::None), None, None)
+			.await;
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers()[CONTENT_TYPE], "application/x-protobuf");
+		let tile = Blob::from(response.data().await.unwrap().unwrap());
+		decode_vector_tile_layers(&tile)
+			.unwrap()
+			.into_iter()
+			.map(|layer| layer.name)
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn test_merge_layers() {
+		let single = Composite::new("single", vec![(get_reader("{}"), HashMap::new())])
+			.await
+			.unwrap();
+		let names = get_layer_names(&single).await;
+		assert_eq!(names[0], "ocean");
+
+		// duplicate layers are skipped and remembered, so that they are only logged once
+		let composite = Composite::new(
+			"composite",
+			vec![(get_reader("{}"), HashMap::new()), (get_reader("{}"), HashMap::new())],
+		)
+		.await
+		.unwrap();
+		assert_eq!(get_layer_names(&composite).await, names);
+		assert_eq!(get_layer_names(&composite).await, names);
+		let duplicate_layers = composite.duplicate_layers.lock().unwrap().clone();
+		assert_eq!(duplicate_layers, HashSet::from(["ocean".to_owned()]));
+
+		// renamed layers are added
+		let composite = Composite::new(
+			"composite",
+			vec![
+				(get_reader("{}"), HashMap::new()),
+				(get_reader("{}"), get_renames(&[("ocean", "sea")])),
+			],
+		)
+		.await
+		.unwrap();
+		let mut expected = names.clone();
+		expected.push("sea".to_owned());
+		assert_eq!(get_layer_names(&composite).await, expected);
+
+		let response = composite
+			.get_data(&["3", "2", "1.png"], enum_set!(Compression::None), None, None)
+			.await;
+		assert_eq!(response.status(), 406);
+		let response = composite
+			.get_data(&["3", "2", "1"], enum_set!(Compression::Gzip), None, None)
+			.await;
+		assert_eq!(response.headers()["content-encoding"], "gzip");
+
+		// browsers accept identity, too, but get compressed tiles
+		let mut response = composite
+			.get_data(
+				&["3", "2", "1"],
+				enum_set!(Compression::None | Compression::Gzip | Compression::Brotli),
+				None,
+				None,
+			)
+			.await;
+		assert_eq!(response.headers()["content-encoding"], "br");
+		let tile = decompress(
+			Blob::from(response.data().await.unwrap().unwrap()),
+			&Compression::Brotli,
+		)
+		.unwrap();
+		assert_eq!(decode_vector_tile_layers(&tile).unwrap().len(), expected.len());
+	}
+
+	#[tokio::test]
+	async fn test_vector_layers() {
+		let basemap =
+			"{\"vector_layers\":[{\"id\":\"water\",\"fields\":{\"kind\":\"String\"},\"minzoom\":0},{\"id\":\"poi\"}]}";
+		let transit = "{\"vector_layers\":[{\"id\":\"poi\",\"description\":\"stops\"}]}";
+
+		let result = Composite::new(
+			"city",
+			vec![
+				(get_reader(basemap), HashMap::new()),
+				(get_reader(transit), HashMap::new()),
+			],
+		)
+		.await;
+		let message = result.unwrap_err().to_string();
+		assert!(message.starts_with("composite source \"city\": layer \"poi\" exists in "));
+		assert!(message.ends_with(", rename one of them"));
+
+		let composite = Composite::new(
+			"city",
+			vec![
+				(get_reader(basemap), HashMap::new()),
+				(get_reader(transit), get_renames(&[("poi", "transit_poi")])),
+			],
+		)
+		.await
+		.unwrap();
+
+		let mut response = composite
+			.get_data(&["tiles.json"], enum_set!(Compression::None), None, None)
+			.await;
+		let meta = String::from_utf8(response.data().await.unwrap().unwrap().to_vec()).unwrap();
+		assert!(meta.starts_with("{\"tilejson\":\"3.0.0\",\"name\":\"city\",\"minzoom\":0,\"maxzoom\":8,"));
+		assert!(meta.ends_with("\"vector_layers\":[{\"fields\":{\"kind\":\"String\"},\"id\":\"water\",\"minzoom\":0},{\"id\":\"poi\"},{\"description\":\"stops\",\"id\":\"transit_poi\"}]}"));
+	}
+
+	#[tokio::test]
+	async fn test_raster_container() {
+		let reader = TileReader::new_dummy(ReaderProfile::PngFast, 8);
+		let result = Composite::new("raster", vec![(reader, HashMap::new())]).await;
+		assert!(result.is_err());
+		assert!(Composite::new("empty", vec![]).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_empty_container() {
+		let mut reader = get_reader("{}");
+		reader
+			.get_parameters_mut()
+			.set_bbox_pyramide(TileBBoxPyramide::new_empty());
+		let composite = Composite::new("empty", vec![(reader, HashMap::new())]).await.unwrap();
+		assert!(composite
+			.get_info_as_json()
+			.contains("\"bbox\":[-180.0, -85.05113, 180.0, 85.05113]"));
+	}
+}
@@ -1,99 +1,415 @@
 use crate::{
-	containers::TileReaderBox,
-	server::{ok_data, ok_not_found, ServerSourceTrait},
-	shared::{compress_brotli, compress_gzip, decompress, Compression, TileCoord3, TileFormat},
+	containers::{get_reader, TileReaderBox, TileReaderStats},
+	server::{ok_data, ok_error, ok_not_found, ServerSourceTrait, TileFilterConfig},
+	shared::{
+		compress_brotli, compress_brotli_fast, compress_gzip, crop_descendant, crop_vector_tile, decode_image,
+		decompress, double_size, encode_image, stitch_images, Blob, Compression, DataConverter, Error, Result,
+		TileCoord3, TileFormat, TileReaderParameters,
+	},
 };
 use async_trait::async_trait;
 use axum::{
 	body::{Bytes, Full},
+	http::header::{CACHE_CONTROL, VARY},
 	response::Response,
 };
 use enumset::EnumSet;
-use std::fmt::Debug;
+use std::{collections::HashMap, fmt::Debug, path::PathBuf, sync::RwLock};
+
+/// maximum number of @2x tiles a container keeps in memory
+const MAX_RETINA_TILES: usize = 1024;
+/// maximum number of tiles converted to another format a container keeps in memory
+const MAX_CONVERTED_TILES: usize = 1024;
+/// a 256 pixel tile is cut down to 1 pixel at 8 levels of overzoom
+pub const MAX_OVERZOOM: u8 = 8;
+
+pub fn get_tile_mime(tile_format: &TileFormat) -> &'static str {
+	match tile_format {
+		TileFormat::BIN => "application/octet-stream",
+		TileFormat::PNG => "image/png",
+		TileFormat::JPG => "image/jpeg",
+		TileFormat::WEBP => "image/webp",
+		TileFormat::AVIF => "image/avif",
+		TileFormat::SVG => "image/svg+xml",
+		TileFormat::PBF => "application/x-protobuf",
+		TileFormat::GEOJSON => "application/geo+json",
+		TileFormat::TOPOJSON => "application/topo+json",
+		TileFormat::JSON => "application/json",
+	}
+}
+
+/// Maps the extension of a tile request, e.g. "webp" in "5/3/2.webp", to a tile format.
+pub fn get_tile_format_by_extension(extension: &str) -> Option<TileFormat> {
+	match extension.to_lowercase().as_str() {
+		"bin" => Some(TileFormat::BIN),
+		"png" => Some(TileFormat::PNG),
+		"jpg" | "jpeg" => Some(TileFormat::JPG),
+		"webp" => Some(TileFormat::WEBP),
+		"avif" => Some(TileFormat::AVIF),
+		"svg" => Some(TileFormat::SVG),
+		"pbf" | "mvt" => Some(TileFormat::PBF),
+		"geojson" => Some(TileFormat::GEOJSON),
+		"topojson" => Some(TileFormat::TOPOJSON),
+		"json" => Some(TileFormat::JSON),
+		_ => None,
+	}
+}
+
+/// Picks the format of a tile for an "Accept" header like "image/webp,image/*;q=0.8".
+/// The stored format is preferred if it is acceptable, otherwise the acceptable format
+/// with the highest quality, that tiles can be converted to. Returns None if nothing is acceptable.
+fn negotiate_tile_format(accept_mime: &str, tile_format: &TileFormat) -> Option<TileFormat> {
+	let ranges: Vec<(String, f32)> = accept_mime
+		.split(',')
+		.filter_map(|entry| {
+			let mut parts = entry.split(';').map(str::trim);
+			let range = parts.next()?.to_lowercase();
+			if range.is_empty() {
+				return None;
+			}
+			let quality = parts
+				.find_map(|part| part.strip_prefix("q="))
+				.map_or(1.0, |q| q.parse().unwrap_or(0.0));
+			Some((range, quality))
+		})
+		.collect();
+
+	if ranges.is_empty() {
+		return Some(tile_format.clone());
+	}
+
+	// the most specific range decides, e.g. "image/webp" before "image/*" before "*/*"
+	let get_quality = |format: &TileFormat| -> f32 {
+		let mime = get_tile_mime(format);
+		let group = format!("{}/*", mime.split('/').next().unwrap());
+		[mime, &group, "*/*"]
+			.iter()
+			.find_map(|range| ranges.iter().find(|(r, _)| r == range).map(|(_, q)| *q))
+			.unwrap_or(0.0)
+	};
+
+	if get_quality(tile_format) > 0.0 {
+		return Some(tile_format.clone());
+	}
+
+	let mut best: Option<(f32, TileFormat)> = None;
+	for format in [TileFormat::WEBP, TileFormat::PNG, TileFormat::JPG, TileFormat::GEOJSON] {
+		let quality = get_quality(&format);
+		if quality > 0.0
+			&& best.as_ref().is_none_or(|(q, _)| quality > *q)
+			&& DataConverter::can_convert_format(tile_format, &format)
+		{
+			best = Some((quality, format));
+		}
+	}
+	best.map(|(_, format)| format)
+}
 
 pub struct TileContainer {
 	reader: TileReaderBox,
+	tile_format: TileFormat,
 	tile_mime: String,
 	compression: Compression,
+	cache_max_age: Option<u64>,
+	url: Option<String>,
+	filter: Option<TileFilterConfig>,
+	max_overzoom: Option<u8>,
+	/// @2x tiles, that were assembled before
+	retina_cache: RwLock<HashMap<TileCoord3, Blob>>,
+	/// tiles, that were converted to another format before, by coordinate, @2x and format
+	converted_cache: RwLock<HashMap<(TileCoord3, bool, TileFormat), Blob>>,
 }
+/// Adds a tile to a cache. If the cache is full, another tile is removed first.
+fn insert_bounded<K: Eq + std::hash::Hash + Clone>(cache: &RwLock<HashMap<K, Blob>>, key: K, tile: Blob, max: usize) {
+	let mut cache = cache.write().unwrap();
+	if cache.len() >= max {
+		if let Some(key) = cache.keys().next().cloned() {
+			cache.remove(&key);
+		}
+	}
+	cache.insert(key, tile);
+}
+
+/// Whether tiles of this mime type get smaller by compression. Images are already compressed.
+fn is_compressible(mime: &str) -> bool {
+	matches!(
+		mime,
+		"application/x-protobuf"
+			| "application/json"
+			| "application/geo+json"
+			| "application/topo+json"
+			| "image/svg+xml"
+	)
+}
+
+/// Answers with a tile in the compression the client accepts.
+/// Uncompressed vector and text tiles are only sent, if the client accepts neither brotli nor gzip.
+/// Raster tiles are sent uncompressed.
+pub fn respond_with_tile(
+	data: Blob, compression: Compression, mime: &str, accept: EnumSet<Compression>,
+) -> Response<Full<Bytes>> {
+	let compressible = is_compressible(mime);
+	if accept.contains(compression) && (compression != Compression::None || !compressible) {
+		return ok_data(data, &compression, mime);
+	}
+
+	// the client does not accept the stored compression or the tile is uncompressed, so (re)compress it
+	let data = decompress(data, &compression).unwrap();
+
+	if !compressible {
+		return ok_data(data, &Compression::None, mime);
+	}
+
+	if accept.contains(Compression::Brotli) {
+		return ok_data(compress_brotli_fast(data).unwrap(), &Compression::Brotli, mime);
+	}
+
+	if accept.contains(Compression::Gzip) {
+		return ok_data(compress_gzip(data).unwrap(), &Compression::Gzip, mime);
+	}
+
+	ok_data(data, &Compression::None, mime)
+}
+
 impl TileContainer {
 	pub fn from(reader: TileReaderBox) -> Box<TileContainer> {
 		let parameters = reader.get_parameters();
 		let compression = *parameters.get_tile_compression();
 
-		let tile_mime = match parameters.get_tile_format() {
-			TileFormat::BIN => "application/octet-stream",
-			TileFormat::PNG => "image/png",
-			TileFormat::JPG => "image/jpeg",
-			TileFormat::WEBP => "image/webp",
-			TileFormat::AVIF => "image/avif",
-			TileFormat::SVG => "image/svg+xml",
-			TileFormat::PBF => "application/x-protobuf",
-			TileFormat::GEOJSON => "application/geo+json",
-			TileFormat::TOPOJSON => "application/topo+json",
-			TileFormat::JSON => "application/json",
-		}
-		.to_string();
+		let tile_format = parameters.get_tile_format().clone();
+		let tile_mime = get_tile_mime(&tile_format).to_string();
 
 		Box::new(TileContainer {
 			reader,
+			tile_format,
 			tile_mime,
 			compression,
+			cache_max_age: None,
+			url: None,
+			filter: None,
+			max_overzoom: None,
+			retina_cache: RwLock::new(HashMap::new()),
+			converted_cache: RwLock::new(HashMap::new()),
 		})
 	}
-}
 
-#[async_trait]
-impl ServerSourceTrait for TileContainer {
-	fn get_name(&self) -> String {
-		self.reader.get_name().to_owned()
+	/// Remembers the file or url the container was opened from, so that it can be reloaded.
+	pub fn set_url(&mut self, url: &str) {
+		self.url = Some(url.to_owned());
 	}
-	fn get_info_as_json(&self) -> String {
-		let parameters = self.reader.get_parameters();
-		let bbox_pyramide = parameters.get_bbox_pyramide();
 
-		let tile_format = format!("{:?}", parameters.get_tile_format()).to_lowercase();
-		let tile_compression = format!("{:?}", parameters.get_tile_compression()).to_lowercase();
+	/// Lets clients and proxies cache successful responses for this many seconds.
+	pub fn set_cache_max_age(&mut self, seconds: u64) {
+		self.cache_max_age = Some(seconds);
+	}
 
-		format!(
-			"{{ \"container\":\"{}\", \"format\":\"{}\", \"compression\":\"{}\", \"zoom_min\":{}, \"zoom_max\":{}, \"bbox\":{:?} }}",
-			self.reader.get_container_name(),
-			tile_format,
-			tile_compression,
-			bbox_pyramide.get_zoom_min().unwrap(),
-			bbox_pyramide.get_zoom_max().unwrap(),
-			bbox_pyramide.get_geo_bbox(),
-		)
+	/// Lets clients select layers and fields of vector tiles, e.g. "?layers=water&fields=name".
+	/// Without an allowlist these query parameters are ignored.
+	pub fn set_filter(&mut self, filter: TileFilterConfig) {
+		self.filter = Some(filter);
+	}
+
+	/// Serves tiles up to this many zoom levels beyond the maximum zoom level of the container,
+	/// cut from the nearest ancestor tile and scaled up. At most `MAX_OVERZOOM` levels are used.
+	pub fn set_max_overzoom(&mut self, levels: u8) {
+		if levels > MAX_OVERZOOM {
+			log::warn!("overzoom of {levels} levels is limited to {MAX_OVERZOOM}");
+		}
+		self.max_overzoom = Some(levels.min(MAX_OVERZOOM));
+	}
+
+	/// Builds a tile above the maximum zoom level from its nearest ancestor. The tile is uncompressed.
+	async fn get_overzoomed_tile(&self, coord: &TileCoord3) -> Option<Blob> {
+		let max_overzoom = self.max_overzoom?;
+		let zoom_max = self.reader.get_parameters().get_bbox_pyramide().get_zoom_max()?;
+		if coord.z <= zoom_max {
+			return None;
+		}
+
+		for depth in (coord.z - zoom_max)..=max_overzoom.min(coord.z) {
+			let ancestor = TileCoord3::new(coord.x >> depth, coord.y >> depth, coord.z - depth);
+			let tile = match self.reader.get_tile_data(&ancestor).await {
+				Some(tile) => tile,
+				None => continue,
+			};
+
+			let mask = (1u64 << depth) - 1;
+			let (x, y) = (coord.x & mask, coord.y & mask);
+			let format = &self.tile_format;
+			let result = decompress(tile, &self.compression).and_then(|tile| match format {
+				TileFormat::PBF => crop_vector_tile(&tile, depth, x, y),
+				_ => decode_image(tile, format)
+					.and_then(|image| crop_descendant(&image, depth, x, y))
+					.and_then(|image| encode_image(&image, format)),
+			});
+
+			return match result {
+				Ok(tile) => Some(tile),
+				Err(err) => {
+					log::warn!("can not overzoom tile {ancestor:?} to {coord:?}: {err}");
+					None
+				}
+			};
+		}
+
+		None
+	}
+
+	/// Builds a raster tile of twice the size from the four tiles one zoom level below, e.g. for "5/3/2@2x.png".
+	/// If they don't exist, the tile itself is scaled up. The tile is uncompressed and in the format of the container.
+	async fn get_retina_tile(&self, coord: &TileCoord3) -> Option<Blob> {
+		if let Some(tile) = self.retina_cache.read().unwrap().get(coord) {
+			return Some(tile.clone());
+		}
+
+		let format = &self.tile_format;
+		let decode = |tile: Blob, compression: &Compression| {
+			decompress(tile, compression).and_then(|tile| decode_image(tile, format))
+		};
+
+		let mut children = Vec::new();
+		for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+			let child = TileCoord3::new(coord.x * 2 + dx, coord.y * 2 + dy, coord.z.checked_add(1)?);
+			match self.reader.get_tile_data(&child).await {
+				Some(tile) => children.push(tile),
+				None => break,
+			}
+		}
+
+		let image = if children.len() == 4 {
+			let images: Result<Vec<_>> = children
+				.into_iter()
+				.map(|tile| decode(tile, &self.compression))
+				.collect();
+			images.and_then(|images| stitch_images([&images[0], &images[1], &images[2], &images[3]]))
+		} else {
+			let (tile, compression) = match self.reader.get_tile_data(coord).await {
+				Some(tile) => (tile, self.compression),
+				None => (self.get_overzoomed_tile(coord).await?, Compression::None),
+			};
+			decode(tile, &compression).map(|image| double_size(&image))
+		};
+
+		let tile = match image.and_then(|image| encode_image(&image, format)) {
+			Ok(tile) => tile,
+			Err(err) => {
+				log::warn!("can not build @2x tile {coord:?}: {err}");
+				return None;
+			}
+		};
+
+		insert_bounded(&self.retina_cache, *coord, tile.clone(), MAX_RETINA_TILES);
+
+		Some(tile)
 	}
 
-	async fn get_data(&self, path: &[&str], accept: EnumSet<Compression>) -> Response<Full<Bytes>> {
+	async fn get_response(
+		&self, path: &[&str], accept: EnumSet<Compression>, accept_mime: Option<&str>, query: Option<&str>,
+	) -> Response<Full<Bytes>> {
 		if path.len() == 3 {
 			let z = path[0].parse::<u8>();
 			let x = path[1].parse::<u64>();
 			let y: String = path[2].chars().take_while(|c| c.is_numeric()).collect();
+			let retina = path[2][y.len()..].starts_with("@2x");
 			let y = y.parse::<u64>();
 
 			if x.is_err() || y.is_err() || z.is_err() {
 				return ok_not_found();
 			}
 
+			// a known extension determines the format, otherwise the "Accept" header
+			let extension_format = path[2]
+				.split_once('.')
+				.and_then(|(_, extension)| get_tile_format_by_extension(extension));
+			// without an extension the response depends on the "Accept" header, even if a request had none
+			let negotiated = extension_format.is_none();
+			let tile_format = match (extension_format, accept_mime) {
+				(Some(tile_format), _) => tile_format,
+				(None, Some(accept_mime)) => match negotiate_tile_format(accept_mime, &self.tile_format) {
+					Some(tile_format) => tile_format,
+					None => return ok_error(406, "Not Acceptable"),
+				},
+				(None, None) => self.tile_format.clone(),
+			};
+
+			if !DataConverter::can_convert_format(&self.tile_format, &tile_format) {
+				return ok_error(406, "Not Acceptable");
+			}
+
+			let filter = match (&self.filter, query) {
+				(Some(config), Some(query)) if tile_format == TileFormat::PBF => match config.parse_query(query) {
+					Ok(filter) => filter,
+					Err(err) => return ok_error(400, &err.to_string()),
+				},
+				_ => None,
+			};
+
 			let coord = TileCoord3::new(x.unwrap(), y.unwrap(), z.unwrap());
 
 			// get tile
-			let tile = self.reader.get_tile_data(&coord).await;
+			let (data, compression) = if retina {
+				if !matches!(self.tile_format, TileFormat::PNG | TileFormat::JPG | TileFormat::WEBP) {
+					return ok_not_found();
+				}
+				match self.get_retina_tile(&coord).await {
+					Some(data) => (data, Compression::None),
+					None => return ok_not_found(),
+				}
+			} else {
+				match self.reader.get_tile_data(&coord).await {
+					Some(data) => (data, self.compression),
+					None => match self.get_overzoomed_tile(&coord).await {
+						Some(data) => (data, Compression::None),
+						None => return ok_not_found(),
+					},
+				}
+			};
 
-			if tile.is_none() {
-				return ok_not_found();
-			}
-
-			let mut data = tile.unwrap();
+			let mut response = if let Some(filter) = filter {
+				match decompress(data, &compression).and_then(|data| filter.apply(&data)) {
+					Ok(data) => respond_with_tile(data, Compression::None, &self.tile_mime, accept),
+					Err(err) => {
+						log::warn!("can not filter tile {coord:?}: {err}");
+						return ok_error(500, "Internal Server Error");
+					}
+				}
+			} else if tile_format == self.tile_format {
+				respond_with_tile(data, compression, &self.tile_mime, accept)
+			} else {
+				// decoding and encoding images is expensive, so converted tiles are kept
+				let key = (coord, retina, tile_format.clone());
+				let cached = self.converted_cache.read().unwrap().get(&key).cloned();
+				let converted = match cached {
+					Some(tile) => Ok(tile),
+					None => {
+						let converter = DataConverter::new_tile_recompressor(
+							&self.tile_format,
+							&compression,
+							&tile_format,
+							&Compression::None,
+							false,
+						);
+						converter.run_at(data, &coord).inspect(|tile| {
+							insert_bounded(&self.converted_cache, key, tile.clone(), MAX_CONVERTED_TILES);
+						})
+					}
+				};
+				match converted {
+					Ok(data) => respond_with_tile(data, Compression::None, get_tile_mime(&tile_format), accept),
+					Err(err) => {
+						log::warn!("can not convert tile {coord:?} to {tile_format:?}: {err}");
+						return ok_error(406, "Not Acceptable");
+					}
+				}
+			};
 
-			if accept.contains(self.compression) {
-				return ok_data(data, &self.compression, &self.tile_mime);
+			if negotiated {
+				response
+					.headers_mut()
+					.insert(VARY, "accept-encoding, accept".parse().unwrap());
 			}
 
-			data = decompress(data, &self.compression).unwrap();
-			return ok_data(data, &Compression::None, &self.tile_mime);
+			return response;
 		} else if (path[0] == "meta.json") || (path[0] == "tiles.json") {
 			// get meta
 			let meta = self.reader.get_meta().await;
@@ -120,24 +436,412 @@ impl ServerSourceTrait for TileContainer {
 	}
 }
 
+#[async_trait]
+impl ServerSourceTrait for TileContainer {
+	fn get_name(&self) -> String {
+		self.reader.get_name().to_owned()
+	}
+	fn get_info_as_json(&self) -> String {
+		let parameters = self.reader.get_parameters();
+		let bbox_pyramide = parameters.get_bbox_pyramide();
+
+		let tile_format = format!("{:?}", parameters.get_tile_format()).to_lowercase();
+		let tile_compression = format!("{:?}", parameters.get_tile_compression()).to_lowercase();
+
+		format!(
+			"{{ \"container\":\"{}\", \"format\":\"{}\", \"compression\":\"{}\", \"zoom_min\":{}, \"zoom_max\":{}, \"bbox\":{:?} }}",
+			self.reader.get_container_name(),
+			tile_format,
+			tile_compression,
+			bbox_pyramide.get_zoom_min().unwrap(),
+			bbox_pyramide.get_zoom_max().unwrap(),
+			bbox_pyramide.get_geo_bbox(),
+		)
+	}
+
+	async fn get_data(
+		&self, path: &[&str], accept: EnumSet<Compression>, accept_mime: Option<&str>, query: Option<&str>,
+	) -> Response<Full<Bytes>> {
+		let mut response = self.get_response(path, accept, accept_mime, query).await;
+
+		if let Some(max_age) = self.cache_max_age {
+			if response.status() == 200 {
+				let value = format!("public, max-age={max_age}");
+				response.headers_mut().insert(CACHE_CONTROL, value.parse().unwrap());
+			}
+		}
+
+		response
+	}
+
+	fn get_reader_stats(&self) -> Option<TileReaderStats> {
+		Some(self.reader.get_stats())
+	}
+
+	fn get_parameters(&self) -> Option<TileReaderParameters> {
+		Some(self.reader.get_parameters().clone())
+	}
+
+	fn get_url(&self) -> Option<String> {
+		self.url.clone()
+	}
+
+	fn get_path(&self) -> Option<PathBuf> {
+		let url = self.url.as_ref()?;
+		if url.starts_with("http://") || url.starts_with("https://") {
+			return None;
+		}
+		Some(PathBuf::from(url))
+	}
+
+	async fn reload(&self) -> Result<Box<dyn ServerSourceTrait>> {
+		let url = match &self.url {
+			Some(url) => url.to_owned(),
+			None => return Err(Error::new(&format!("source {} can not be reloaded", self.get_name()))),
+		};
+
+		// readers panic on broken containers, so open the new one in its own task
+		let url_clone = url.clone();
+		let mut reader = tokio::spawn(async move { get_reader(&url_clone).await.map_err(|e| e.to_string()) })
+			.await
+			.map_err(|_| Error::new(&format!("container {url} is invalid")))?
+			.map_err(|e| Error::new(&format!("can not open container {url}: {e}")))?;
+
+		if reader.get_parameters().get_bbox_pyramide().is_empty() {
+			return Err(Error::new(&format!("container {url} contains no tiles")));
+		}
+
+		let flip = self.reader.get_parameters().get_vertical_flip();
+		reader.get_parameters_mut().set_vertical_flip(flip);
+
+		let mut container = TileContainer::from(reader);
+		container.cache_max_age = self.cache_max_age;
+		container.url = self.url.clone();
+		container.filter = self.filter.clone();
+		container.max_overzoom = self.max_overzoom;
+
+		Ok(container)
+	}
+}
+
 impl Debug for TileContainer {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.debug_struct("TileContainer")
 			.field("reader", &self.reader)
+			.field("tile_format", &self.tile_format)
 			.field("tile_mime", &self.tile_mime)
 			.field("compression", &self.compression)
+			.field("cache_max_age", &self.cache_max_age)
+			.field("url", &self.url)
 			.finish()
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use super::TileContainer;
-	use crate::containers::dummy::{ReaderProfile, TileReader};
+	use super::*;
+	use crate::containers::{
+		dummy::{ReaderProfile, TileReader},
+		tests::make_test_file,
+	};
+	use crate::shared::decode_vector_tile_layers;
+	use axum::{
+		body::HttpBody,
+		http::header::{CONTENT_ENCODING, CONTENT_TYPE},
+	};
+	use enumset::enum_set;
 
 	#[test]
 	fn tile_container_from() {
 		let reader = TileReader::new_dummy(ReaderProfile::PngFast, 8);
 		let _container = TileContainer::from(reader);
 	}
+
+	#[tokio::test]
+	async fn transcode_tiles() {
+		let file = make_test_file(TileFormat::PBF, Compression::Brotli, 3, "versatiles").await;
+		let reader = get_reader(file.to_str().unwrap()).await.unwrap();
+		let container = TileContainer::from(reader);
+
+		let reference = container.reader.get_tile_data(&TileCoord3::new(0, 0, 0)).await;
+		let reference = decompress(reference.unwrap(), &Compression::Brotli).unwrap();
+
+		async fn test(
+			container: &TileContainer, accept: EnumSet<Compression>, encoding: Option<&str>, compression: Compression,
+			reference: &Blob,
+		) {
+			let mut response = container
+				.get_data(&["0", "0", "0.pbf"], accept, Option::None, Option::None)
+				.await;
+			assert_eq!(response.status(), 200);
+			assert_eq!(response.headers().get(VARY).unwrap(), "accept-encoding");
+			assert_eq!(
+				response.headers().get(CONTENT_ENCODING).map(|v| v.to_str().unwrap()),
+				encoding
+			);
+			let data = Blob::from(response.data().await.unwrap().unwrap());
+			assert_eq!(&decompress(data, &compression).unwrap(), reference);
+		}
+
+		use Compression::*;
+		test(
+			&container,
+			enum_set!(None | Brotli | Gzip),
+			Some("br"),
+			Brotli,
+			&reference,
+		)
+		.await;
+		test(&container, enum_set!(None | Gzip), Some("gzip"), Gzip, &reference).await;
+		test(&container, enum_set!(Gzip), Some("gzip"), Gzip, &reference).await;
+		test(&container, enum_set!(None), Option::None, None, &reference).await;
+	}
+
+	#[tokio::test]
+	async fn compress_uncompressed_tiles() {
+		use Compression::*;
+		let tile = Blob::from("uncompressed tile");
+
+		let test = |accept: EnumSet<Compression>, compression: Compression| {
+			let mut response = respond_with_tile(tile.clone(), None, "application/x-protobuf", accept);
+			let tile = tile.clone();
+			async move {
+				let encoding = response
+					.headers()
+					.get(CONTENT_ENCODING)
+					.map(|v| v.to_str().unwrap().to_owned());
+				let data = Blob::from(response.data().await.unwrap().unwrap());
+				assert_eq!(decompress(data, &compression).unwrap(), tile);
+				encoding
+			}
+		};
+
+		// browsers accept identity, but get compressed tiles
+		assert_eq!(
+			test(enum_set!(None | Gzip | Brotli), Brotli).await.as_deref(),
+			Some("br")
+		);
+		assert_eq!(test(enum_set!(None | Gzip), Gzip).await.as_deref(), Some("gzip"));
+		assert_eq!(test(enum_set!(None), None).await, Option::None);
+
+		// raster tiles are already compressed
+		let response = respond_with_tile(tile.clone(), None, "image/png", enum_set!(None | Gzip | Brotli));
+		assert_eq!(response.headers().get(CONTENT_ENCODING), Option::None);
+		assert_eq!(Blob::from(response.into_body().data().await.unwrap().unwrap()), tile);
+	}
+
+	#[test]
+	fn test_negotiate_tile_format() {
+		use TileFormat::*;
+		let negotiate = |accept: &str, format: TileFormat| negotiate_tile_format(accept, &format);
+
+		assert_eq!(negotiate("*/*", PNG), Some(PNG));
+		assert_eq!(negotiate("", PNG), Some(PNG));
+		assert_eq!(negotiate("image/*", JPG), Some(JPG));
+		assert_eq!(negotiate("image/webp", PNG), Some(WEBP));
+		assert_eq!(negotiate("image/webp,image/png;q=0.5", PNG), Some(PNG));
+		assert_eq!(negotiate("image/png;q=0", PNG), None);
+		assert_eq!(negotiate("image/png;q=0,image/*;q=0.8", PNG), Some(WEBP));
+		assert_eq!(negotiate("image/jpeg;q=0.9,image/webp;q=0.5", PNG), Some(JPG));
+		assert_eq!(negotiate("image/avif", PNG), None);
+		assert_eq!(negotiate("image/png", PBF), None);
+		assert_eq!(negotiate("application/x-protobuf", PBF), Some(PBF));
+		assert_eq!(negotiate("application/geo+json", PBF), Some(GEOJSON));
+
+		assert_eq!(get_tile_format_by_extension("JPEG"), Some(JPG));
+		assert_eq!(get_tile_format_by_extension("mvt"), Some(PBF));
+		assert_eq!(get_tile_format_by_extension("txt"), Option::None);
+	}
+
+	#[tokio::test]
+	async fn convert_raster_tiles() {
+		let mut container = TileContainer::from(TileReader::new_dummy(ReaderProfile::PngFast, 8));
+		container.set_cache_max_age(3600);
+
+		let get = |y: &'static str, accept_mime: Option<&'static str>| {
+			let container = &container;
+			async move {
+				let mut response = container
+					.get_data(&["3", "2", y], enum_set!(Compression::None), accept_mime, Option::None)
+					.await;
+				let data = response.data().await.map(|data| data.unwrap().to_vec());
+				(response, data)
+			}
+		};
+
+		let (response, data) = get("1.jpg", Option::None).await;
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers()[CONTENT_TYPE], "image/jpeg");
+		assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=3600");
+		assert_eq!(response.headers()[VARY], "accept-encoding");
+		assert_eq!(&data.unwrap()[0..3], b"\xFF\xD8\xFF");
+
+		// the extension wins over the "Accept" header
+		let (response, data) = get("1.png", Some("image/webp")).await;
+		assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+		assert_eq!(&data.unwrap()[0..4], b"\x89PNG");
+
+		let (response, _) = get("1", Some("image/jpeg")).await;
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers()[CONTENT_TYPE], "image/jpeg");
+		assert_eq!(response.headers()[VARY], "accept-encoding, accept");
+
+		let (response, data) = get("1.webp", Option::None).await;
+		assert_eq!(response.headers()[CONTENT_TYPE], "image/webp");
+		let data = data.unwrap();
+		assert_eq!(&data[8..12], b"WEBP");
+
+		// converted tiles are cached
+		assert_eq!(container.converted_cache.read().unwrap().len(), 2);
+		assert_eq!(get("1.webp", Option::None).await.1.unwrap(), data);
+		assert_eq!(container.converted_cache.read().unwrap().len(), 2);
+
+		let (response, _) = get("1", Option::None).await;
+		assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+		assert_eq!(response.headers()[VARY], "accept-encoding, accept");
+
+		assert_eq!(get("1.avif", Option::None).await.0.status(), 406);
+		assert_eq!(get("1", Some("image/avif")).await.0.status(), 406);
+
+		let container = TileContainer::from(TileReader::new_dummy(ReaderProfile::PbfFast, 8));
+		let response = container
+			.get_data(
+				&["3", "2", "1.png"],
+				enum_set!(Compression::None),
+				Option::None,
+				Option::None,
+			)
+			.await;
+		assert_eq!(response.status(), 406);
+	}
+
+	#[tokio::test]
+	async fn overzoom_tiles() {
+		async fn get(container: &TileContainer, path: &[&str]) -> (u16, Blob) {
+			let mut response = container
+				.get_data(path, enum_set!(Compression::None), Option::None, Option::None)
+				.await;
+			let data = response
+				.data()
+				.await
+				.map(|data| data.unwrap().to_vec())
+				.unwrap_or_default();
+			(response.status().as_u16(), Blob::from(data))
+		}
+
+		for format in [TileFormat::PNG, TileFormat::PBF] {
+			let file = make_test_file(format.clone(), Compression::Gzip, 3, "versatiles").await;
+			let mut container = TileContainer::from(get_reader(file.to_str().unwrap()).await.unwrap());
+			assert_eq!(get(&container, &["5", "21", "22"]).await.0, 404);
+
+			container.set_max_overzoom(2);
+			let (status, data) = get(&container, &["5", "21", "22"]).await;
+			assert_eq!(status, 200);
+			match format {
+				TileFormat::PNG => assert_eq!(decode_image(data, &format).unwrap().width(), 256),
+				_ => assert!(!decode_vector_tile_layers(&data).unwrap().is_empty()),
+			}
+
+			assert_eq!(get(&container, &["4", "10", "11"]).await.0, 200);
+			assert_eq!(get(&container, &["4", "16", "11"]).await.0, 404);
+			assert_eq!(get(&container, &["6", "42", "44"]).await.0, 404);
+
+			// shifting by 64 or more bits would overflow
+			container.set_max_overzoom(u8::MAX);
+			assert_eq!(container.max_overzoom, Some(MAX_OVERZOOM));
+			assert_eq!(get(&container, &["99", "0", "0"]).await.0, 404);
+		}
+	}
+
+	#[tokio::test]
+	async fn convert_vector_tiles_to_geojson() {
+		let container = TileContainer::from(TileReader::new_dummy(ReaderProfile::PbfFast, 8));
+		for (y, accept_mime) in [("1.geojson", Option::None), ("1", Some("application/geo+json"))] {
+			let mut response = container
+				.get_data(&["3", "2", y], enum_set!(Compression::None), accept_mime, Option::None)
+				.await;
+			assert_eq!(response.status(), 200);
+			assert_eq!(response.headers()[CONTENT_TYPE], "application/geo+json");
+			let data = Blob::from(response.data().await.unwrap().unwrap());
+			assert!(data
+				.as_str()
+				.starts_with("{\"type\":\"FeatureCollection\",\"features\":[{"));
+			assert!(data.as_str().contains("\"layer\":\"ocean\""));
+		}
+
+		let container = TileContainer::from(TileReader::new_dummy(ReaderProfile::PngFast, 8));
+		let response = container
+			.get_data(
+				&["3", "2", "1.geojson"],
+				enum_set!(Compression::None),
+				Option::None,
+				Option::None,
+			)
+			.await;
+		assert_eq!(response.status(), 406);
+	}
+
+	#[tokio::test]
+	async fn retina_tiles() {
+		async fn get_size(container: &TileContainer, y: &str) -> Option<(u32, u32)> {
+			let mut response = container
+				.get_data(&["3", "2", y], enum_set!(Compression::None), Option::None, Option::None)
+				.await;
+			if response.status() != 200 {
+				return Option::None;
+			}
+			let format = get_tile_format_by_extension(y.split_once('.').unwrap().1).unwrap();
+			let image = decode_image(Blob::from(response.data().await.unwrap().unwrap()), &format).unwrap();
+			Some((image.width(), image.height()))
+		}
+
+		// stitched from four children
+		let container = TileContainer::from(TileReader::new_dummy(ReaderProfile::PngFast, 8));
+		assert_eq!(get_size(&container, "1.png").await, Some((256, 256)));
+		assert_eq!(get_size(&container, "1@2x.png").await, Some((512, 512)));
+		assert_eq!(container.retina_cache.read().unwrap().len(), 1);
+		assert_eq!(get_size(&container, "1@2x.png").await, Some((512, 512)));
+		assert_eq!(get_size(&container, "1@2x.webp").await, Some((512, 512)));
+		assert_eq!(container.retina_cache.read().unwrap().len(), 1);
+
+		// scaled up at the maximum zoom level
+		let file = make_test_file(TileFormat::PNG, Compression::None, 3, "versatiles").await;
+		let container = TileContainer::from(get_reader(file.to_str().unwrap()).await.unwrap());
+		assert_eq!(get_size(&container, "1@2x.png").await, Some((512, 512)));
+
+		let container = TileContainer::from(TileReader::new_dummy(ReaderProfile::PbfFast, 8));
+		assert_eq!(get_size(&container, "1@2x.pbf").await, Option::None);
+	}
+
+	#[tokio::test]
+	async fn filter_vector_tiles() {
+		let mut container = TileContainer::from(TileReader::new_dummy(ReaderProfile::PbfFast, 8));
+
+		async fn get_layers(container: &TileContainer, query: Option<&str>) -> (u16, Vec<String>) {
+			let mut response = container
+				.get_data(&["3", "2", "1"], enum_set!(Compression::Gzip), Option::None, query)
+				.await;
+			let status = response.status().as_u16();
+			if status != 200 {
+				return (status, Vec::new());
+			}
+			let data = Blob::from(response.data().await.unwrap().unwrap());
+			let data = decompress(data, &Compression::Gzip).unwrap();
+			let layers = decode_vector_tile_layers(&data).unwrap();
+			(status, layers.into_iter().map(|layer| layer.name).collect())
+		}
+
+		let ocean = vec!["ocean".to_owned()];
+		assert_eq!(get_layers(&container, Option::None).await, (200, ocean.clone()));
+
+		// without an allowlist the query is ignored
+		assert_eq!(get_layers(&container, Some("layers=")).await, (200, ocean.clone()));
+
+		container.set_filter(TileFilterConfig::new(&["ocean"], &["name"]));
+		assert_eq!(get_layers(&container, Some("layers=")).await, (200, vec![]));
+		assert_eq!(get_layers(&container, Some("layers=ocean")).await, (200, ocean.clone()));
+		assert_eq!(get_layers(&container, Some("fields=name")).await, (200, ocean.clone()));
+		assert_eq!(get_layers(&container, Some("api_key=abc")).await, (200, ocean));
+		assert_eq!(get_layers(&container, Some("layers=water")).await.0, 400);
+	}
 }
@@ -48,7 +48,9 @@ impl ServerSourceTrait for Folder {
 		"{\"type\":\"folder\"}".to_owned()
 	}
 
-	async fn get_data(&self, path: &[&str], accept: EnumSet<Compression>) -> Response<Full<Bytes>> {
+	async fn get_data(
+		&self, path: &[&str], accept: EnumSet<Compression>, _accept_mime: Option<&str>, _query: Option<&str>,
+	) -> Response<Full<Bytes>> {
 		let mut local_path = self.folder.clone();
 		local_path.push(PathBuf::from(path.join("/")));
 
@@ -115,13 +117,15 @@ mod tests {
 			assert_eq!(folder.get_info_as_json(), "{\"type\":\"folder\"}");
 
 			let mut result = folder
-				.get_data(&["recipes", "Queijo.txt"], enum_set!(Compression::None))
+				.get_data(&["recipes", "Queijo.txt"], enum_set!(Compression::None), None, None)
 				.await;
 			assert_eq!(result.status(), StatusCode::NOT_FOUND);
 			let result = result.data().await.unwrap().unwrap();
 			assert_eq!(format!("{:?}", result), "b\"Not Found\"");
 
-			let mut result = folder.get_data(&["berlin.mbtiles"], enum_set!(Compression::None)).await;
+			let mut result = folder
+				.get_data(&["berlin.mbtiles"], enum_set!(Compression::None), None, None)
+				.await;
 			assert_eq!(result.status(), StatusCode::OK);
 			let result = result.data().await.unwrap().unwrap();
 			assert_eq!(result.len(), 26533888);
@@ -1,5 +1,6 @@
+mod composite;
 mod container;
 mod folder;
 mod tar_file;
 
-pub use self::{container::*, folder::*, tar_file::*};
+pub use self::{composite::*, container::*, folder::*, tar_file::*};
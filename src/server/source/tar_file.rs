@@ -134,7 +134,9 @@ impl ServerSourceTrait for TarFile {
 		"{\"type\":\"tar\"}".to_owned()
 	}
 
-	async fn get_data(&self, path: &[&str], accept: EnumSet<Compression>) -> Response<Full<Bytes>> {
+	async fn get_data(
+		&self, path: &[&str], accept: EnumSet<Compression>, _accept_mime: Option<&str>, _query: Option<&str>,
+	) -> Response<Full<Bytes>> {
 		let entry_name = path.join("/");
 		let entry_option = self.lookup.get(&entry_name);
 		if entry_option.is_none() {
@@ -207,7 +209,7 @@ mod tests {
 	use hyper::header::CONTENT_ENCODING;
 
 	async fn get_as_string(container: &Box<TarFile>, path: &[&str], compression: &Compression) -> String {
-		let mut resp = container.get_data(path, enum_set!(compression)).await;
+		let mut resp = container.get_data(path, enum_set!(compression), None, None).await;
 		let encoding = resp.headers().get(CONTENT_ENCODING);
 
 		let content_compression = match encoding {
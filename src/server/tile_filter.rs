@@ -0,0 +1,125 @@
+use super::decode_percent;
+use crate::shared::{filter_vector_tile, Blob, Error, Result};
+use serde::Deserialize;
+
+/// Layers and fields of vector tiles, that clients may select with "?layers=water,roads&fields=name,class".
+/// Requests for anything else are rejected, so that filtering can't be used to create unlimited variants of a tile.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TileFilterConfig {
+	pub layers: Vec<String>,
+	pub fields: Vec<String>,
+}
+
+/// The layers and fields a request asks for, `None` keeps all of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TileFilter {
+	layers: Option<Vec<String>>,
+	fields: Option<Vec<String>>,
+}
+
+impl TileFilterConfig {
+	pub fn new(layers: &[&str], fields: &[&str]) -> TileFilterConfig {
+		TileFilterConfig {
+			layers: layers.iter().map(|layer| layer.to_string()).collect(),
+			fields: fields.iter().map(|field| field.to_string()).collect(),
+		}
+	}
+
+	pub fn validate(&self) -> Result<()> {
+		if self.layers.is_empty() && self.fields.is_empty() {
+			return Err(Error::new("tile filter must allow at least one layer or field"));
+		}
+		Ok(())
+	}
+
+	/// Reads "layers" and "fields" from a query string. Returns None if the query does not filter.
+	pub fn parse_query(&self, query: &str) -> Result<Option<TileFilter>> {
+		let mut filter = TileFilter {
+			layers: None,
+			fields: None,
+		};
+
+		for pair in query.split('&') {
+			let (key, value) = match pair.split_once('=') {
+				Some(pair) => pair,
+				None => continue,
+			};
+			let (allowed, names) = match key {
+				"layers" => (&self.layers, &mut filter.layers),
+				"fields" => (&self.fields, &mut filter.fields),
+				_ => continue,
+			};
+
+			let mut list: Vec<String> = Vec::new();
+			for name in decode_percent(value).split(',').filter(|name| !name.is_empty()) {
+				if !allowed.iter().any(|allowed| allowed == name) {
+					return Err(Error::new(&format!("{key} \"{name}\" can not be selected")));
+				}
+				if !list.iter().any(|other| other == name) {
+					list.push(name.to_owned());
+				}
+			}
+			*names = Some(list);
+		}
+
+		if filter.layers.is_none() && filter.fields.is_none() {
+			return Ok(None);
+		}
+		Ok(Some(filter))
+	}
+}
+
+impl TileFilter {
+	/// Removes the unrequested layers and fields from an uncompressed vector tile.
+	pub fn apply(&self, tile: &Blob) -> Result<Blob> {
+		filter_vector_tile(tile, self.layers.as_deref(), self.fields.as_deref())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_query() {
+		let config = TileFilterConfig::new(&["water", "roads"], &["name", "class"]);
+		let filter = |query: &str| config.parse_query(query).map_err(|e| e.to_string());
+		let list = |names: &[&str]| Some(names.iter().map(|name| name.to_string()).collect::<Vec<String>>());
+
+		assert_eq!(filter("").unwrap(), None);
+		assert_eq!(filter("api_key=abc").unwrap(), None);
+		assert_eq!(
+			filter("layers=water,roads,water&fields=name").unwrap(),
+			Some(TileFilter {
+				layers: list(&["water", "roads"]),
+				fields: list(&["name"]),
+			})
+		);
+		assert_eq!(
+			filter("fields=name%2Cclass").unwrap(),
+			Some(TileFilter {
+				layers: None,
+				fields: list(&["name", "class"]),
+			})
+		);
+		assert_eq!(
+			filter("layers=").unwrap(),
+			Some(TileFilter {
+				layers: list(&[]),
+				fields: None,
+			})
+		);
+		assert_eq!(
+			filter("layers=buildings").unwrap_err(),
+			"layers \"buildings\" can not be selected"
+		);
+		assert_eq!(
+			filter("fields=water").unwrap_err(),
+			"fields \"water\" can not be selected"
+		);
+
+		assert!(TileFilterConfig::default().validate().is_err());
+		assert!(config.validate().is_ok());
+	}
+}
@@ -1,31 +1,176 @@
-use super::ServerSourceTrait;
-use crate::shared::{Blob, Compression};
+use super::{
+	decode_percent, get_client_ip, get_token, guess_container_mime, make_capabilities, make_collection,
+	make_collections, make_conformance, make_exception, make_landing_page, make_preview_html, make_preview_style,
+	make_tile_matrix_set, make_tile_matrix_sets, make_tileset, make_tilesets, parse_kvp_request, redact_token,
+	respond_with_range, serve_raw_file, Access, AccessLog, AccessLogConfig, AccessLogEntry, AccessTokens, CorsConfig,
+	IpRange, Metrics, OgcCollection, RateLimitConfig, RateLimiter, ServerSourceTrait, SourceConfig, SourceDirConfig,
+	TlsConfig, WmtsException, WmtsLayer, WmtsRequest, OGC_TILE_MATRIX_SET, PREVIEW_ASSETS, WMTS_TILE_MATRIX_SET,
+};
+#[cfg(unix)]
+use super::{UnixAccept, UnixSocketConfig};
+use crate::shared::{Blob, Compression, Error, Result};
 use axum::{
-	body::{Bytes, Full},
-	extract::{Path, State},
+	body::{Bytes, Full, HttpBody},
+	extract::{ConnectInfo, Path, State},
 	http::{
-		header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE},
-		HeaderMap, Uri,
+		header::{
+			ACCEPT, ACCEPT_ENCODING, AUTHORIZATION, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, HOST,
+			IF_RANGE, RANGE, RETRY_AFTER, VARY, WWW_AUTHENTICATE,
+		},
+		HeaderMap, Request, StatusCode, Uri,
 	},
-	response::Response,
-	routing::get,
-	Router, Server,
+	middleware::{self, Next},
+	response::{IntoResponse, Response},
+	routing::{delete, get, post},
+	Extension, Router,
 };
+use axum_server::Handle;
 use enumset::{enum_set, EnumSet};
-use std::sync::Arc;
-use tokio::sync::oneshot::Sender;
+use std::{
+	collections::HashMap,
+	net::SocketAddr,
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, RwLock,
+	},
+	time::{Instant, SystemTime},
+};
+use tokio::{
+	task::JoinHandle,
+	time::{interval, sleep, Duration},
+};
 
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+type SourceBox = Arc<Box<dyn ServerSourceTrait>>;
+type TileSourceList = Arc<RwLock<Vec<TileSource>>>;
+
+#[derive(Clone)]
 struct TileSource {
 	prefix: String,
-	source: Arc<Box<dyn ServerSourceTrait>>,
+	source: SourceBox,
+	/// only accessible with an access token
+	private: bool,
+	/// limits the requests per client to this source
+	rate_limiter: Option<Arc<RateLimiter>>,
+	/// serves the container file at "/files/{file_name}"
+	file_name: Option<String>,
+}
+
+impl TileSource {
+	fn new(url_prefix: &str, source: Box<dyn ServerSourceTrait>) -> TileSource {
+		TileSource {
+			prefix: clean_prefix(url_prefix),
+			source: Arc::new(source),
+			private: false,
+			rate_limiter: None,
+			file_name: None,
+		}
+	}
+}
+
+/// Tile sources and static content served for requests to some host names.
+#[derive(Clone)]
+struct HostGroup {
+	hostnames: Vec<String>,
+	tile_sources: TileSourceList,
+	static_sources: Vec<SourceBox>,
+}
+
+impl HostGroup {
+	fn new(hostnames: Vec<String>) -> HostGroup {
+		HostGroup {
+			hostnames,
+			tile_sources: Arc::new(RwLock::new(Vec::new())),
+			static_sources: Vec::new(),
+		}
+	}
+}
+
+/// Selects the host group of a request by its "Host" header.
+#[derive(Clone)]
+struct HostRouter {
+	default: HostGroup,
+	hosts: Arc<Vec<HostGroup>>,
+}
+
+impl HostRouter {
+	fn get(&self, headers: &HeaderMap, uri: &Uri) -> &HostGroup {
+		let hostname = match get_hostname(headers, uri) {
+			Some(hostname) => hostname,
+			None => return &self.default,
+		};
+		self
+			.hosts
+			.iter()
+			.find(|group| group.hostnames.contains(&hostname))
+			.unwrap_or(&self.default)
+	}
+
+	fn get_tile_sources(&self, headers: &HeaderMap, uri: &Uri) -> TileSourceList {
+		self.get(headers, uri).tile_sources.clone()
+	}
+
+	/// Whether any tile source of any host group matches.
+	fn any_tile_source(&self, predicate: impl Fn(&TileSource) -> bool) -> bool {
+		std::iter::once(&self.default)
+			.chain(self.hosts.iter())
+			.any(|group| group.tile_sources.read().unwrap().iter().any(&predicate))
+	}
+}
+
+/// Stops the running server.
+enum ServerHandle {
+	Tcp(Handle),
+	#[cfg(unix)]
+	Unix(UnixSocketConfig, tokio::sync::oneshot::Sender<()>),
+}
+
+#[derive(Clone)]
+struct RequestLimits {
+	host_router: HostRouter,
+	rate_limiter: Option<Arc<RateLimiter>>,
+	trusted_proxies: Arc<Vec<IpRange>>,
+}
+
+#[derive(Clone)]
+struct RequestObserver {
+	host_router: HostRouter,
+	metrics: Option<Arc<Metrics>>,
+	access_log: Option<Arc<AccessLog>>,
 }
 
 pub struct TileServer {
 	ip: String,
 	port: u16,
-	tile_sources: Vec<TileSource>,
-	static_sources: Vec<Arc<Box<dyn ServerSourceTrait>>>,
-	exit_signal: Option<Sender<()>>,
+	tile_sources: TileSourceList,
+	static_sources: Vec<SourceBox>,
+	/// sources and static content of other host names, unmatched hosts are served from the lists above
+	hosts: Vec<HostGroup>,
+	/// directories watched for new containers
+	source_dirs: Vec<SourceDirConfig>,
+	cors: CorsConfig,
+	tls: Option<TlsConfig>,
+	watch: bool,
+	admin_token: Option<String>,
+	metrics: Option<Arc<Metrics>>,
+	preview: bool,
+	wmts: bool,
+	ogc_api: bool,
+	base_url: Option<String>,
+	access_log: Option<AccessLogConfig>,
+	access_tokens: AccessTokens,
+	rate_limiter: Option<Arc<RateLimiter>>,
+	trusted_proxies: Vec<IpRange>,
+	#[cfg(unix)]
+	socket: Option<UnixSocketConfig>,
+	draining: Arc<AtomicBool>,
+	/// time between the start of a shutdown and closing the listener
+	drain_period: Duration,
+	handle: Option<ServerHandle>,
+	server_task: Option<JoinHandle<()>>,
+	tasks: Vec<JoinHandle<()>>,
 }
 
 impl TileServer {
@@ -33,36 +178,118 @@ impl TileServer {
 		TileServer {
 			ip: ip.to_owned(),
 			port,
-			tile_sources: Vec::new(),
+			tile_sources: Arc::new(RwLock::new(Vec::new())),
 			static_sources: Vec::new(),
-			exit_signal: None,
+			hosts: Vec::new(),
+			source_dirs: Vec::new(),
+			cors: CorsConfig::default(),
+			tls: None,
+			watch: false,
+			admin_token: None,
+			metrics: None,
+			preview: false,
+			wmts: false,
+			ogc_api: false,
+			base_url: None,
+			access_log: None,
+			access_tokens: AccessTokens::new(),
+			rate_limiter: None,
+			trusted_proxies: Vec::new(),
+			#[cfg(unix)]
+			socket: None,
+			draining: Arc::new(AtomicBool::new(false)),
+			drain_period: Duration::ZERO,
+			handle: None,
+			server_task: None,
+			tasks: Vec::new(),
 		}
 	}
 
 	pub fn add_tile_source(&mut self, url_prefix: &str, tile_source: Box<dyn ServerSourceTrait>) {
 		log::debug!("add source: prefix='{}', source={:?}", url_prefix, tile_source);
 
-		let mut prefix = url_prefix.trim().to_owned();
-		if !prefix.starts_with('/') {
-			prefix = "/".to_owned() + &prefix;
+		if let Err(err) = insert_tile_source(&self.tile_sources, TileSource::new(url_prefix, tile_source)) {
+			panic!("{}", err);
 		}
-		if !prefix.ends_with('/') {
-			prefix += "/";
+	}
+
+	/// Watches a directory for new containers, if watching is enabled, and serves them at "/tiles/{name}/".
+	/// The containers already in the directory have to be added with `add_tile_source`.
+	pub fn add_source_dir(&mut self, source_dir: SourceDirConfig) {
+		log::debug!("add source dir: {:?}", source_dir);
+		self.source_dirs.push(source_dir);
+	}
+
+	/// Makes a tile source only accessible with an access token, see `add_access_token`.
+	pub fn set_private(&mut self, url_prefix: &str) {
+		self.update_tile_source(url_prefix, |tile_source| tile_source.private = true);
+	}
+
+	/// Limits the requests per client to a tile source, in addition to the global rate limit.
+	pub fn set_source_rate_limit(&mut self, url_prefix: &str, rate_limit: &RateLimitConfig) {
+		let rate_limiter = Arc::new(RateLimiter::new(rate_limit));
+		self.update_tile_source(url_prefix, |tile_source| tile_source.rate_limiter = Some(rate_limiter));
+	}
+
+	/// Serves the container file of a tile source at "/files/{file_name}", e.g. for clients
+	/// that read the container themselves via range requests.
+	pub fn expose_file(&mut self, url_prefix: &str, file_name: &str) {
+		if let Err(err) = check_file_name(&self.tile_sources.read().unwrap(), file_name) {
+			panic!("{}", err);
 		}
+		let file_name = file_name.to_owned();
+		self.update_tile_source(url_prefix, |tile_source| {
+			assert!(
+				tile_source.source.get_url().is_some(),
+				"source '{}' has no container file",
+				tile_source.prefix
+			);
+			tile_source.file_name = Some(file_name);
+		});
+	}
 
-		for other_tile_source in self.tile_sources.iter() {
-			if other_tile_source.prefix.starts_with(&prefix) || prefix.starts_with(&other_tile_source.prefix) {
-				panic!(
-					"multiple sources with the prefix '{}' and '{}' are defined",
-					prefix, other_tile_source.prefix
-				);
-			};
+	fn update_tile_source(&mut self, url_prefix: &str, update: impl FnOnce(&mut TileSource)) {
+		let prefix = clean_prefix(url_prefix);
+		let mut tile_sources = self.tile_sources.write().unwrap();
+		match tile_sources.iter_mut().find(|tile_source| tile_source.prefix == prefix) {
+			Some(tile_source) => update(tile_source),
+			None => panic!("no source with the prefix '{}'", prefix),
 		}
+	}
 
-		self.tile_sources.push(TileSource {
-			prefix,
-			source: Arc::new(tile_source),
-		});
+	/// Listens on a unix domain socket instead of ip and port.
+	/// Requests via the socket count as requests from 127.0.0.1.
+	#[cfg(unix)]
+	pub fn set_socket(&mut self, socket: UnixSocketConfig) {
+		log::debug!("set socket: {:?}", socket);
+		self.socket = Some(socket);
+	}
+
+	/// Limits the requests per client to the whole server.
+	pub fn set_rate_limit(&mut self, rate_limit: &RateLimitConfig) {
+		log::debug!("set rate limit: {:?}", rate_limit);
+		self.rate_limiter = Some(Arc::new(RateLimiter::new(rate_limit)));
+	}
+
+	/// Requests from these proxies are identified by the header "X-Forwarded-For" when rate limiting.
+	pub fn set_trusted_proxies(&mut self, trusted_proxies: &[IpRange]) {
+		self.trusted_proxies = trusted_proxies.to_vec();
+	}
+
+	/// Grants a token access to the private sources with these prefixes. The prefix "*" grants access to all sources.
+	/// Tokens can be sent as "Authorization: Bearer <token>", "X-API-Key: <token>" or "?api_key=<token>".
+	pub fn add_access_token(&mut self, token: &str, url_prefixes: &[String]) {
+		let prefixes: Vec<String> = url_prefixes
+			.iter()
+			.map(|prefix| {
+				if prefix == "*" {
+					prefix.to_owned()
+				} else {
+					clean_prefix(prefix)
+				}
+			})
+			.collect();
+		self.access_tokens.add(token, &prefixes);
 	}
 
 	pub fn add_static_source(&mut self, source: Box<dyn ServerSourceTrait>) {
@@ -70,235 +297,1843 @@ impl TileServer {
 		self.static_sources.push(Arc::new(source));
 	}
 
-	pub async fn start(&mut self) {
-		if self.exit_signal.is_some() {
+	/// Adds a group of host names with their own tile sources, static content and "/api/tiles.json".
+	/// Requests to other host names are answered with the sources added by `add_tile_source` and `add_static_source`.
+	pub fn add_host(&mut self, hostnames: &[&str]) {
+		log::debug!("add host: hostnames={:?}", hostnames);
+
+		let hostnames: Vec<String> = hostnames.iter().map(|hostname| clean_hostname(hostname)).collect();
+		for hostname in hostnames.iter() {
+			assert!(!hostname.is_empty(), "host name must not be empty");
+			assert!(
+				!self.hosts.iter().any(|group| group.hostnames.contains(hostname)),
+				"host '{}' is defined multiple times",
+				hostname
+			);
+		}
+		self.hosts.push(HostGroup::new(hostnames));
+	}
+
+	pub fn add_host_tile_source(&mut self, hostname: &str, url_prefix: &str, tile_source: Box<dyn ServerSourceTrait>) {
+		log::debug!(
+			"add source: host='{}', prefix='{}', source={:?}",
+			hostname,
+			url_prefix,
+			tile_source
+		);
+
+		let group = self.get_host_group(hostname);
+		if let Err(err) = insert_tile_source(&group.tile_sources, TileSource::new(url_prefix, tile_source)) {
+			panic!("{}", err);
+		}
+	}
+
+	pub fn add_host_static_source(&mut self, hostname: &str, source: Box<dyn ServerSourceTrait>) {
+		log::debug!("set static: host='{}', source={:?}", hostname, source);
+		self.get_host_group(hostname).static_sources.push(Arc::new(source));
+	}
+
+	fn get_host_group(&mut self, hostname: &str) -> &mut HostGroup {
+		let hostname = clean_hostname(hostname);
+		match self.hosts.iter_mut().find(|group| group.hostnames.contains(&hostname)) {
+			Some(group) => group,
+			None => panic!("no host '{}', add it with add_host", hostname),
+		}
+	}
+
+	fn get_host_router(&self) -> HostRouter {
+		HostRouter {
+			default: HostGroup {
+				hostnames: Vec::new(),
+				tile_sources: self.tile_sources.clone(),
+				static_sources: self.static_sources.clone(),
+			},
+			hosts: Arc::new(self.hosts.clone()),
+		}
+	}
+
+	fn get_base_url(&self) -> BaseUrl {
+		// IPv6 addresses are written in brackets, like in the "Host" header
+		let ip = if self.ip.contains(':') {
+			format!("[{}]", self.ip)
+		} else {
+			self.ip.clone()
+		};
+		let mut hostnames = vec![clean_hostname(&ip)];
+		hostnames.extend(self.hosts.iter().flat_map(|group| group.hostnames.iter().cloned()));
+		BaseUrl {
+			configured: self.base_url.clone(),
+			scheme: if self.tls.is_some() { "https" } else { "http" },
+			address: format!("{ip}:{}", self.port),
+			hostnames,
+		}
+	}
+
+	/// The tile sources of the default group and of all hosts.
+	fn get_all_tile_sources(&self) -> Vec<TileSourceList> {
+		let mut lists = vec![self.tile_sources.clone()];
+		lists.extend(self.hosts.iter().map(|group| group.tile_sources.clone()));
+		lists
+	}
+
+	pub fn set_cors(&mut self, cors: CorsConfig) {
+		log::debug!("set cors: {:?}", cors);
+		self.cors = cors;
+	}
+
+	/// Enables HTTPS (including HTTP/2). Certificate and key are reloaded automatically when the files change.
+	pub fn set_tls(&mut self, tls: TlsConfig) {
+		log::debug!("set tls: {:?}", tls);
+		self.tls = Some(tls);
+	}
+
+	/// Reloads tile sources automatically when their files are modified.
+	pub fn set_watch(&mut self, watch: bool) {
+		self.watch = watch;
+	}
+
+	/// Enables the admin API:
+	/// - "POST /api/admin/reload" reloads all sources
+	/// - "POST /api/admin/sources" adds a source, e.g. {"path":"data/osm.versatiles","name":"osm"}
+	/// - "DELETE /api/admin/sources/{prefix}" removes a source
+	///
+	/// Requests must send the header "Authorization: Bearer <token>".
+	pub fn set_admin_token(&mut self, token: &str) {
+		self.admin_token = Some(token.to_owned());
+	}
+
+	/// Enables the endpoint "/metrics" in the Prometheus text format.
+	pub fn set_metrics(&mut self, enabled: bool) {
+		self.metrics = if enabled { Some(Arc::new(Metrics::new())) } else { None };
+	}
+
+	/// Enables a map preview of every tile source at "/preview/{name}", where name is the last part of the prefix.
+	pub fn set_preview(&mut self, enabled: bool) {
+		self.preview = enabled;
+	}
+
+	/// Enables an OGC WMTS 1.0 endpoint for all tile sources: the capabilities at "/wmts/1.0.0/WMTSCapabilities.xml",
+	/// RESTful tiles at "/wmts/1.0.0/{name}/default/GoogleMapsCompatible/{z}/{row}/{col}.{ext}"
+	/// and KVP requests at "/wmts".
+	pub fn set_wmts(&mut self, enabled: bool) {
+		self.wmts = enabled;
+	}
+
+	/// Enables OGC API - Tiles at "/ogc": a landing page, "/ogc/collections" with a collection per tile source,
+	/// their tilesets at "/ogc/collections/{name}/tiles" and the tile matrix sets at "/ogc/tileMatrixSets".
+	pub fn set_ogc_api(&mut self, enabled: bool) {
+		self.ogc_api = enabled;
+	}
+
+	/// Sets the public url of the server, e.g. "https://tiles.example.org", used for the absolute urls
+	/// of WMTS and OGC API. Otherwise the "Host" header is used, if it matches a host or the listen address.
+	pub fn set_base_url(&mut self, base_url: &str) {
+		self.base_url = Some(base_url.trim().trim_end_matches('/').to_owned());
+	}
+
+	/// Keeps accepting requests for this long after `shutdown` was called, while "/status" and "/api/status.json"
+	/// respond with 503, so that load balancers can take the server out of rotation first.
+	pub fn set_drain_period(&mut self, drain_period: Duration) {
+		self.drain_period = drain_period;
+	}
+
+	/// Logs every request to stdout or a file. Log files are reopened when the server receives SIGHUP.
+	pub fn set_access_log(&mut self, access_log: AccessLogConfig) {
+		log::debug!("set access log: {:?}", access_log);
+		self.access_log = Some(access_log);
+	}
+
+	/// Starts listening. Fails if the address, the socket, the TLS certificate or the access log can't be used.
+	pub async fn start(&mut self) -> Result<()> {
+		if self.handle.is_some() {
 			self.stop().await
 		}
 
 		log::debug!("starting server");
 
+		self.draining.store(false, Ordering::Relaxed);
+
 		// Initialize App
-		let mut app = Router::new().route("/status", get(|| async { "ready!" }));
+		let mut app = Router::new()
+			.route(
+				"/status",
+				get(|State(draining): State<Arc<AtomicBool>>| async move {
+					if draining.load(Ordering::Relaxed) {
+						ok_error(503, "draining!")
+					} else {
+						ok_data(Blob::from("ready!"), &Compression::None, "text/plain")
+					}
+				}),
+			)
+			.with_state(self.draining.clone());
 
-		app = self.add_tile_sources_to_app(app);
 		app = self.add_api_to_app(app);
-		app = self.add_static_sources_to_app(app);
+		app = self.add_admin_api_to_app(app);
+		app = self.add_files_to_app(app);
+		app = self.add_preview_to_app(app);
+		app = self.add_wmts_to_app(app);
+		app = self.add_ogc_api_to_app(app);
+		app = self.add_sources_to_app(app);
+		app = self.add_metrics_to_app(app);
+		app = self.add_rate_limit_to_app(app);
 
-		let addr = format!("{}:{}", self.ip, self.port);
-		println!("server starts listening on {}", addr);
+		let access_log = match &self.access_log {
+			Some(config) => Some(Arc::new(
+				AccessLog::new(config).map_err(|err| Error::new(&format!("can not open access log: {err}")))?,
+			)),
+			None => None,
+		};
+		app = self.add_observer_to_app(app, access_log.clone());
 
-		let server = Server::bind(&addr.parse().unwrap()).serve(app.into_make_service());
+		if self.cors.is_enabled() {
+			app = app.layer(self.cors.get_layer()?);
+		}
 
-		let (tx, rx) = tokio::sync::oneshot::channel::<()>();
-		let graceful = server.with_graceful_shutdown(async {
-			rx.await.ok();
-		});
+		#[cfg(unix)]
+		let app = match self.socket.clone() {
+			Some(socket) => {
+				self.listen_on_socket(app, socket)?;
+				None
+			}
+			None => Some(app),
+		};
+		#[cfg(not(unix))]
+		let app = Some(app);
 
-		self.exit_signal = Some(tx);
+		if let Some(app) = app {
+			self.listen_on_address(app).await?;
+		}
 
-		tokio::spawn(async move {
-			if let Err(e) = graceful.await {
-				eprintln!("server error: {}", e);
+		self.tasks.push(self.reload_on_signal(access_log));
+		if self.watch {
+			for tile_sources in self.get_all_tile_sources() {
+				self.tasks.push(reload_on_modification(tile_sources));
 			}
-		});
+			if !self.source_dirs.is_empty() {
+				self.tasks.push(mount_new_containers(
+					self.tile_sources.clone(),
+					self.source_dirs.clone(),
+				));
+			}
+		}
+
+		Ok(())
 	}
 
-	pub async fn stop(&mut self) {
-		if self.exit_signal.is_none() {
-			return;
+	async fn listen_on_address(&mut self, app: Router) -> Result<()> {
+		let addr: SocketAddr = format!("{}:{}", self.ip, self.port)
+			.parse()
+			.map_err(|_| Error::new(&format!("invalid address {}:{}", self.ip, self.port)))?;
+		let handle = Handle::new();
+		let service = app.into_make_service_with_connect_info::<SocketAddr>();
+
+		// bind before spawning the server, so that errors like "address in use" can be returned
+		let listener = tokio::net::TcpListener::bind(addr)
+			.await
+			.and_then(|listener| listener.into_std())
+			.map_err(|err| Error::new(&format!("can not listen on {addr}: {err}")))?;
+
+		if let Some(tls) = &self.tls {
+			let rustls_config = tls
+				.load()
+				.await
+				.map_err(|err| Error::new(&format!("can not load TLS certificate: {err}")))?;
+			self.tasks.push(tls.watch(rustls_config.clone()));
+
+			println!("server starts listening on https://{}", addr);
+
+			let server = axum_server::from_tcp_rustls(listener, rustls_config).handle(handle.clone());
+			self.server_task = Some(tokio::spawn(async move {
+				if let Err(e) = server.serve(service).await {
+					eprintln!("server error: {}", e);
+				}
+			}));
+		} else {
+			println!("server starts listening on {}", addr);
+
+			let server = axum_server::from_tcp(listener).handle(handle.clone());
+			self.server_task = Some(tokio::spawn(async move {
+				if let Err(e) = server.serve(service).await {
+					eprintln!("server error: {}", e);
+				}
+			}));
 		}
 
-		log::debug!("stopping server");
+		if handle.listening().await.is_none() {
+			return Err(Error::new(&format!("can not listen on {addr}")));
+		}
+
+		self.handle = Some(ServerHandle::Tcp(handle));
+		Ok(())
+	}
+
+	#[cfg(unix)]
+	fn listen_on_socket(&mut self, app: Router, socket: UnixSocketConfig) -> Result<()> {
+		let listener = socket.bind()?;
+		println!(
+			"server starts listening on unix:{}",
+			socket.get_path().to_str().unwrap()
+		);
 
-		self.exit_signal.take().unwrap().send(()).unwrap();
+		// requests via unix socket count as local requests
+		let app = app.layer(Extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0)))));
+		let (sender, receiver) = tokio::sync::oneshot::channel::<()>();
+		let server = hyper::Server::builder(UnixAccept(listener))
+			.serve(app.into_make_service())
+			.with_graceful_shutdown(async {
+				receiver.await.ok();
+			});
+		self.server_task = Some(tokio::spawn(async move {
+			if let Err(e) = server.await {
+				eprintln!("server error: {}", e);
+			}
+		}));
+		self.handle = Some(ServerHandle::Unix(socket, sender));
+		Ok(())
 	}
 
-	fn add_tile_sources_to_app(&self, mut app: Router) -> Router {
-		for tile_source in self.tile_sources.iter() {
-			let route = tile_source.prefix.to_owned() + "*path";
-			let source = tile_source.source.clone();
+	/// Stops the server immediately, requests in flight are cancelled. See `shutdown` for a graceful stop.
+	pub async fn stop(&mut self) {
+		if self.handle.is_none() {
+			return;
+		}
 
-			let tile_app = Router::new().route(&route, get(serve_tile)).with_state(source);
-			app = app.merge(tile_app);
+		log::debug!("stopping server");
 
-			async fn serve_tile(
-				Path(path): Path<String>, headers: HeaderMap, State(source): State<Arc<Box<dyn ServerSourceTrait>>>,
-			) -> Response<Full<Bytes>> {
-				let sub_path: Vec<&str> = path.split('/').collect();
-				source.get_data(&sub_path, get_encoding(headers)).await
+		match self.handle.take().unwrap() {
+			ServerHandle::Tcp(handle) => {
+				// wait until the listener is closed, so that the server can be started again right away
+				handle.shutdown();
+				if let Some(server_task) = self.server_task.take() {
+					let _ = server_task.await;
+				}
+			}
+			#[cfg(unix)]
+			ServerHandle::Unix(socket, sender) => {
+				let _ = sender.send(());
+				socket.remove();
 			}
 		}
+		self.server_task = None;
 
-		app
+		for task in self.tasks.drain(..) {
+			task.abort();
+		}
 	}
 
-	fn add_static_sources_to_app(&self, app: Router) -> Router {
-		let sources = self.static_sources.clone();
+	/// Responds to "/status" and "/api/status.json" with "draining" for the drain period, see `set_drain_period`.
+	/// Then stops accepting new connections and waits until all active requests are finished.
+	/// Requests that are still running after the timeout are cancelled.
+	pub async fn shutdown(&mut self, timeout: Duration) {
+		let handle = match self.handle.take() {
+			Some(handle) => handle,
+			None => return,
+		};
 
-		let static_app = Router::new().fallback(get(serve_static)).with_state(sources);
+		log::info!("shutting down server");
 
-		return app.merge(static_app);
+		self.draining.store(true, Ordering::Relaxed);
+		if !self.drain_period.is_zero() {
+			log::info!("draining for {:?}", self.drain_period);
+			sleep(self.drain_period).await;
+		}
 
-		async fn serve_static(
-			uri: Uri, headers: HeaderMap, State(sources): State<Vec<Arc<Box<dyn ServerSourceTrait>>>>,
-		) -> Response<Full<Bytes>> {
-			let mut path_vec: Vec<&str> = uri.path().split('/').skip(1).collect();
+		match handle {
+			ServerHandle::Tcp(handle) => {
+				log::info!("draining {} connections", handle.connection_count());
+				handle.graceful_shutdown(Some(timeout));
 
-			if let Some(last) = path_vec.last_mut() {
-				if last.is_empty() {
-					*last = "index.html";
+				if let Some(server_task) = self.server_task.take() {
+					let _ = server_task.await;
 				}
 			}
+			#[cfg(unix)]
+			ServerHandle::Unix(socket, sender) => {
+				let _ = sender.send(());
 
-			let path_slice = path_vec.as_slice();
-			let encoding_set = get_encoding(headers);
-
-			for source in sources.iter() {
-				let response = source.get_data(path_slice, encoding_set).await;
-				if response.status() == 200 {
-					return response;
+				if let Some(mut server_task) = self.server_task.take() {
+					if tokio::time::timeout(timeout, &mut server_task).await.is_err() {
+						server_task.abort();
+					}
 				}
+				socket.remove();
 			}
+		}
 
-			ok_not_found()
+		for task in self.tasks.drain(..) {
+			task.abort();
 		}
+
+		log::info!("server stopped");
 	}
 
-	fn add_api_to_app(&self, app: Router) -> Router {
-		let mut tile_sources_json_lines: Vec<String> = Vec::new();
-		for tile_source in self.tile_sources.iter() {
-			tile_sources_json_lines.push(format!(
-				"{{ \"url\":\"{}\", \"name\":\"{}\", \"info\":{} }}",
-				tile_source.prefix,
-				tile_source.source.get_name(),
-				tile_source.source.get_info_as_json()
-			));
+	/// Reopens all tile sources. Returns the prefixes of the reloaded sources and all errors.
+	pub async fn reload(&self) -> (Vec<String>, Vec<String>) {
+		let mut reloaded: Vec<String> = Vec::new();
+		let mut errors: Vec<String> = Vec::new();
+		for tile_sources in self.get_all_tile_sources() {
+			let (mut list_reloaded, mut list_errors) = reload_tile_sources(&tile_sources, |_| true).await;
+			reloaded.append(&mut list_reloaded);
+			errors.append(&mut list_errors);
 		}
-		let tile_sources_json: String = "[\n\t".to_owned() + &tile_sources_json_lines.join(",\n\t") + "\n]";
+		(reloaded, errors)
+	}
 
-		let api_app = Router::new()
-			.route(
-				"/api/status.json",
-				get(|| async {
-					ok_data(
-						Blob::from("{\"status\":\"ready\"}"),
-						&Compression::None,
-						"application/json",
-					)
-				}),
-			)
-			.route(
-				"/api/tiles.json",
-				get(|| async move { ok_data(Blob::from(&tile_sources_json), &Compression::None, "application/json") }),
-			);
+	/// Reloads all sources and reopens the access log on SIGHUP.
+	fn reload_on_signal(&self, access_log: Option<Arc<AccessLog>>) -> JoinHandle<()> {
+		let all_tile_sources = self.get_all_tile_sources();
 
-		app.merge(api_app)
-	}
+		tokio::spawn(async move {
+			#[cfg(unix)]
+			{
+				use tokio::signal::unix::{signal, SignalKind};
+				let mut hangup = signal(SignalKind::hangup()).unwrap();
+				while hangup.recv().await.is_some() {
+					log::info!("received SIGHUP, reloading sources");
+					for tile_sources in all_tile_sources.iter() {
+						reload_tile_sources(tile_sources, |_| true).await;
+					}
 
-	pub fn iter_url_mapping(&self) -> impl Iterator<Item = (String, String)> + '_ {
-		self
-			.tile_sources
-			.iter()
-			.map(|tile_source| (tile_source.prefix.to_owned(), tile_source.source.get_name()))
+					if let Some(access_log) = &access_log {
+						if let Err(err) = access_log.reopen() {
+							log::error!("can not reopen access log: {err}");
+						}
+					}
+				}
+			}
+			#[cfg(not(unix))]
+			let _ = (all_tile_sources, access_log);
+		})
 	}
-}
 
-pub fn ok_not_found() -> Response<Full<Bytes>> {
-	Response::builder().status(404).body(Full::from("Not Found")).unwrap()
-}
+	fn add_files_to_app(&self, app: Router) -> Router {
+		// sources with exposed files can also be added via the admin api
+		let host_router = self.get_host_router();
+		let has_files = host_router.any_tile_source(|tile_source| tile_source.file_name.is_some());
+		if !has_files && self.admin_token.is_none() {
+			return app;
+		}
 
-pub fn ok_data(data: Blob, compression: &Compression, mime: &str) -> Response<Full<Bytes>> {
-	let mut response = Response::builder()
-		.status(200)
-		.header(CONTENT_TYPE, mime)
-		.header(CACHE_CONTROL, "public");
+		let files_app = Router::new().route("/files/:file_name", get(serve_file)).with_state((
+			host_router,
+			Arc::new(self.access_tokens.clone()),
+			reqwest::Client::new(),
+		));
 
-	match compression {
-		Compression::None => {}
-		Compression::Gzip => response = response.header(CONTENT_ENCODING, "gzip"),
-		Compression::Brotli => response = response.header(CONTENT_ENCODING, "br"),
-	}
+		return app.merge(files_app);
 
-	response.body(Full::from(data.as_vec())).unwrap()
-}
+		/// The client is shared by all requests for remote files, so that it can reuse connections.
+		async fn serve_file(
+			uri: Uri, headers: HeaderMap, Path(file_name): Path<String>,
+			State((host_router, access_tokens, client)): State<(HostRouter, Arc<AccessTokens>, reqwest::Client)>,
+		) -> Response {
+			let tile_source = host_router
+				.get_tile_sources(&headers, &uri)
+				.read()
+				.unwrap()
+				.iter()
+				.find(|tile_source| tile_source.file_name.as_ref() == Some(&file_name))
+				.cloned();
 
-pub fn guess_mime(path: &std::path::Path) -> String {
-	let mime = mime_guess::from_path(path).first_or_octet_stream();
-	return mime.essence_str().to_owned();
-}
+			let tile_source = match tile_source {
+				Some(tile_source) => tile_source,
+				None => return ok_not_found().into_response(),
+			};
 
-fn get_encoding(headers: HeaderMap) -> EnumSet<Compression> {
-	let mut encoding_set: EnumSet<Compression> = enum_set!(Compression::None);
-	let encoding_option = headers.get(ACCEPT_ENCODING);
-	if let Some(encoding) = encoding_option {
-		let encoding_string = encoding.to_str().unwrap_or("");
+			let token = get_token(&headers, &uri);
+			if let Some(response) = deny_access(&tile_source, &access_tokens, token.as_deref()) {
+				return response.into_response();
+			}
 
-		if encoding_string.contains("gzip") {
-			encoding_set.insert(Compression::Gzip);
-		}
-		if encoding_string.contains("br") {
-			encoding_set.insert(Compression::Brotli);
+			match tile_source.source.get_url() {
+				Some(url) => serve_raw_file(&client, &url, guess_container_mime(&file_name), &headers).await,
+				None => ok_not_found().into_response(),
+			}
 		}
 	}
-	encoding_set
-}
 
-#[cfg(test)]
-mod tests {
-	use super::{get_encoding, guess_mime, TileServer};
-	use crate::{
-		containers::dummy,
-		server::source::TileContainer,
-		shared::Compression::{self, *},
-	};
-	use axum::http::{header::ACCEPT_ENCODING, HeaderMap};
-	use enumset::{enum_set, EnumSet};
-	use std::path::Path;
+	fn add_preview_to_app(&self, app: Router) -> Router {
+		if !self.preview {
+			return app;
+		}
 
-	const IP: &str = "127.0.0.1";
-	const PORT: u16 = 3000;
+		let preview_app = Router::new()
+			.route("/preview/assets/:file", get(serve_asset))
+			.route("/preview/:name", get(serve_page))
+			.route("/preview/:name/style.json", get(serve_style))
+			.with_state((self.get_host_router(), Arc::new(self.access_tokens.clone())));
 
-	#[test]
-	fn test_get_encoding() {
-		let test = |encoding: &str, comp0: EnumSet<Compression>| {
-			let mut map = HeaderMap::new();
-			if encoding != "NONE" {
-				map.insert(ACCEPT_ENCODING, encoding.parse().unwrap());
+		return app.merge(preview_app);
+
+		async fn serve_asset(Path(file): Path<String>) -> Response<Full<Bytes>> {
+			match PREVIEW_ASSETS.iter().find(|(name, _, _)| name == &file) {
+				Some((_, mime, data)) => ok_data(Blob::from(data.to_vec()), &Compression::None, mime),
+				None => ok_not_found(),
 			}
-			let comp = get_encoding(map);
-			assert_eq!(comp, comp0);
-		};
+		}
 
-		test("NONE", enum_set!(None));
-		test("", enum_set!(None));
-		test("*", enum_set!(None));
-		test("br", enum_set!(None | Brotli));
-		test("br;q=1.0, gzip;q=0.8, *;q=0.1", enum_set!(None | Brotli | Gzip));
-		test("compress", enum_set!(None));
-		test("compress, gzip", enum_set!(None | Gzip));
-		test("compress;q=0.5, gzip;q=1.0", enum_set!(None | Gzip));
-		test("deflate", enum_set!(None));
-		test("deflate, gzip;q=1.0, *;q=0.5", enum_set!(None | Gzip));
-		test("gzip", enum_set!(None | Gzip));
-		test("gzip, compress, br", enum_set!(None | Brotli | Gzip));
-		test(
-			"gzip, deflate, br;q=1.0, identity;q=0.5, *;q=0.25",
-			enum_set!(None | Brotli | Gzip),
-		);
-		test("gzip;q=1.0, identity; q=0.5, *;q=0", enum_set!(None | Gzip));
-		test("identity", enum_set!(None));
-	}
+		async fn serve_page(
+			uri: Uri, headers: HeaderMap, Path(name): Path<String>,
+			State((host_router, access_tokens)): State<(HostRouter, Arc<AccessTokens>)>,
+		) -> Response<Full<Bytes>> {
+			let tile_sources = host_router.get_tile_sources(&headers, &uri);
+			let tile_source = match find_preview_source(&tile_sources, &name) {
+				Some(tile_source) => tile_source,
+				None => return ok_not_found(),
+			};
+			let token = get_token(&headers, &uri);
+			if let Some(response) = deny_access(&tile_source, &access_tokens, token.as_deref()) {
+				return response;
+			}
 
-	#[test]
-	fn test_guess_mime() {
-		let test = |path: &str, mime: &str| {
-			assert_eq!(guess_mime(Path::new(path)), mime);
-		};
+			let html = make_preview_html(&name, &tile_source.source.get_info_as_json());
+			ok_data(Blob::from(html), &Compression::None, "text/html")
+		}
 
-		test("fluffy.css", "text/css");
-		test("fluffy.gif", "image/gif");
-		test("fluffy.htm", "text/html");
-		test("fluffy.html", "text/html");
+		async fn serve_style(
+			uri: Uri, headers: HeaderMap, Path(name): Path<String>,
+			State((host_router, access_tokens)): State<(HostRouter, Arc<AccessTokens>)>,
+		) -> Response<Full<Bytes>> {
+			let tile_sources = host_router.get_tile_sources(&headers, &uri);
+			let tile_source = match find_preview_source(&tile_sources, &name) {
+				Some(tile_source) => tile_source,
+				None => return ok_not_found(),
+			};
+			let token = get_token(&headers, &uri);
+			if let Some(response) = deny_access(&tile_source, &access_tokens, token.as_deref()) {
+				return response;
+			}
+
+			let response = tile_source
+				.source
+				.get_data(&["meta.json"], enum_set!(Compression::None), None, None)
+				.await;
+			let meta = match response.status() {
+				StatusCode::OK => hyper::body::to_bytes(response.into_body()).await.unwrap_or_default(),
+				_ => Bytes::new(),
+			};
+
+			let style = make_preview_style(&tile_source.prefix, &tile_source.source.get_info_as_json(), &meta);
+			ok_data(Blob::from(style), &Compression::None, "application/json")
+		}
+
+		fn find_preview_source(tile_sources: &TileSourceList, name: &str) -> Option<TileSource> {
+			tile_sources
+				.read()
+				.unwrap()
+				.iter()
+				.find(|tile_source| get_source_id(&tile_source.prefix) == name)
+				.cloned()
+		}
+	}
+
+	fn add_wmts_to_app(&self, app: Router) -> Router {
+		if !self.wmts {
+			return app;
+		}
+
+		let wmts_app = Router::new()
+			.route("/wmts", get(serve_kvp))
+			.route("/wmts/1.0.0/WMTSCapabilities.xml", get(serve_capabilities))
+			.route(
+				"/wmts/1.0.0/:layer/:style/:tile_matrix_set/:z/:row/:col",
+				get(serve_restful),
+			)
+			.with_state((
+				self.get_host_router(),
+				Arc::new(self.access_tokens.clone()),
+				Arc::new(self.get_base_url()),
+			));
+
+		return app.merge(wmts_app);
+
+		type WmtsState = (HostRouter, Arc<AccessTokens>, Arc<BaseUrl>);
+
+		async fn serve_kvp(uri: Uri, headers: HeaderMap, State(state): State<WmtsState>) -> Response<Full<Bytes>> {
+			match parse_kvp_request(uri.query().unwrap_or("")) {
+				Ok(WmtsRequest::GetCapabilities) => capabilities(&uri, &headers, &state),
+				Ok(WmtsRequest::GetTile {
+					layer,
+					tile_matrix_set,
+					z,
+					row,
+					col,
+				}) => tile(&uri, headers, &state, &layer, &tile_matrix_set, z, row, col).await,
+				Err(exception) => exception_response(exception),
+			}
+		}
+
+		async fn serve_capabilities(
+			uri: Uri, headers: HeaderMap, State(state): State<WmtsState>,
+		) -> Response<Full<Bytes>> {
+			capabilities(&uri, &headers, &state)
+		}
+
+		async fn serve_restful(
+			uri: Uri, headers: HeaderMap,
+			Path((layer, _style, tile_matrix_set, z, row, col)): Path<(String, String, String, String, String, String)>,
+			State(state): State<WmtsState>,
+		) -> Response<Full<Bytes>> {
+			// the column is followed by the file extension
+			let col: String = col.chars().take_while(|c| c.is_ascii_digit()).collect();
+			match (z.parse::<u8>(), row.parse::<u64>(), col.parse::<u64>()) {
+				(Ok(z), Ok(row), Ok(col)) => tile(&uri, headers, &state, &layer, &tile_matrix_set, z, row, col).await,
+				_ => ok_not_found(),
+			}
+		}
+
+		fn capabilities(uri: &Uri, headers: &HeaderMap, state: &WmtsState) -> Response<Full<Bytes>> {
+			let (host_router, access_tokens, base_url) = state;
+			let token = get_token(headers, uri);
+
+			// private sources are only listed if the token grants access to them
+			let layers: Vec<WmtsLayer> = host_router
+				.get_tile_sources(headers, uri)
+				.read()
+				.unwrap()
+				.iter()
+				.filter(|tile_source| deny_access(tile_source, access_tokens, token.as_deref()).is_none())
+				.filter_map(|tile_source| {
+					Some(WmtsLayer {
+						id: get_source_id(&tile_source.prefix).to_owned(),
+						title: tile_source.source.get_name().to_owned(),
+						parameters: tile_source.source.get_parameters()?,
+					})
+				})
+				.collect();
+
+			let xml = make_capabilities(&base_url.get(headers), &layers);
+			ok_data(Blob::from(xml), &Compression::None, "application/xml")
+		}
+
+		#[allow(clippy::too_many_arguments)]
+		async fn tile(
+			uri: &Uri, headers: HeaderMap, state: &WmtsState, layer: &str, tile_matrix_set: &str, z: u8, row: u64,
+			col: u64,
+		) -> Response<Full<Bytes>> {
+			let (host_router, access_tokens, _) = state;
+
+			let tile_source = host_router
+				.get_tile_sources(&headers, uri)
+				.read()
+				.unwrap()
+				.iter()
+				.find(|tile_source| get_source_id(&tile_source.prefix) == layer)
+				.cloned();
+			let tile_source = match tile_source {
+				Some(tile_source) => tile_source,
+				None => return exception_response(WmtsException::new("InvalidParameterValue", "LAYER")),
+			};
+			if tile_matrix_set != WMTS_TILE_MATRIX_SET {
+				return exception_response(WmtsException::new("InvalidParameterValue", "TILEMATRIXSET"));
+			}
+
+			let token = get_token(&headers, uri);
+			if let Some(response) = deny_access(&tile_source, access_tokens, token.as_deref()) {
+				return response;
+			}
+
+			let (z, x, y) = (z.to_string(), col.to_string(), row.to_string());
+			tile_source
+				.source
+				.get_data(&[&z, &x, &y], get_encoding(headers), None, None)
+				.await
+		}
+
+		fn exception_response(exception: WmtsException) -> Response<Full<Bytes>> {
+			Response::builder()
+				.status(400)
+				.header(CONTENT_TYPE, "application/xml")
+				.body(Full::from(exception.to_xml()))
+				.unwrap()
+		}
+	}
+
+	fn add_ogc_api_to_app(&self, app: Router) -> Router {
+		if !self.ogc_api {
+			return app;
+		}
+
+		let ogc_app = Router::new()
+			.route("/ogc", get(serve_landing_page))
+			.route("/ogc/", get(serve_landing_page))
+			.route("/ogc/conformance", get(serve_conformance))
+			.route("/ogc/collections", get(serve_collections))
+			.route("/ogc/collections/:id", get(serve_collection))
+			.route("/ogc/collections/:id/tiles", get(serve_tilesets))
+			.route("/ogc/collections/:id/tiles/:tile_matrix_set", get(serve_tileset))
+			.route(
+				"/ogc/collections/:id/tiles/:tile_matrix_set/:z/:row/:col",
+				get(serve_tile),
+			)
+			.route("/ogc/tileMatrixSets", get(serve_tile_matrix_sets))
+			.route("/ogc/tileMatrixSets/:tile_matrix_set", get(serve_tile_matrix_set))
+			.with_state((
+				self.get_host_router(),
+				Arc::new(self.access_tokens.clone()),
+				Arc::new(self.get_base_url()),
+			));
+
+		return app.merge(ogc_app);
+
+		type OgcState = (HostRouter, Arc<AccessTokens>, Arc<BaseUrl>);
+
+		fn get_root_url(headers: &HeaderMap, state: &OgcState) -> String {
+			state.2.get(headers) + "/ogc"
+		}
+
+		async fn serve_landing_page(headers: HeaderMap, State(state): State<OgcState>) -> Response<Full<Bytes>> {
+			ok_json(make_landing_page(&get_root_url(&headers, &state)))
+		}
+
+		async fn serve_conformance() -> Response<Full<Bytes>> {
+			ok_json(make_conformance())
+		}
+
+		async fn serve_collections(uri: Uri, headers: HeaderMap, State(state): State<OgcState>) -> Response<Full<Bytes>> {
+			let (host_router, access_tokens, _) = &state;
+			let token = get_token(&headers, &uri);
+
+			// private sources are only listed if the token grants access to them
+			let collections: Vec<OgcCollection> = host_router
+				.get_tile_sources(&headers, &uri)
+				.read()
+				.unwrap()
+				.iter()
+				.filter(|tile_source| deny_access(tile_source, access_tokens, token.as_deref()).is_none())
+				.filter_map(get_collection)
+				.collect();
+
+			ok_json(make_collections(&get_root_url(&headers, &state), &collections))
+		}
+
+		async fn serve_collection(
+			uri: Uri, headers: HeaderMap, Path(id): Path<String>, State(state): State<OgcState>,
+		) -> Response<Full<Bytes>> {
+			serve_document(&uri, &headers, &state, &id, make_collection)
+		}
+
+		async fn serve_tilesets(
+			uri: Uri, headers: HeaderMap, Path(id): Path<String>, State(state): State<OgcState>,
+		) -> Response<Full<Bytes>> {
+			serve_document(&uri, &headers, &state, &id, make_tilesets)
+		}
+
+		async fn serve_tileset(
+			uri: Uri, headers: HeaderMap, Path((id, tile_matrix_set)): Path<(String, String)>,
+			State(state): State<OgcState>,
+		) -> Response<Full<Bytes>> {
+			if tile_matrix_set != OGC_TILE_MATRIX_SET {
+				return not_found(&format!("unknown tile matrix set \"{tile_matrix_set}\""));
+			}
+			serve_document(&uri, &headers, &state, &id, make_tileset)
+		}
+
+		async fn serve_tile(
+			uri: Uri, headers: HeaderMap,
+			Path((id, tile_matrix_set, z, row, col)): Path<(String, String, String, String, String)>,
+			State((host_router, access_tokens, _)): State<OgcState>,
+		) -> Response<Full<Bytes>> {
+			if tile_matrix_set != OGC_TILE_MATRIX_SET {
+				return not_found(&format!("unknown tile matrix set \"{tile_matrix_set}\""));
+			}
+
+			let tile_sources = host_router.get_tile_sources(&headers, &uri);
+			let tile_source = match find_tile_source(&tile_sources, &id) {
+				Some(tile_source) => tile_source,
+				None => return not_found(&format!("unknown collection \"{id}\"")),
+			};
+			let token = get_token(&headers, &uri);
+			if let Some(response) = deny_access(&tile_source, &access_tokens, token.as_deref()) {
+				return response;
+			}
+
+			let accept_mime = headers
+				.get(ACCEPT)
+				.and_then(|value| value.to_str().ok())
+				.map(str::to_owned);
+			tile_source
+				.source
+				.get_data(&[&z, &col, &row], get_encoding(headers), accept_mime.as_deref(), None)
+				.await
+		}
+
+		async fn serve_tile_matrix_sets(headers: HeaderMap, State(state): State<OgcState>) -> Response<Full<Bytes>> {
+			ok_json(make_tile_matrix_sets(&get_root_url(&headers, &state)))
+		}
+
+		async fn serve_tile_matrix_set(Path(tile_matrix_set): Path<String>) -> Response<Full<Bytes>> {
+			if tile_matrix_set != OGC_TILE_MATRIX_SET {
+				return not_found(&format!("unknown tile matrix set \"{tile_matrix_set}\""));
+			}
+			ok_json(make_tile_matrix_set())
+		}
+
+		fn find_tile_source(tile_sources: &TileSourceList, id: &str) -> Option<TileSource> {
+			tile_sources
+				.read()
+				.unwrap()
+				.iter()
+				.find(|tile_source| get_source_id(&tile_source.prefix) == id)
+				.cloned()
+		}
+
+		/// Builds a document for an accessible collection.
+		fn serve_document(
+			uri: &Uri, headers: &HeaderMap, state: &OgcState, id: &str, make: fn(&str, &OgcCollection) -> String,
+		) -> Response<Full<Bytes>> {
+			let (host_router, access_tokens, _) = state;
+			let tile_sources = host_router.get_tile_sources(headers, uri);
+			let tile_source = match find_tile_source(&tile_sources, id) {
+				Some(tile_source) => tile_source,
+				None => return not_found(&format!("unknown collection \"{id}\"")),
+			};
+			let token = get_token(headers, uri);
+			if let Some(response) = deny_access(&tile_source, access_tokens, token.as_deref()) {
+				return response;
+			}
+			match get_collection(&tile_source) {
+				Some(collection) => ok_json(make(&get_root_url(headers, state), &collection)),
+				None => not_found(&format!("collection \"{id}\" has no tiles")),
+			}
+		}
+
+		fn get_collection(tile_source: &TileSource) -> Option<OgcCollection> {
+			Some(OgcCollection {
+				id: get_source_id(&tile_source.prefix).to_owned(),
+				title: tile_source.source.get_name(),
+				parameters: tile_source.source.get_parameters()?,
+			})
+		}
+
+		fn ok_json(json: String) -> Response<Full<Bytes>> {
+			ok_data(Blob::from(json), &Compression::None, "application/json")
+		}
+
+		fn not_found(description: &str) -> Response<Full<Bytes>> {
+			Response::builder()
+				.status(404)
+				.header(CONTENT_TYPE, "application/json")
+				.body(Full::from(make_exception("NotFound", description)))
+				.unwrap()
+		}
+	}
+
+	fn add_sources_to_app(&self, app: Router) -> Router {
+		let state = (self.get_host_router(), Arc::new(self.access_tokens.clone()));
+
+		let sources_app = Router::new().fallback(get(serve_sources)).with_state(state);
+
+		return app.merge(sources_app);
+
+		async fn serve_sources(
+			uri: Uri, headers: HeaderMap, State((host_router, access_tokens)): State<(HostRouter, Arc<AccessTokens>)>,
+		) -> Response<Full<Bytes>> {
+			let HostGroup {
+				tile_sources,
+				static_sources,
+				..
+			} = host_router.get(&headers, &uri).clone();
+			let path = uri.path();
+			let token = get_token(&headers, &uri);
+			let header = |name| {
+				headers
+					.get(name)
+					.and_then(|value| value.to_str().ok())
+					.map(str::to_owned)
+			};
+			let range = header(RANGE);
+			let if_range = header(IF_RANGE);
+			let accept_mime = header(ACCEPT);
+			let encoding_set = get_encoding(headers);
+
+			// find the tile source and release the lock before serving,
+			// so that sources can be swapped while requests are in flight
+			let tile_source = tile_sources
+				.read()
+				.unwrap()
+				.iter()
+				.find(|tile_source| path.starts_with(&tile_source.prefix))
+				.cloned();
+
+			if let Some(tile_source) = tile_source {
+				if let Some(response) = deny_access(&tile_source, &access_tokens, token.as_deref()) {
+					return response;
+				}
+
+				let sub_path: Vec<&str> = path[tile_source.prefix.len()..].split('/').collect();
+				return tile_source
+					.source
+					.get_data(&sub_path, encoding_set, accept_mime.as_deref(), uri.query())
+					.await;
+			}
+
+			let mut path_vec: Vec<&str> = path.split('/').skip(1).collect();
+
+			if let Some(last) = path_vec.last_mut() {
+				if last.is_empty() {
+					*last = "index.html";
+				}
+			}
+
+			let path_slice = path_vec.as_slice();
+
+			// byte ranges refer to the uncompressed content
+			let encoding_set = if range.is_some() {
+				enum_set!(Compression::None)
+			} else {
+				encoding_set
+			};
+
+			for source in static_sources.iter() {
+				let response = source.get_data(path_slice, encoding_set, None, None).await;
+				if response.status() == 200 {
+					return respond_with_range(response, range.as_deref(), if_range.as_deref()).await;
+				}
+			}
+
+			ok_not_found()
+		}
+	}
+
+	fn add_api_to_app(&self, app: Router) -> Router {
+		let host_router = self.get_host_router();
+
+		let status_app = Router::new()
+			.route(
+				"/api/status.json",
+				get(|State(draining): State<Arc<AtomicBool>>| async move {
+					if draining.load(Ordering::Relaxed) {
+						let mut response = ok_data(
+							Blob::from("{\"status\":\"draining\"}"),
+							&Compression::None,
+							"application/json",
+						);
+						*response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+						response
+					} else {
+						ok_data(
+							Blob::from("{\"status\":\"ready\"}"),
+							&Compression::None,
+							"application/json",
+						)
+					}
+				}),
+			)
+			.with_state(self.draining.clone());
+
+		let api_app = Router::new()
+			.route(
+				"/api/tiles.json",
+				get(
+					|uri: Uri,
+					 headers: HeaderMap,
+					 State((host_router, access_tokens)): State<(HostRouter, Arc<AccessTokens>)>| async move {
+						// private sources are only listed for tokens that may access them
+						let token = get_token(&headers, &uri);
+						let tile_sources = &host_router.get(&headers, &uri).tile_sources;
+						let mut tile_sources_json_lines: Vec<String> = Vec::new();
+						for tile_source in tile_sources.read().unwrap().iter() {
+							if tile_source.private
+								&& access_tokens.check(token.as_deref(), &tile_source.prefix) != Access::Granted
+							{
+								continue;
+							}
+							tile_sources_json_lines.push(format!(
+								"{{ \"url\":\"{}\", \"name\":\"{}\", \"info\":{} }}",
+								tile_source.prefix,
+								tile_source.source.get_name(),
+								tile_source.source.get_info_as_json()
+							));
+						}
+						let tile_sources_json: String = "[\n\t".to_owned() + &tile_sources_json_lines.join(",\n\t") + "\n]";
+
+						ok_data(Blob::from(&tile_sources_json), &Compression::None, "application/json")
+					},
+				),
+			)
+			.with_state((host_router, Arc::new(self.access_tokens.clone())));
+
+		app.merge(status_app).merge(api_app)
+	}
+
+	fn add_admin_api_to_app(&self, app: Router) -> Router {
+		let token = match &self.admin_token {
+			Some(token) => token.to_owned(),
+			None => return app,
+		};
+
+		let admin_app = Router::new()
+			.route("/api/admin/reload", post(reload))
+			.route("/api/admin/sources", post(add_source))
+			.route("/api/admin/sources/*prefix", delete(remove_source))
+			.with_state((self.get_host_router(), Arc::new(token)));
+
+		return app.merge(admin_app);
+
+		/// Opens a container and mounts it for the host of the request. The body is a source definition in JSON,
+		/// with the fields of the config file, e.g. {"path":"data/osm.versatiles","name":"osm"}
+		async fn add_source(
+			uri: Uri, headers: HeaderMap, State((host_router, token)): State<(HostRouter, Arc<String>)>, body: Bytes,
+		) -> Response<Full<Bytes>> {
+			if !is_authorized(&headers, &token) {
+				return ok_error(401, "Unauthorized");
+			}
+			let tile_sources = host_router.get_tile_sources(&headers, &uri);
+
+			let source_config: SourceConfig = match serde_json::from_slice(&body) {
+				Ok(source_config) => source_config,
+				Err(err) => return ok_error(400, &format!("invalid source definition: {err}")),
+			};
+			if let Err(err) = source_config.validate() {
+				return ok_error(400, &err.to_string());
+			}
+
+			// check the prefix before opening the container, the insert below checks again
+			let prefix = source_config.get_prefix();
+			if let Err(err) = check_prefix(&tile_sources.read().unwrap(), &prefix) {
+				return ok_error(409, &err.to_string());
+			}
+
+			// a panicking reader must not take down the server
+			let private = source_config.private;
+			let file_name = source_config.expose_file.then(|| source_config.get_file_name());
+			let rate_limiter = source_config
+				.rate_limit
+				.as_ref()
+				.map(|rate_limit| Arc::new(RateLimiter::new(rate_limit)));
+			let opened = tokio::spawn(async move { source_config.open().await.map_err(|err| err.to_string()) }).await;
+			let container = match opened {
+				Ok(Ok(container)) => container,
+				Ok(Err(err)) => return ok_error(400, &format!("can not open source: {err}")),
+				Err(_) => return ok_error(400, "can not open source"),
+			};
+			let name = container.get_name();
+
+			let mut tile_source = TileSource::new(&prefix, container);
+			tile_source.private = private;
+			tile_source.rate_limiter = rate_limiter;
+			tile_source.file_name = file_name;
+
+			match insert_tile_source(&tile_sources, tile_source) {
+				Ok(prefix) => {
+					log::info!("added source {prefix}");
+					let json = format!("{{\"prefix\":{:?},\"name\":{:?}}}", prefix, name);
+					let mut response = ok_data(Blob::from(json), &Compression::None, "application/json");
+					*response.status_mut() = StatusCode::CREATED;
+					response
+				}
+				Err(err) => ok_error(409, &err.to_string()),
+			}
+		}
+
+		/// Unmounts a source of the host of the request, e.g. "DELETE /api/admin/sources/tiles/osm/".
+		/// Requests in flight are finished with the removed source.
+		async fn remove_source(
+			uri: Uri, headers: HeaderMap, Path(prefix): Path<String>,
+			State((host_router, token)): State<(HostRouter, Arc<String>)>,
+		) -> Response<Full<Bytes>> {
+			if !is_authorized(&headers, &token) {
+				return ok_error(401, "Unauthorized");
+			}
+
+			let prefix = clean_prefix(&prefix);
+			let tile_sources = host_router.get_tile_sources(&headers, &uri);
+			let mut list = tile_sources.write().unwrap();
+			let length = list.len();
+			list.retain(|tile_source| tile_source.prefix != prefix);
+
+			if list.len() == length {
+				return ok_error(404, &format!("no source with the prefix '{prefix}'"));
+			}
+
+			log::info!("removed source {prefix}");
+			let json = format!("{{\"removed\":{:?}}}", prefix);
+			ok_data(Blob::from(json), &Compression::None, "application/json")
+		}
+
+		/// Reloads the sources of the host of the request.
+		async fn reload(
+			uri: Uri, headers: HeaderMap, State((host_router, token)): State<(HostRouter, Arc<String>)>,
+		) -> Response<Full<Bytes>> {
+			if !is_authorized(&headers, &token) {
+				return ok_error(401, "Unauthorized");
+			}
+
+			let tile_sources = host_router.get_tile_sources(&headers, &uri);
+			let (reloaded, errors) = reload_tile_sources(&tile_sources, |_| true).await;
+
+			let json = format!("{{\"reloaded\":{:?},\"errors\":{:?}}}", reloaded, errors);
+			ok_data(Blob::from(json), &Compression::None, "application/json")
+		}
+	}
+
+	fn add_metrics_to_app(&self, app: Router) -> Router {
+		let metrics = match &self.metrics {
+			Some(metrics) => metrics.clone(),
+			None => return app,
+		};
+
+		let metrics_app = Router::new()
+			.route("/metrics", get(serve_metrics))
+			.with_state((metrics, self.get_host_router()));
+
+		return app.merge(metrics_app);
+
+		/// Reports the readers of the host of the request.
+		async fn serve_metrics(
+			uri: Uri, headers: HeaderMap, State((metrics, host_router)): State<(Arc<Metrics>, HostRouter)>,
+		) -> Response<Full<Bytes>> {
+			let readers: Vec<_> = host_router
+				.get_tile_sources(&headers, &uri)
+				.read()
+				.unwrap()
+				.iter()
+				.filter_map(|tile_source| {
+					let stats = tile_source.source.get_reader_stats()?;
+					// the address changes, when the source is reloaded
+					let reader_id = Arc::as_ptr(&tile_source.source).cast::<()>() as usize;
+					Some((tile_source.prefix.clone(), reader_id, stats))
+				})
+				.collect();
+
+			ok_data(
+				Blob::from(metrics.render(&readers)),
+				&Compression::None,
+				"text/plain; version=0.0.4",
+			)
+		}
+	}
+
+	/// Answers requests with 429 if the client exceeds the global rate limit or the rate limit of the tile source.
+	fn add_rate_limit_to_app(&self, app: Router) -> Router {
+		let has_source_limits = self
+			.get_host_router()
+			.any_tile_source(|tile_source| tile_source.rate_limiter.is_some());
+		// sources with rate limits can also be added later via the admin API
+		if self.rate_limiter.is_none() && !has_source_limits && self.admin_token.is_none() {
+			return app;
+		}
+
+		let limits = RequestLimits {
+			host_router: self.get_host_router(),
+			rate_limiter: self.rate_limiter.clone(),
+			trusted_proxies: Arc::new(self.trusted_proxies.clone()),
+		};
+
+		return app.layer(middleware::from_fn_with_state(limits, limit_request));
+
+		async fn limit_request<B>(State(limits): State<RequestLimits>, request: Request<B>, next: Next<B>) -> Response {
+			let RequestLimits {
+				host_router,
+				rate_limiter,
+				trusted_proxies,
+			} = limits;
+
+			let peer = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+				Some(connect_info) => connect_info.0.ip(),
+				None => return next.run(request).await,
+			};
+			let client = get_client_ip(peer, request.headers(), &trusted_proxies);
+
+			let source_limiter = find_requested_source(
+				&host_router
+					.get(request.headers(), request.uri())
+					.tile_sources
+					.read()
+					.unwrap(),
+				request.uri(),
+			)
+			.and_then(|tile_source| tile_source.rate_limiter.clone());
+
+			for limiter in [rate_limiter.as_ref(), source_limiter.as_ref()].into_iter().flatten() {
+				if let Err(wait) = limiter.check(client) {
+					let mut response = ok_error(429, "Too Many Requests");
+					let seconds = wait.as_secs_f64().ceil().max(1.0) as u64;
+					response
+						.headers_mut()
+						.insert(RETRY_AFTER, seconds.to_string().parse().unwrap());
+					return response.map(axum::body::boxed);
+				}
+			}
+
+			next.run(request).await
+		}
+	}
+
+	/// Records every request in the metrics and the access log, if enabled.
+	fn add_observer_to_app(&self, app: Router, access_log: Option<Arc<AccessLog>>) -> Router {
+		if self.metrics.is_none() && access_log.is_none() {
+			return app;
+		}
+
+		let observer = RequestObserver {
+			host_router: self.get_host_router(),
+			metrics: self.metrics.clone(),
+			access_log,
+		};
+
+		return app.layer(middleware::from_fn_with_state(observer, observe_request));
+
+		async fn observe_request<B>(
+			State(observer): State<RequestObserver>, request: Request<B>, next: Next<B>,
+		) -> Response {
+			let time = SystemTime::now();
+			let start = Instant::now();
+			let path = request.uri().path().to_owned();
+			let path_and_query = request
+				.uri()
+				.path_and_query()
+				.map_or(path.clone(), |path_and_query| redact_token(path_and_query.as_str()));
+			let method = request.method().to_string();
+			let version = format!("{:?}", request.version());
+			let remote = request
+				.extensions()
+				.get::<ConnectInfo<SocketAddr>>()
+				.map(|connect_info| connect_info.0.ip());
+			let tile_sources = observer
+				.host_router
+				.get(request.headers(), request.uri())
+				.tile_sources
+				.clone();
+
+			let response = next.run(request).await;
+			let duration = start.elapsed();
+
+			// requests are grouped by tile source, all other requests are either api or static
+			let tile_prefix = tile_sources
+				.read()
+				.unwrap()
+				.iter()
+				.find(|tile_source| path.starts_with(&tile_source.prefix))
+				.map(|tile_source| tile_source.prefix.clone());
+			let source = match tile_prefix {
+				Some(prefix) => prefix,
+				None if path.starts_with("/api/") || path == "/status" || path == "/metrics" => "api".to_owned(),
+				None if path.starts_with("/files/") => "files".to_owned(),
+				None if path == "/wmts" || path.starts_with("/wmts/") => "wmts".to_owned(),
+				None if path == "/ogc" || path.starts_with("/ogc/") => "ogc".to_owned(),
+				None => "static".to_owned(),
+			};
+
+			let status = response.status().as_u16();
+			let encoding = response
+				.headers()
+				.get(CONTENT_ENCODING)
+				.and_then(|value| value.to_str().ok())
+				.unwrap_or("identity")
+				.to_owned();
+			// streamed bodies have no exact size, but a "Content-Length"
+			let bytes = response.body().size_hint().exact().or_else(|| {
+				response
+					.headers()
+					.get(CONTENT_LENGTH)
+					.and_then(|value| value.to_str().ok()?.parse().ok())
+			});
+			let bytes = bytes.unwrap_or(0);
+
+			if let Some(metrics) = &observer.metrics {
+				metrics.record(&source, status, &encoding, bytes, duration);
+			}
+
+			if let Some(access_log) = &observer.access_log {
+				access_log.log(&AccessLogEntry {
+					time,
+					remote,
+					method,
+					path: path_and_query,
+					version,
+					status,
+					bytes,
+					encoding,
+					duration,
+					source,
+				});
+			}
+
+			response
+		}
+	}
+
+	pub fn iter_url_mapping(&self) -> impl Iterator<Item = (String, String)> + '_ {
+		let mapping: Vec<(String, String)> = self
+			.tile_sources
+			.read()
+			.unwrap()
+			.iter()
+			.map(|tile_source| (tile_source.prefix.to_owned(), tile_source.source.get_name()))
+			.collect();
+		mapping.into_iter()
+	}
+}
+
+/// Modification time and size of a file.
+type FileStamp = (SystemTime, u64);
+
+/// Tracks the file of a tile source, so that it is only reloaded after it has stopped changing.
+struct FileWatch {
+	/// stamp of the file when it was last loaded
+	loaded: FileStamp,
+	/// stamp of the file at the last poll
+	seen: FileStamp,
+}
+
+impl FileWatch {
+	fn new(stamp: FileStamp) -> FileWatch {
+		FileWatch {
+			loaded: stamp,
+			seen: stamp,
+		}
+	}
+
+	/// Returns true if the file differs from the loaded one and has been unchanged since the last poll,
+	/// so that files that are still being written are not opened.
+	fn is_modified(&mut self, stamp: FileStamp) -> bool {
+		if stamp != self.seen {
+			self.seen = stamp;
+			return false;
+		}
+		if stamp != self.loaded {
+			self.loaded = stamp;
+			return true;
+		}
+		false
+	}
+}
+
+/// Reloads tile sources when their files are modified.
+/// If a modified file can't be opened, the old container stays in use until the file changes again.
+fn reload_on_modification(tile_sources: TileSourceList) -> JoinHandle<()> {
+	tokio::spawn(async move {
+		let mut known: HashMap<String, FileWatch> = HashMap::new();
+		let mut ticker = interval(WATCH_INTERVAL);
+
+		loop {
+			ticker.tick().await;
+
+			let sources: Vec<TileSource> = tile_sources.read().unwrap().clone();
+			let mut modified: Vec<String> = Vec::new();
+
+			for tile_source in sources.iter() {
+				let path = match tile_source.source.get_path() {
+					Some(path) => path,
+					None => continue,
+				};
+				let stamp = match path.metadata().and_then(|m| Ok((m.modified()?, m.len()))) {
+					Ok(stamp) => stamp,
+					Err(_) => continue,
+				};
+				match known.get_mut(&tile_source.prefix) {
+					Some(watch) => {
+						if watch.is_modified(stamp) {
+							modified.push(tile_source.prefix.clone());
+						}
+					}
+					None => {
+						known.insert(tile_source.prefix.clone(), FileWatch::new(stamp));
+					}
+				}
+			}
+
+			if !modified.is_empty() {
+				reload_tile_sources(&tile_sources, |t| modified.contains(&t.prefix)).await;
+			}
+		}
+	})
+}
+
+/// Serves containers that are added to the source directories.
+fn mount_new_containers(tile_sources: TileSourceList, source_dirs: Vec<SourceDirConfig>) -> JoinHandle<()> {
+	tokio::spawn(async move {
+		// containers that could not be mounted are only tried again when they are modified
+		let mut failed: HashMap<String, SystemTime> = HashMap::new();
+		let mut ticker = interval(WATCH_INTERVAL);
+
+		loop {
+			ticker.tick().await;
+
+			for source_dir in source_dirs.iter() {
+				let source_configs = match source_dir.scan() {
+					Ok(source_configs) => source_configs,
+					Err(err) => {
+						log::error!("{err}");
+						continue;
+					}
+				};
+
+				for source_config in source_configs {
+					let is_mounted = tile_sources
+						.read()
+						.unwrap()
+						.iter()
+						.any(|tile_source| tile_source.source.get_url().as_ref() == Some(&source_config.path));
+					if is_mounted {
+						continue;
+					}
+
+					let time = match PathBuf::from(&source_config.path).metadata().and_then(|m| m.modified()) {
+						Ok(time) => time,
+						Err(_) => continue,
+					};
+					if failed.get(&source_config.path) == Some(&time) {
+						continue;
+					}
+
+					match mount_container(&tile_sources, &source_config).await {
+						Ok(prefix) => {
+							log::info!("added source {prefix} from {}", source_config.path);
+							failed.remove(&source_config.path);
+						}
+						Err(err) => {
+							log::warn!("can not add source {}: {err}", source_config.path);
+							failed.insert(source_config.path, time);
+						}
+					}
+				}
+			}
+		}
+	})
+}
+
+/// Opens a container and mounts it at "/tiles/{name}/". Returns its prefix.
+async fn mount_container(tile_sources: &TileSourceList, source_config: &SourceConfig) -> Result<String> {
+	// check the prefix before opening the container, the insert below checks again
+	let prefix = source_config.get_prefix();
+	check_prefix(&tile_sources.read().unwrap(), &prefix)?;
+
+	let container = source_config.open_isolated().await?;
+	insert_tile_source(tile_sources, TileSource::new(&prefix, container))
+}
+
+/// Reads the host name of a request from the "Host" header, or the uri in HTTP/2 requests.
+fn get_hostname(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+	let host = match headers.get(HOST).and_then(|value| value.to_str().ok()) {
+		Some(host) => host,
+		None => uri.host()?,
+	};
+	let hostname = clean_hostname(host);
+	if hostname.is_empty() {
+		None
+	} else {
+		Some(hostname)
+	}
+}
+
+/// Lowercases a host name and removes the port and a trailing dot, e.g. "Example.org.:8080" becomes "example.org".
+fn clean_hostname(host: &str) -> String {
+	let host = host.trim();
+	let hostname = if host.starts_with('[') {
+		// IPv6 address, e.g. "[::1]:8080"
+		match host.find(']') {
+			Some(end) => &host[..=end],
+			None => host,
+		}
+	} else {
+		host.split(':').next().unwrap_or(host)
+	};
+	hostname.trim_end_matches('.').to_lowercase()
+}
+
+/// Checks that a prefix doesn't overlap with the prefix of another source.
+fn check_prefix(tile_sources: &[TileSource], prefix: &str) -> Result<()> {
+	for other_tile_source in tile_sources.iter() {
+		if prefixes_overlap(prefix, &other_tile_source.prefix) {
+			return Err(Error::new(&format!(
+				"multiple sources with the prefix '{}' and '{}' are defined",
+				prefix, other_tile_source.prefix
+			)));
+		}
+	}
+	Ok(())
+}
+
+/// Checks that no other source exposes its file with this name.
+fn check_file_name(tile_sources: &[TileSource], file_name: &str) -> Result<()> {
+	if tile_sources
+		.iter()
+		.any(|tile_source| tile_source.file_name.as_deref() == Some(file_name))
+	{
+		return Err(Error::new(&format!(
+			"multiple sources with the file name '{file_name}' are defined"
+		)));
+	}
+	Ok(())
+}
+
+/// Answers requests for private sources without a valid access token with 401 or 403.
+fn deny_access(
+	tile_source: &TileSource, access_tokens: &AccessTokens, token: Option<&str>,
+) -> Option<Response<Full<Bytes>>> {
+	if !tile_source.private {
+		return None;
+	}
+
+	match access_tokens.check(token, &tile_source.prefix) {
+		Access::Granted => None,
+		Access::Unauthorized => {
+			let mut response = ok_error(401, "Unauthorized");
+			response
+				.headers_mut()
+				.insert(WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+			Some(response)
+		}
+		Access::Forbidden => Some(ok_error(403, "Forbidden")),
+	}
+}
+
+/// Mounts a tile source. Returns its prefix.
+fn insert_tile_source(tile_sources: &TileSourceList, tile_source: TileSource) -> Result<String> {
+	let mut list = tile_sources.write().unwrap();
+
+	check_prefix(&list, &tile_source.prefix)?;
+	if let Some(file_name) = &tile_source.file_name {
+		check_file_name(&list, file_name)?;
+	}
+
+	let prefix = tile_source.prefix.clone();
+	list.push(tile_source);
+
+	Ok(prefix)
+}
+
+/// Reopens the selected tile sources. A source is only replaced if its new version could be opened and validated.
+/// Requests in flight keep using the old source until they are finished.
+async fn reload_tile_sources(
+	tile_sources: &TileSourceList, filter: impl Fn(&TileSource) -> bool,
+) -> (Vec<String>, Vec<String>) {
+	let selected: Vec<TileSource> = tile_sources
+		.read()
+		.unwrap()
+		.iter()
+		.filter(|tile_source| filter(tile_source))
+		.cloned()
+		.collect();
+
+	let mut reloaded: Vec<String> = Vec::new();
+	let mut errors: Vec<String> = Vec::new();
+
+	for tile_source in selected {
+		match tile_source.source.reload().await {
+			Ok(new_source) => {
+				let mut list = tile_sources.write().unwrap();
+				// the source might have been replaced in the meantime
+				if let Some(entry) = list.iter_mut().find(|t| Arc::ptr_eq(&t.source, &tile_source.source)) {
+					log::info!("reloaded source {}", tile_source.prefix);
+					entry.source = Arc::new(new_source);
+					reloaded.push(tile_source.prefix);
+				}
+			}
+			Err(err) => {
+				log::error!("can not reload source {}: {}", tile_source.prefix, err);
+				errors.push(format!("{}: {}", tile_source.prefix, err));
+			}
+		}
+	}
+
+	(reloaded, errors)
+}
+
+/// Finds the tile source of a request, by its prefix or via the WMTS, OGC API, files and preview urls,
+/// so that the limits of a source apply to all of them.
+fn find_requested_source<'a>(tile_sources: &'a [TileSource], uri: &Uri) -> Option<&'a TileSource> {
+	let path = uri.path();
+	if let Some(tile_source) = tile_sources
+		.iter()
+		.find(|tile_source| path.starts_with(&tile_source.prefix))
+	{
+		return Some(tile_source);
+	}
+
+	// path parameters are percent-decoded by the router
+	let parts: Vec<String> = path.trim_start_matches('/').split('/').map(decode_percent).collect();
+	let parts: Vec<&str> = parts.iter().map(|part| part.as_str()).collect();
+	let by_id = |id: &str| {
+		tile_sources
+			.iter()
+			.find(|tile_source| get_source_id(&tile_source.prefix) == id)
+	};
+
+	match parts.as_slice() {
+		["wmts"] => match parse_kvp_request(uri.query().unwrap_or("")) {
+			Ok(WmtsRequest::GetTile { layer, .. }) => by_id(&layer),
+			_ => None,
+		},
+		["wmts", "1.0.0", layer, _, ..] => by_id(layer),
+		["ogc", "collections", id, ..] => by_id(id),
+		["preview", "assets", ..] => None,
+		["preview", name, ..] => by_id(name),
+		["files", file_name] => tile_sources
+			.iter()
+			.find(|tile_source| tile_source.file_name.as_deref() == Some(*file_name)),
+		_ => None,
+	}
+}
+
+/// Checks the header "Authorization: Bearer <token>".
+fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+	let value = match headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()) {
+		Some(value) => value,
+		None => return false,
+	};
+	match value.strip_prefix("Bearer ") {
+		// compare all bytes, so that the time does not depend on the position of the first difference
+		Some(given) => {
+			given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |a, (b, c)| a | (b ^ c)) == 0
+		}
+		None => false,
+	}
+}
+
+/// Builds the absolute urls in WMTS capabilities and OGC API responses.
+struct BaseUrl {
+	/// public url of the server, e.g. "https://tiles.example.org", used for all requests
+	configured: Option<String>,
+	scheme: &'static str,
+	/// listen address, used if the "Host" header is missing or unknown
+	address: String,
+	/// host names accepted from the "Host" header
+	hostnames: Vec<String>,
+}
+
+impl BaseUrl {
+	/// Returns the url of the server as seen by the client, e.g. "https://example.org".
+	/// The "Host" header is controlled by the client, so it is only used for known host names.
+	fn get(&self, headers: &HeaderMap) -> String {
+		if let Some(url) = &self.configured {
+			return url.clone();
+		}
+		let host = headers
+			.get(HOST)
+			.and_then(|value| value.to_str().ok())
+			.filter(|host| is_valid_host(host) && self.hostnames.contains(&clean_hostname(host)))
+			.unwrap_or(&self.address);
+		format!("{}://{host}", self.scheme)
+	}
+}
+
+/// Checks that a "Host" header only contains a host name or address and an optional port.
+fn is_valid_host(host: &str) -> bool {
+	!host.is_empty()
+		&& host
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+}
+
+/// Returns the id of a tile source in the preview, WMTS and OGC API urls: the last part of its prefix,
+/// e.g. "/tiles/osm/" becomes "osm".
+pub fn get_source_id(prefix: &str) -> &str {
+	prefix.trim_matches('/').rsplit('/').next().unwrap()
+}
+
+/// Normalizes a url prefix, so that it starts and ends with a slash.
+pub fn clean_prefix(url_prefix: &str) -> String {
+	let mut prefix = url_prefix.trim().to_owned();
+	if !prefix.starts_with('/') {
+		prefix = "/".to_owned() + &prefix;
+	}
+	if !prefix.ends_with('/') {
+		prefix += "/";
+	}
+	prefix
+}
+
+/// Two tile sources can't be served if one prefix contains the other.
+pub fn prefixes_overlap(prefix1: &str, prefix2: &str) -> bool {
+	prefix1.starts_with(prefix2) || prefix2.starts_with(prefix1)
+}
+
+pub fn ok_not_found() -> Response<Full<Bytes>> {
+	Response::builder().status(404).body(Full::from("Not Found")).unwrap()
+}
+
+pub fn ok_error(status: u16, message: &str) -> Response<Full<Bytes>> {
+	Response::builder()
+		.status(status)
+		.body(Full::from(message.to_owned()))
+		.unwrap()
+}
+
+pub fn ok_data(data: Blob, compression: &Compression, mime: &str) -> Response<Full<Bytes>> {
+	let mut response = Response::builder()
+		.status(200)
+		.header(CONTENT_TYPE, mime)
+		.header(CACHE_CONTROL, "public")
+		.header(VARY, "accept-encoding");
+
+	match compression {
+		Compression::None => {}
+		Compression::Gzip => response = response.header(CONTENT_ENCODING, "gzip"),
+		Compression::Brotli => response = response.header(CONTENT_ENCODING, "br"),
+	}
+
+	response.body(Full::from(data.as_vec())).unwrap()
+}
+
+pub fn guess_mime(path: &std::path::Path) -> String {
+	let mime = mime_guess::from_path(path).first_or_octet_stream();
+	return mime.essence_str().to_owned();
+}
+
+/// Parses the `Accept-Encoding` header according to RFC 9110 section 12.5.3.
+///
+/// Returns the compressions the client prefers, i.e. the accepted codings with the highest q-value,
+/// either explicitly or via `*`. If several have the same q-value, the server picks its own preference.
+/// `identity` is always acceptable unless it is excluded with `identity;q=0` or `*;q=0`, and is included
+/// as a fallback. If the client excludes everything, the header is disregarded and only `identity` is returned.
+fn get_encoding(headers: HeaderMap) -> EnumSet<Compression> {
+	let encoding_option = headers.get(ACCEPT_ENCODING);
+	if encoding_option.is_none() {
+		return enum_set!(Compression::None);
+	}
+
+	let encoding_string = encoding_option.unwrap().to_str().unwrap_or("").to_lowercase();
+
+	let mut q_gzip: Option<f32> = None;
+	let mut q_brotli: Option<f32> = None;
+	let mut q_identity: Option<f32> = None;
+	let mut q_any: Option<f32> = None;
+
+	for entry in encoding_string.split(',') {
+		let mut parts = entry.split(';').map(|part| part.trim());
+		let coding = parts.next().unwrap_or("");
+
+		let mut q: f32 = 1.0;
+		for param in parts {
+			if let Some((key, value)) = param.split_once('=') {
+				if key.trim() == "q" {
+					q = value.trim().parse::<f32>().unwrap_or(0.0);
+				}
+			}
+		}
+
+		match coding {
+			"gzip" | "x-gzip" => q_gzip = Some(q),
+			"br" => q_brotli = Some(q),
+			"identity" => q_identity = Some(q),
+			"*" => q_any = Some(q),
+			_ => {}
+		}
+	}
+
+	let q_gzip = q_gzip.or(q_any).unwrap_or(0.0);
+	let q_brotli = q_brotli.or(q_any).unwrap_or(0.0);
+	// identity is acceptable by default, but least preferred
+	let identity_accepted = q_identity.or(q_any).is_none_or(|q| q > 0.0);
+	let q_identity = q_identity.or(q_any).unwrap_or(0.0);
+	let q_best = q_gzip.max(q_brotli).max(q_identity);
+
+	let mut encoding_set: EnumSet<Compression> = EnumSet::new();
+	if identity_accepted {
+		encoding_set.insert(Compression::None);
+	}
+	if q_best > 0.0 && q_gzip == q_best {
+		encoding_set.insert(Compression::Gzip);
+	}
+	if q_best > 0.0 && q_brotli == q_best {
+		encoding_set.insert(Compression::Brotli);
+	}
+
+	if encoding_set.is_empty() {
+		encoding_set.insert(Compression::None);
+	}
+
+	encoding_set
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		clean_hostname, get_encoding, get_source_id, guess_mime, ok_data, FileWatch, ServerSourceTrait, TileServer,
+	};
+	use crate::{
+		containers::{dummy, get_reader, tests::make_test_file},
+		server::{
+			source::{self, TileContainer},
+			tls::tests::make_test_tls,
+			AccessLogConfig, AccessLogFormat, CorsConfig, IpRange, RateLimitConfig, SourceDirConfig,
+		},
+		shared::{
+			Blob,
+			Compression::{self, *},
+			TileFormat,
+		},
+	};
+	use assert_fs::{NamedTempFile, TempDir};
+	use axum::http::{
+		header::{
+			ACCEPT_ENCODING, ACCEPT_RANGES, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+			ACCESS_CONTROL_REQUEST_METHOD, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, HOST,
+			IF_NONE_MATCH, IF_RANGE, ORIGIN, RANGE,
+		},
+		HeaderMap, Method, Version,
+	};
+	use axum::{
+		body::{Bytes, Full},
+		response::Response,
+	};
+	use enumset::{enum_set, EnumSet};
+	use std::{
+		fs::{copy, read_to_string, rename, write},
+		path::Path,
+		time::SystemTime,
+	};
+	use tokio::time::{sleep, Duration, Instant};
+
+	const IP: &str = "127.0.0.1";
+	const PORT: u16 = 3000;
+
+	#[test]
+	fn test_get_encoding() {
+		let test = |encoding: &str, comp0: EnumSet<Compression>| {
+			let mut map = HeaderMap::new();
+			if encoding != "NONE" {
+				map.insert(ACCEPT_ENCODING, encoding.parse().unwrap());
+			}
+			let comp = get_encoding(map);
+			assert_eq!(comp, comp0);
+		};
+
+		test("NONE", enum_set!(None));
+		test("", enum_set!(None));
+		test("*", enum_set!(None | Brotli | Gzip));
+		test("br", enum_set!(None | Brotli));
+		test("br;q=1.0, gzip;q=0.8, *;q=0.1", enum_set!(None | Brotli));
+		test("compress", enum_set!(None));
+		test("compress, gzip", enum_set!(None | Gzip));
+		test("compress;q=0.5, gzip;q=1.0", enum_set!(None | Gzip));
+		test("deflate", enum_set!(None));
+		test("deflate, gzip;q=1.0, *;q=0.5", enum_set!(None | Gzip));
+		test("gzip", enum_set!(None | Gzip));
+		test("gzip, compress, br", enum_set!(None | Brotli | Gzip));
+		test(
+			"gzip, deflate, br;q=1.0, identity;q=0.5, *;q=0.25",
+			enum_set!(None | Brotli | Gzip),
+		);
+		test("gzip;q=1.0, identity; q=0.5, *;q=0", enum_set!(None | Gzip));
+		test("gzip;q=0", enum_set!(None));
+		test("gzip;q=0.000, br", enum_set!(None | Brotli));
+		test("GZIP;Q=0.5", enum_set!(None | Gzip));
+		test("x-gzip", enum_set!(None | Gzip));
+		test("br, *;q=0", enum_set!(Brotli));
+		test("gzip, identity;q=0", enum_set!(Gzip));
+		test("identity;q=0", enum_set!(None));
+		test("*, br;q=0", enum_set!(None | Gzip));
+		test("identity", enum_set!(None));
+		test("gzip;q=1, br;q=0.1", enum_set!(None | Gzip));
+		test("br;q=0.5, gzip;q=0.5", enum_set!(None | Brotli | Gzip));
+		test("gzip;q=0.5, identity;q=1", enum_set!(None));
+	}
+
+	#[test]
+	fn test_guess_mime() {
+		let test = |path: &str, mime: &str| {
+			assert_eq!(guess_mime(Path::new(path)), mime);
+		};
+
+		test("fluffy.css", "text/css");
+		test("fluffy.gif", "image/gif");
+		test("fluffy.htm", "text/html");
+		test("fluffy.html", "text/html");
 		test("fluffy.jpeg", "image/jpeg");
 		test("fluffy.jpg", "image/jpeg");
 		test("fluffy.js", "application/javascript");
@@ -308,40 +2143,1200 @@ mod tests {
 		test("fluffy.svg", "image/svg+xml");
 	}
 
+	#[test]
+	fn test_get_source_id() {
+		assert_eq!(get_source_id("/tiles/osm/"), "osm");
+		assert_eq!(get_source_id("/osm/"), "osm");
+	}
+
+	#[tokio::test]
+	async fn test_server() {
+		async fn get(path: &str) -> String {
+			reqwest::get(format!("http://{IP}:{PORT}/{path}"))
+				.await
+				.unwrap()
+				.text()
+				.await
+				.unwrap()
+		}
+
+		let mut server = TileServer::new(IP, PORT);
+
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+		let source = TileContainer::from(reader);
+		server.add_tile_source("cheese", source);
+
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+		let source = TileContainer::from(reader);
+		server.add_static_source(source);
+
+		server.start().await.unwrap();
+
+		assert_eq!(get("api/status.json").await, "{\"status\":\"ready\"}");
+		assert_eq!(get("api/tiles.json").await, "[\n\t{ \"url\":\"/cheese/\", \"name\":\"dummy name\", \"info\":{ \"container\":\"dummy container\", \"format\":\"pbf\", \"compression\":\"gzip\", \"zoom_min\":0, \"zoom_max\":8, \"bbox\":[-180.0, -85.05113, 180.0, 85.05112] } }\n]");
+		assert!(get("cheese/0/0/0.pbf").await.starts_with("\u{1a}4\n\u{5}ocean"));
+		assert_eq!(get("cheese/meta.json").await, "dummy meta data");
+		assert_eq!(get("cheese/tiles.json").await, "dummy meta data");
+		assert_eq!(get("cheese/brum.json").await, "Not Found");
+		assert_eq!(get("status").await, "ready!");
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_cors() {
+		const PORT: u16 = 3001;
+
+		let mut server = TileServer::new(IP, PORT);
+
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+		server.add_tile_source("cheese", TileContainer::from(reader));
+
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+		server.add_static_source(TileContainer::from(reader));
+
+		server.set_cors(CorsConfig::new(&["https://*.example.org".to_owned()]));
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		let allowed_origin = |path: &str, origin: &str| {
+			let request = client
+				.get(format!("http://{IP}:{PORT}/{path}"))
+				.header(ORIGIN, origin)
+				.send();
+			async move {
+				let response = request.await.unwrap();
+				response
+					.headers()
+					.get(ACCESS_CONTROL_ALLOW_ORIGIN)
+					.map(|value| value.to_str().unwrap().to_owned())
+			}
+		};
+
+		let origin = "https://maps.example.org";
+		assert_eq!(allowed_origin("cheese/0/0/0.pbf", origin).await.unwrap(), origin);
+		assert_eq!(allowed_origin("api/tiles.json", origin).await.unwrap(), origin);
+		assert_eq!(allowed_origin("meta.json", origin).await.unwrap(), origin);
+		assert_eq!(
+			allowed_origin("cheese/0/0/0.pbf", "https://example.com").await,
+			Option::None
+		);
+
+		let response = client
+			.request(Method::OPTIONS, format!("http://{IP}:{PORT}/cheese/0/0/0.pbf"))
+			.header(ORIGIN, origin)
+			.header(ACCESS_CONTROL_REQUEST_METHOD, "GET")
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), origin);
+		assert_eq!(
+			response.headers().get(ACCESS_CONTROL_ALLOW_METHODS).unwrap(),
+			"GET,HEAD,OPTIONS"
+		);
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_tls() {
+		const PORT: u16 = 3002;
+
+		let dir = TempDir::new().unwrap();
+		let mut server = TileServer::new(IP, PORT);
+
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+		server.add_tile_source("cheese", TileContainer::from(reader));
+		server.set_tls(make_test_tls(&dir));
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::builder()
+			.use_rustls_tls()
+			.danger_accept_invalid_certs(true)
+			.build()
+			.unwrap();
+		let response = client
+			.get(format!("https://localhost:{PORT}/cheese/meta.json"))
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(response.version(), Version::HTTP_2);
+		assert_eq!(response.text().await.unwrap(), "dummy meta data");
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_reload() {
+		const PORT: u16 = 3003;
+
+		let dir = TempDir::new().unwrap();
+		let filename = dir.path().join("cheese.versatiles");
+		let replace_file = |file: &NamedTempFile| {
+			let temp = dir.path().join("temp.versatiles");
+			copy(file.path(), &temp).unwrap();
+			rename(&temp, &filename).unwrap();
+		};
+
+		replace_file(&make_test_file(TileFormat::PBF, Gzip, 3, "versatiles").await);
+
+		let reader = get_reader(filename.to_str().unwrap()).await.unwrap();
+		let mut source = TileContainer::from(reader);
+		source.set_url(filename.to_str().unwrap());
+
+		let mut server = TileServer::new(IP, PORT);
+		server.add_tile_source("cheese", source);
+		server.set_admin_token("secret");
+		server.set_watch(true);
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		let get_format = || async {
+			let text = reqwest::get(format!("http://{IP}:{PORT}/api/tiles.json"))
+				.await
+				.unwrap()
+				.text()
+				.await
+				.unwrap();
+			text.split("\"format\":\"").nth(1).unwrap()[0..3].to_owned()
+		};
+		let post_reload = |token: &str| {
+			client
+				.post(format!("http://{IP}:{PORT}/api/admin/reload"))
+				.bearer_auth(token)
+				.send()
+		};
+
+		assert_eq!(get_format().await, "pbf");
+
+		// wrong token
+		assert_eq!(post_reload("cheddar").await.unwrap().status(), 401);
+
+		// replace file and reload via admin api
+		replace_file(&make_test_file(TileFormat::PNG, None, 3, "versatiles").await);
+		let response = post_reload("secret").await.unwrap();
+		assert_eq!(response.status(), 200);
+		assert_eq!(
+			response.text().await.unwrap(),
+			"{\"reloaded\":[\"/cheese/\"],\"errors\":[]}"
+		);
+		assert_eq!(get_format().await, "png");
+
+		// broken files are rejected and the old source stays in use
+		write(&filename, "broken").unwrap();
+		let response = post_reload("secret").await.unwrap().text().await.unwrap();
+		assert!(response.starts_with("{\"reloaded\":[],\"errors\":[\"/cheese/: "));
+		assert_eq!(get_format().await, "png");
+
+		// the watcher picks up modified files, once they are unchanged for one interval
+		replace_file(&make_test_file(TileFormat::PBF, Gzip, 3, "versatiles").await);
+		sleep(Duration::from_secs(7)).await;
+		assert_eq!(get_format().await, "pbf");
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_metrics() {
+		const PORT: u16 = 3004;
+
+		let file = make_test_file(TileFormat::PBF, Gzip, 3, "versatiles").await;
+		let reader = get_reader(file.to_str().unwrap()).await.unwrap();
+
+		let mut server = TileServer::new(IP, PORT);
+		server.add_tile_source("cheese", TileContainer::from(reader));
+		server.set_metrics(true);
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		let get = |path: &str| {
+			client
+				.get(format!("http://{IP}:{PORT}/{path}"))
+				.header("accept-encoding", "gzip")
+				.send()
+		};
+
+		get("cheese/0/0/0.pbf").await.unwrap();
+		get("cheese/1/0/0.pbf").await.unwrap();
+		get("cheese/brum.json").await.unwrap();
+		get("api/status.json").await.unwrap();
+
+		let text = get("metrics").await.unwrap().text().await.unwrap();
+		let lines: Vec<&str> = text.lines().collect();
+		let contains = |line: &str| assert!(lines.contains(&line), "missing line: {line}\n{text}");
+
+		contains("versatiles_requests_total{source=\"/cheese/\",status=\"200\",encoding=\"gzip\"} 2");
+		contains("versatiles_requests_total{source=\"/cheese/\",status=\"404\",encoding=\"identity\"} 1");
+		contains("versatiles_requests_total{source=\"api\",status=\"200\",encoding=\"identity\"} 1");
+		contains("versatiles_request_duration_seconds_count{source=\"/cheese/\"} 3");
+		contains("versatiles_reader_cache_misses_total{source=\"/cheese/\"} 2");
+		contains("versatiles_upstream_range_requests_total{source=\"/cheese/\"} 0");
+
+		// without metrics the endpoint doesn't exist
+		server.stop().await;
+		server.set_metrics(false);
+		server.start().await.unwrap();
+		assert_eq!(get("metrics").await.unwrap().status(), 404);
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_access_log() {
+		const PORT: u16 = 3005;
+
+		let dir = TempDir::new().unwrap();
+		let path = dir.path().join("access.log");
+
+		let mut server = TileServer::new(IP, PORT);
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+		server.add_tile_source("cheese", TileContainer::from(reader));
+		server.set_access_log(AccessLogConfig {
+			format: AccessLogFormat::Common,
+			path: Some(path.clone()),
+			max_size: Option::None,
+		});
+		server.start().await.unwrap();
+
+		reqwest::get(format!("http://{IP}:{PORT}/cheese/meta.json?v=1"))
+			.await
+			.unwrap();
+		reqwest::get(format!("http://{IP}:{PORT}/brum")).await.unwrap();
+
+		server.stop().await;
+
+		let log = read_to_string(&path).unwrap();
+		let lines: Vec<&str> = log.lines().collect();
+		assert_eq!(lines.len(), 2);
+		assert!(lines[0].starts_with("127.0.0.1 - - ["));
+		assert!(lines[0].contains("] \"GET /cheese/meta.json?v=1 HTTP/1.1\" 200 15 \"identity\" "));
+		assert!(lines[0].ends_with(" \"/cheese/\""));
+		assert!(lines[1].contains("\"GET /brum HTTP/1.1\" 404 9 \"identity\" "));
+		assert!(lines[1].ends_with(" \"static\""));
+	}
+
+	#[derive(Debug)]
+	struct SlowSource(Duration);
+
+	#[async_trait::async_trait]
+	impl ServerSourceTrait for SlowSource {
+		fn get_name(&self) -> String {
+			"slow".to_owned()
+		}
+		fn get_info_as_json(&self) -> String {
+			"{}".to_owned()
+		}
+		async fn get_data(
+			&self, _path: &[&str], _accept: EnumSet<Compression>, _accept_mime: Option<&str>, _query: Option<&str>,
+		) -> Response<Full<Bytes>> {
+			sleep(self.0).await;
+			ok_data(Blob::from("finally"), &Compression::None, "text/plain")
+		}
+	}
+
+	#[tokio::test]
+	async fn test_shutdown() {
+		const PORT: u16 = 3006;
+
+		let mut server = TileServer::new(IP, PORT);
+		server.add_tile_source("slow", Box::new(SlowSource(Duration::from_millis(500))));
+		server.add_tile_source("slower", Box::new(SlowSource(Duration::from_secs(10))));
+		server.start().await.unwrap();
+
+		let get = |path: &str| reqwest::get(format!("http://{IP}:{PORT}/{path}"));
+
+		assert_eq!(get("status").await.unwrap().text().await.unwrap(), "ready!");
+
+		// requests are still accepted during the drain period, but the server is not ready
+		server.set_drain_period(Duration::from_millis(500));
+		let shutdown = tokio::spawn(async move {
+			server.shutdown(Duration::from_secs(5)).await;
+			server
+		});
+		sleep(Duration::from_millis(100)).await;
+		let response = get("status").await.unwrap();
+		assert_eq!(response.status(), 503);
+		assert_eq!(response.text().await.unwrap(), "draining!");
+		let response = get("api/status.json").await.unwrap();
+		assert_eq!(response.status(), 503);
+		assert_eq!(response.text().await.unwrap(), "{\"status\":\"draining\"}");
+		assert_eq!(get("slow/").await.unwrap().text().await.unwrap(), "finally");
+		let mut server = shutdown.await.unwrap();
+		assert!(get("status").await.is_err());
+
+		server.set_drain_period(Duration::ZERO);
+		server.start().await.unwrap();
+		assert_eq!(get("status").await.unwrap().text().await.unwrap(), "ready!");
+
+		// active requests are finished
+		let request = tokio::spawn(get("slow/"));
+		sleep(Duration::from_millis(250)).await;
+		let start = Instant::now();
+		server.shutdown(Duration::from_secs(5)).await;
+		assert!(start.elapsed() >= Duration::from_millis(100));
+		assert!(start.elapsed() < Duration::from_secs(5));
+		assert_eq!(request.await.unwrap().unwrap().text().await.unwrap(), "finally");
+
+		// new connections are refused
+		assert!(get("status").await.is_err());
+
+		// requests exceeding the timeout are cancelled
+		server.start().await.unwrap();
+		let request = tokio::spawn(get("slower/"));
+		sleep(Duration::from_millis(100)).await;
+		let start = Instant::now();
+		server.shutdown(Duration::from_millis(200)).await;
+		assert!(start.elapsed() < Duration::from_secs(2));
+		assert!(request.await.unwrap().is_err());
+	}
+
 	#[tokio::test]
-	async fn test_server() {
-		async fn get(path: &str) -> String {
-			reqwest::get(format!("http://{IP}:{PORT}/{path}"))
-				.await
-				.unwrap()
-				.text()
+	async fn test_admin_sources() {
+		const PORT: u16 = 3007;
+
+		let dir = TempDir::new().unwrap();
+		let filename = dir.path().join("cheese.versatiles");
+		copy(
+			make_test_file(TileFormat::PBF, Gzip, 3, "versatiles").await.path(),
+			&filename,
+		)
+		.unwrap();
+
+		let mut server = TileServer::new(IP, PORT);
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+		server.add_tile_source("tiles/dummy", TileContainer::from(reader));
+		server.set_admin_token("secret");
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		let add = |body: String, token: &str| {
+			client
+				.post(format!("http://{IP}:{PORT}/api/admin/sources"))
+				.bearer_auth(token)
+				.body(body)
+				.send()
+		};
+		let remove = |prefix: &str| {
+			client
+				.delete(format!("http://{IP}:{PORT}/api/admin/sources/{prefix}"))
+				.bearer_auth("secret")
+				.send()
+		};
+		let get_status = |path: &str| {
+			let url = format!("http://{IP}:{PORT}/{path}");
+			async move { reqwest::get(url).await.unwrap().status() }
+		};
+		let source = |prefix: &str| format!("{{\"path\":{:?},\"prefix\":\"{prefix}\"}}", filename.to_str().unwrap());
+
+		assert_eq!(add(source("/cheese/"), "cheddar").await.unwrap().status(), 401);
+		assert_eq!(get_status("cheese/meta.json").await, 404);
+
+		// add a source
+		let response = add(source("/cheese/"), "secret").await.unwrap();
+		assert_eq!(response.status(), 201);
+		assert!(response
+			.text()
+			.await
+			.unwrap()
+			.starts_with("{\"prefix\":\"/cheese/\",\"name\":"));
+		assert_eq!(get_status("cheese/0/0/0.pbf").await, 200);
+
+		// errors
+		let response = add(source("/tiles/"), "secret").await.unwrap();
+		assert_eq!(response.status(), 409);
+		assert_eq!(
+			response.text().await.unwrap(),
+			"multiple sources with the prefix '/tiles/' and '/tiles/dummy/' are defined"
+		);
+		let response = add("{\"path\":\"missing.versatiles\"}".to_owned(), "secret")
+			.await
+			.unwrap();
+		assert_eq!(response.status(), 400);
+		let response = add("[broken".to_owned(), "secret").await.unwrap();
+		assert_eq!(response.status(), 400);
+		assert!(response
+			.text()
+			.await
+			.unwrap()
+			.starts_with("invalid source definition: "));
+		let response = add("path: osm.versatiles".to_owned(), "secret").await.unwrap();
+		assert_eq!(response.status(), 400);
+		let broken = dir.path().join("broken.versatiles");
+		write(&broken, "broken").unwrap();
+		let response = add(format!("{{\"path\":{:?}}}", broken.to_str().unwrap()), "secret")
+			.await
+			.unwrap();
+		assert_eq!(response.status(), 400);
+		assert!(response.text().await.unwrap().starts_with("can not open source"));
+
+		// remove the source
+		let response = remove("cheese").await.unwrap();
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.text().await.unwrap(), "{\"removed\":\"/cheese/\"}");
+		assert_eq!(get_status("cheese/0/0/0.pbf").await, 404);
+		assert_eq!(remove("cheese").await.unwrap().status(), 404);
+		assert_eq!(get_status("tiles/dummy/meta.json").await, 200);
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_access_tokens() {
+		const PORT: u16 = 3008;
+
+		let mut server = TileServer::new(IP, PORT);
+		for prefix in ["public", "partner", "internal"] {
+			let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+			server.add_tile_source(prefix, TileContainer::from(reader));
+		}
+		server.set_private("partner");
+		server.set_private("/internal/");
+		server.add_access_token("partner-token", &["partner".to_owned()]);
+		server.add_access_token("admin-token", &["*".to_owned()]);
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		let get = |path: &str, header: Option<(&str, &str)>| {
+			let mut request = client.get(format!("http://{IP}:{PORT}/{path}"));
+			if let Some((key, value)) = header {
+				request = request.header(key, value);
+			}
+			async move {
+				let response = request.send().await.unwrap();
+				(response.status().as_u16(), response.text().await.unwrap())
+			}
+		};
+		let get_status = |path: &str, header: Option<(&str, &str)>| {
+			let request = get(path, header);
+			async move { request.await.0 }
+		};
+
+		assert_eq!(get_status("public/meta.json", Option::None).await, 200);
+		assert_eq!(get_status("partner/meta.json", Option::None).await, 401);
+		assert_eq!(
+			get_status("partner/meta.json", Some(("authorization", "Bearer wrong"))).await,
+			401
+		);
+		assert_eq!(
+			get_status("partner/meta.json", Some(("authorization", "Bearer partner-token"))).await,
+			200
+		);
+		assert_eq!(
+			get_status("partner/meta.json", Some(("x-api-key", "partner-token"))).await,
+			200
+		);
+		assert_eq!(
+			get_status("partner/meta.json?api_key=partner-token", Option::None).await,
+			200
+		);
+		assert_eq!(
+			get_status("internal/meta.json?api_key=partner-token", Option::None).await,
+			403
+		);
+		assert_eq!(
+			get_status("internal/meta.json?api_key=admin-token", Option::None).await,
+			200
+		);
+
+		let list = |header: Option<(&str, &str)>| {
+			let request = get("api/tiles.json", header);
+			async move {
+				let text = request.await.1;
+				["public", "partner", "internal"]
+					.into_iter()
+					.filter(|name| text.contains(&format!("\"url\":\"/{name}/\"")))
+					.collect::<Vec<&str>>()
+			}
+		};
+		assert_eq!(list(Option::None).await, ["public"]);
+		assert_eq!(list(Some(("x-api-key", "partner-token"))).await, ["public", "partner"]);
+		assert_eq!(
+			list(Some(("x-api-key", "admin-token"))).await,
+			["public", "partner", "internal"]
+		);
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_rate_limit() {
+		const PORT: u16 = 3009;
+
+		let mut server = TileServer::new(IP, PORT);
+		for prefix in ["cheese", "limited"] {
+			let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+			server.add_tile_source(prefix, TileContainer::from(reader));
+		}
+		server.set_rate_limit(&RateLimitConfig::new(1, Some(5)));
+		server.set_source_rate_limit("limited", &RateLimitConfig::new(1, Some(1)));
+		server.set_trusted_proxies(&[IpRange::parse("127.0.0.1").unwrap()]);
+		server.set_wmts(true);
+		server.set_ogc_api(true);
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		let get = |path: &str, client_ip: &str| {
+			client
+				.get(format!("http://{IP}:{PORT}/{path}"))
+				.header("x-forwarded-for", client_ip)
+				.send()
+		};
+
+		// per source limit
+		assert_eq!(get("limited/meta.json", "1.1.1.1").await.unwrap().status(), 200);
+		let response = get("limited/meta.json", "1.1.1.1").await.unwrap();
+		assert_eq!(response.status(), 429);
+		assert_eq!(response.headers().get("retry-after").unwrap(), "1");
+
+		// global limit, the first request was already counted
+		for _ in 0..3 {
+			assert_eq!(get("cheese/meta.json", "1.1.1.1").await.unwrap().status(), 200);
+		}
+		assert_eq!(get("cheese/meta.json", "1.1.1.1").await.unwrap().status(), 429);
+
+		// other clients are not affected
+		assert_eq!(get("cheese/meta.json", "2.2.2.2").await.unwrap().status(), 200);
+
+		// the source limit also applies to WMTS and OGC API urls
+		let tile = "wmts/1.0.0/limited/default/GoogleMapsCompatible/0/0/0.pbf";
+		assert_ne!(get(tile, "3.3.3.3").await.unwrap().status(), 429);
+		let tile = "ogc/collections/%6Cimited/tiles/WebMercatorQuad/0/0/0";
+		assert_eq!(get(tile, "3.3.3.3").await.unwrap().status(), 429);
+		let tile = "wmts?SERVICE=WMTS&REQUEST=GetTile&LAYER=limited&TILEMATRIXSET=GoogleMapsCompatible&TILEMATRIX=0&TILEROW=0&TILECOL=0";
+		assert_eq!(get(tile, "3.3.3.3").await.unwrap().status(), 429);
+		assert_eq!(get("ogc/collections/cheese", "3.3.3.3").await.unwrap().status(), 200);
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_static_ranges() {
+		const PORT: u16 = 3010;
+
+		let dir = TempDir::new().unwrap();
+		let content: String = (0..1000).map(|i| format!("{:03}\n", i % 1000)).collect();
+		write(dir.path().join("video.mp4"), &content).unwrap();
+
+		let mut server = TileServer::new(IP, PORT);
+		server.add_static_source(source::Folder::from(dir.path().to_str().unwrap()));
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		let url = format!("http://{IP}:{PORT}/video.mp4");
+
+		// full response
+		let response = client.get(&url).send().await.unwrap();
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+		assert_eq!(response.headers()[CONTENT_LENGTH], "4000");
+		let etag = response.headers()[ETAG].to_str().unwrap().to_owned();
+		assert_eq!(response.text().await.unwrap(), content);
+
+		// HEAD
+		let response = client.head(&url).send().await.unwrap();
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers()[CONTENT_LENGTH], "4000");
+		assert_eq!(response.headers()[ETAG], etag.as_str());
+		assert_eq!(response.text().await.unwrap(), "");
+
+		// compressed responses have the same entity tag as byte ranges
+		let response = client.get(&url).header(ACCEPT_ENCODING, "gzip").send().await.unwrap();
+		assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+		assert_eq!(response.headers()[ETAG], etag.as_str());
+
+		// single range, even if the client accepts compression
+		let response = client
+			.get(&url)
+			.header(RANGE, "bytes=8-15")
+			.header(ACCEPT_ENCODING, "br, gzip")
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(response.status(), 206);
+		assert_eq!(response.headers()[CONTENT_RANGE], "bytes 8-15/4000");
+		assert_eq!(response.headers()[CONTENT_LENGTH], "8");
+		assert!(response.headers().get(CONTENT_ENCODING).is_none());
+		assert_eq!(response.text().await.unwrap(), "002\n003\n");
+
+		// If-Range
+		let range = |if_range: &str| {
+			client
+				.get(&url)
+				.header(RANGE, "bytes=-4")
+				.header(IF_RANGE, if_range)
+				.send()
+		};
+		assert_eq!(range(&etag).await.unwrap().text().await.unwrap(), "999\n");
+		assert_eq!(range("\"outdated\"").await.unwrap().status(), 200);
+
+		// multiple and unsatisfiable ranges
+		for header in ["bytes=0-3,8-11", "bytes=4000-"] {
+			let response = client.get(&url).header(RANGE, header).send().await.unwrap();
+			assert_eq!(response.status(), 416);
+			assert_eq!(response.headers()[CONTENT_RANGE], "bytes */4000");
+		}
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_expose_files() {
+		let file = make_test_file(TileFormat::PBF, Gzip, 3, "versatiles").await;
+		let path = file.to_str().unwrap();
+		let content = std::fs::read(path).unwrap();
+
+		// serves a local file
+		let mut origin = TileServer::new(IP, 3011);
+		let mut container = TileContainer::from(get_reader(path).await.unwrap());
+		container.set_url(path);
+		origin.add_tile_source("osm", container);
+		origin.expose_file("osm", "osm.versatiles");
+		origin.start().await.unwrap();
+
+		// serves the same file as a remote source
+		let url = format!("http://{IP}:3011/files/osm.versatiles");
+		let mut proxy = TileServer::new(IP, 3012);
+		let mut container = TileContainer::from(get_reader(&url).await.unwrap());
+		container.set_url(&url);
+		proxy.add_tile_source("remote", container);
+		proxy.expose_file("remote", "remote.versatiles");
+		proxy.set_private("remote");
+		proxy.add_access_token("secret", &["remote".to_owned()]);
+		proxy.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		for url in [
+			format!("http://{IP}:3011/files/osm.versatiles"),
+			format!("http://{IP}:3012/files/remote.versatiles?api_key=secret"),
+		] {
+			let response = client.get(&url).send().await.unwrap();
+			assert_eq!(response.status(), 200);
+			assert_eq!(response.headers()[CONTENT_TYPE], "application/octet-stream");
+			assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+			let etag = response.headers()[ETAG].to_str().unwrap().to_owned();
+			assert_eq!(response.bytes().await.unwrap(), content);
+
+			let response = client.get(&url).header(RANGE, "bytes=10-29").send().await.unwrap();
+			assert_eq!(response.status(), 206);
+			assert_eq!(
+				response.headers()[CONTENT_RANGE],
+				format!("bytes 10-29/{}", content.len()).as_str()
+			);
+			assert_eq!(response.headers()[CONTENT_LENGTH], "20");
+			assert_eq!(response.bytes().await.unwrap(), content[10..30]);
+
+			let response = client
+				.get(&url)
+				.header(RANGE, "bytes=10-29")
+				.header(IF_RANGE, "\"outdated\"")
+				.send()
 				.await
-				.unwrap()
+				.unwrap();
+			assert_eq!(response.status(), 200);
+
+			let response = client.head(&url).send().await.unwrap();
+			assert_eq!(response.headers()[CONTENT_LENGTH], content.len().to_string().as_str());
+
+			let response = client.get(&url).header(IF_NONE_MATCH, &etag).send().await.unwrap();
+			assert_eq!(response.status(), 304);
+		}
+
+		let get_status = |url: String| async { client.get(url).send().await.unwrap().status() };
+		assert_eq!(
+			get_status(format!("http://{IP}:3012/files/remote.versatiles")).await,
+			401
+		);
+		assert_eq!(
+			get_status(format!("http://{IP}:3011/files/other.versatiles")).await,
+			404
+		);
+		assert_eq!(get_status(format!("http://{IP}:3011/osm/0/0/0")).await, 200);
+
+		proxy.stop().await;
+		origin.stop().await;
+	}
+
+	#[test]
+	fn test_clean_hostname() {
+		assert_eq!(clean_hostname("example.org"), "example.org");
+		assert_eq!(clean_hostname("Maps.Example.ORG:8080"), "maps.example.org");
+		assert_eq!(clean_hostname("example.org."), "example.org");
+		assert_eq!(clean_hostname("[::1]:3000"), "[::1]");
+		assert_eq!(clean_hostname(" localhost "), "localhost");
+	}
+
+	#[tokio::test]
+	async fn test_hosts() {
+		const PORT: u16 = 3016;
+
+		let dir_a = TempDir::new().unwrap();
+		write(dir_a.path().join("index.html"), "site a").unwrap();
+		let dir_default = TempDir::new().unwrap();
+		write(dir_default.path().join("index.html"), "default site").unwrap();
+
+		let new_source = || TileContainer::from(dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8));
+
+		let mut server = TileServer::new(IP, PORT);
+		server.add_tile_source("default", new_source());
+		server.add_static_source(source::Folder::from(dir_default.path().to_str().unwrap()));
+		server.add_host(&["a.example.org", "www.a.example.org"]);
+		server.add_host_tile_source("a.example.org", "osm", new_source());
+		server.add_host_static_source("a.example.org", source::Folder::from(dir_a.path().to_str().unwrap()));
+		server.add_host(&["B.example.org"]);
+		server.add_host_tile_source("b.example.org", "osm", new_source());
+		server.add_host_tile_source("b.example.org", "satellite", new_source());
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		let get = |host: &str, path: &str| {
+			let request = client.get(format!("http://{IP}:{PORT}/{path}")).header("host", host);
+			async move {
+				let response = request.send().await.unwrap();
+				(response.status().as_u16(), response.text().await.unwrap())
+			}
+		};
+
+		assert_eq!(get("a.example.org", "").await, (200, "site a".to_owned()));
+		assert_eq!(get("WWW.A.example.org:3016", "").await, (200, "site a".to_owned()));
+		assert_eq!(get("b.example.org", "").await.0, 404);
+		assert_eq!(get("c.example.org", "").await, (200, "default site".to_owned()));
+
+		assert_eq!(get("a.example.org", "osm/meta.json").await.0, 200);
+		assert_eq!(get("a.example.org", "satellite/meta.json").await.0, 404);
+		assert_eq!(get("a.example.org", "default/meta.json").await.0, 404);
+		assert_eq!(get("b.example.org", "satellite/meta.json").await.0, 200);
+		assert_eq!(get("c.example.org", "default/meta.json").await.0, 200);
+		assert_eq!(get("c.example.org", "osm/meta.json").await.0, 404);
+
+		let list = |host: &'static str| {
+			let request = get(host, "api/tiles.json");
+			async move {
+				let text = request.await.1;
+				["default", "osm", "satellite"]
+					.into_iter()
+					.filter(|name| text.contains(&format!("\"url\":\"/{name}/\"")))
+					.collect::<Vec<&str>>()
+			}
+		};
+		assert_eq!(list("a.example.org").await, ["osm"]);
+		assert_eq!(list("b.example.org").await, ["osm", "satellite"]);
+		assert_eq!(list("localhost").await, ["default"]);
+
+		// dummy sources can not be reloaded, but all sources of all hosts are tried
+		let (reloaded, errors) = server.reload().await;
+		assert!(reloaded.is_empty());
+		assert_eq!(errors.len(), 4);
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_host_services() {
+		const PORT: u16 = 3019;
+
+		let new_source = || TileContainer::from(dummy::TileReader::new_dummy(dummy::ReaderProfile::PngFast, 8));
+
+		let mut server = TileServer::new(IP, PORT);
+		server.add_tile_source("world", new_source());
+		server.add_host(&["a.example.org"]);
+		server.add_host_tile_source("a.example.org", "osm", new_source());
+		server.add_host(&["b.example.org"]);
+		server.add_host_tile_source("b.example.org", "satellite", new_source());
+		server.set_wmts(true);
+		server.set_ogc_api(true);
+		server.start().await.unwrap();
+
+		let client = reqwest::Client::new();
+		let get = |host: &str, path: &str| {
+			let request = client.get(format!("http://{IP}:{PORT}/{path}")).header("host", host);
+			async move { request.send().await.unwrap().text().await.unwrap() }
+		};
+
+		let wmts_layers = |host: &'static str| {
+			let request = get(host, "wmts/1.0.0/WMTSCapabilities.xml");
+			async move {
+				let xml = request.await;
+				["world", "osm", "satellite"]
+					.into_iter()
+					.filter(|name| xml.contains(&format!("<ows:Identifier>{name}</ows:Identifier>")))
+					.collect::<Vec<&str>>()
+			}
+		};
+		assert_eq!(wmts_layers("a.example.org").await, ["osm"]);
+		assert_eq!(wmts_layers("b.example.org").await, ["satellite"]);
+		assert_eq!(wmts_layers("localhost").await, ["world"]);
+
+		let ogc_collections = |host: &'static str| {
+			let request = get(host, "ogc/collections");
+			async move {
+				let json = serde_yaml::from_str::<serde_yaml::Value>(&request.await).unwrap();
+				json["collections"]
+					.as_sequence()
+					.unwrap()
+					.iter()
+					.map(|collection| collection["id"].as_str().unwrap().to_owned())
+					.collect::<Vec<String>>()
+			}
+		};
+		assert_eq!(ogc_collections("a.example.org").await, ["osm"]);
+		assert_eq!(ogc_collections("b.example.org").await, ["satellite"]);
+		assert_eq!(ogc_collections("localhost").await, ["world"]);
+
+		assert!(get("a.example.org", "ogc/collections/satellite")
+			.await
+			.contains("unknown collection"));
+		assert!(!get("b.example.org", "ogc/collections/satellite")
+			.await
+			.contains("unknown collection"));
+
+		server.stop().await;
+	}
+
+	#[test]
+	fn test_file_watch() {
+		let time = SystemTime::UNIX_EPOCH;
+		let later = time + Duration::from_secs(1);
+		let mut watch = FileWatch::new((time, 100));
+
+		assert!(!watch.is_modified((time, 100)));
+		// still being written
+		assert!(!watch.is_modified((later, 200)));
+		assert!(!watch.is_modified((later, 300)));
+		// unchanged for one poll
+		assert!(watch.is_modified((later, 300)));
+		assert!(!watch.is_modified((later, 300)));
+		// only the size changed
+		assert!(!watch.is_modified((later, 400)));
+		assert!(watch.is_modified((later, 400)));
+	}
+
+	#[test]
+	fn test_base_url() {
+		let mut server = TileServer::new("127.0.0.1", 8080);
+		server.add_host(&["maps.example.org"]);
+		let get = |server: &TileServer, host: Option<&str>| {
+			let mut headers = HeaderMap::new();
+			if let Some(host) = host {
+				headers.insert(HOST, host.parse().unwrap());
+			}
+			server.get_base_url().get(&headers)
+		};
+
+		assert_eq!(get(&server, Some("maps.example.org")), "http://maps.example.org");
+		assert_eq!(
+			get(&server, Some("Maps.example.org:8443")),
+			"http://Maps.example.org:8443"
+		);
+		assert_eq!(get(&server, Some("127.0.0.1:8080")), "http://127.0.0.1:8080");
+		assert_eq!(get(&server, Some("evil.example.com")), "http://127.0.0.1:8080");
+		assert_eq!(get(&server, Some("maps.example.org/x\"")), "http://127.0.0.1:8080");
+		assert_eq!(get(&server, Option::None), "http://127.0.0.1:8080");
+
+		server.set_base_url("https://tiles.example.org/");
+		assert_eq!(get(&server, Some("maps.example.org")), "https://tiles.example.org");
+
+		let server = TileServer::new("::1", 8080);
+		assert_eq!(get(&server, Some("[::1]:8080")), "http://[::1]:8080");
+		assert_eq!(get(&server, Some("localhost")), "http://[::1]:8080");
+	}
+
+	#[tokio::test]
+	async fn test_source_dir() {
+		const PORT: u16 = 3017;
+
+		let dir = TempDir::new().unwrap();
+		let mut server = TileServer::new(IP, PORT);
+		server.add_source_dir(SourceDirConfig::new(
+			dir.path().to_str().unwrap(),
+			false,
+			Some("*.versatiles"),
+		));
+		server.set_watch(true);
+		server.start().await.unwrap();
+
+		let get_status = |name: &str| {
+			let url = format!("http://{IP}:{PORT}/tiles/{name}/meta.json");
+			async move { reqwest::get(url).await.unwrap().status().as_u16() }
+		};
+		assert_eq!(get_status("osm").await, 404);
+
+		// files are copied under another name first, so that they are only found when complete
+		let add_file = |file: &NamedTempFile, name: &str| {
+			let temp = dir.path().join(".temp");
+			copy(file.path(), &temp).unwrap();
+			rename(&temp, dir.path().join(name)).unwrap();
+		};
+		let file = make_test_file(TileFormat::PBF, Gzip, 3, "versatiles").await;
+		add_file(&file, "osm.versatiles");
+		add_file(&file, "osm.tar");
+		write(dir.path().join("broken.versatiles"), "broken").unwrap();
+
+		let start = Instant::now();
+		while get_status("osm").await != 200 {
+			assert!(
+				start.elapsed() < Duration::from_secs(10),
+				"new container was not served"
+			);
+			sleep(Duration::from_millis(200)).await;
 		}
+		let prefixes: Vec<String> = server.iter_url_mapping().map(|(prefix, _)| prefix).collect();
+		assert_eq!(prefixes, ["/tiles/osm/"]);
+		assert_eq!(get_status("broken").await, 404);
+
+		server.stop().await;
+	}
 
+	#[test]
+	#[should_panic(expected = "host 'a.example.org' is defined multiple times")]
+	fn test_duplicate_hosts() {
 		let mut server = TileServer::new(IP, PORT);
+		server.add_host(&["a.example.org"]);
+		server.add_host(&["A.example.org"]);
+	}
 
+	#[tokio::test]
+	async fn test_preview() {
+		const PORT: u16 = 3013;
+
+		let mut server = TileServer::new(IP, PORT);
 		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
-		let source = TileContainer::from(reader);
-		server.add_tile_source("cheese", source);
+		server.add_tile_source("tiles/osm", TileContainer::from(reader));
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PngFast, 8);
+		server.add_tile_source("tiles/satellite", TileContainer::from(reader));
+		server.set_private("tiles/satellite");
+		server.add_access_token("secret", &["tiles/satellite".to_owned()]);
+		server.set_preview(true);
+		server.start().await.unwrap();
+
+		let get = |path: &str| reqwest::get(format!("http://{IP}:{PORT}/{path}"));
+
+		let response = get("preview/osm").await.unwrap();
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers()[CONTENT_TYPE], "text/html");
+		assert!(response
+			.text()
+			.await
+			.unwrap()
+			.contains("/preview/assets/maplibre-gl.js"));
+
+		let style = get("preview/osm/style.json").await.unwrap().text().await.unwrap();
+		assert!(style.contains("\"type\":\"vector\",\"tiles\":[\"/tiles/osm/{z}/{x}/{y}\"]"));
+
+		assert_eq!(get("preview/satellite").await.unwrap().status(), 401);
+		let style = get("preview/satellite/style.json?api_key=secret").await.unwrap();
+		assert!(style.text().await.unwrap().contains("\"type\":\"raster\""));
+
+		let response = get("preview/assets/maplibre-gl.js").await.unwrap();
+		assert_eq!(response.headers()[CONTENT_TYPE], "application/javascript");
+		assert_eq!(get("preview/assets/missing.js").await.unwrap().status(), 404);
+		assert_eq!(get("preview/unknown").await.unwrap().status(), 404);
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_wmts() {
+		const PORT: u16 = 3014;
+
+		let mut server = TileServer::new(IP, PORT);
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PngFast, 8);
+		server.add_tile_source("tiles/osm", TileContainer::from(reader));
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PngFast, 8);
+		server.add_tile_source("tiles/satellite", TileContainer::from(reader));
+		server.set_private("tiles/satellite");
+		server.add_access_token("secret", &["tiles/satellite".to_owned()]);
+		server.set_wmts(true);
+		server.start().await.unwrap();
 
+		let get = |path: &str| reqwest::get(format!("http://{IP}:{PORT}/{path}"));
+
+		let response = get("wmts/1.0.0/WMTSCapabilities.xml").await.unwrap();
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers()[CONTENT_TYPE], "application/xml");
+		let xml = response.text().await.unwrap();
+		assert!(xml.contains("<ows:Identifier>osm</ows:Identifier>"));
+		assert!(xml.contains(&format!(
+			"template=\"http://{IP}:{PORT}/wmts/1.0.0/osm/{{Style}}/{{TileMatrixSet}}/{{TileMatrix}}/{{TileRow}}/{{TileCol}}.png\""
+		)));
+		assert!(!xml.contains("satellite"));
+
+		let xml = get("wmts?SERVICE=WMTS&REQUEST=GetCapabilities&api_key=secret")
+			.await
+			.unwrap()
+			.text()
+			.await
+			.unwrap();
+		assert!(xml.contains("<ows:Identifier>satellite</ows:Identifier>"));
+
+		let response = get("wmts/1.0.0/osm/default/GoogleMapsCompatible/3/2/1.png")
+			.await
+			.unwrap();
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+		let response = get("wmts?service=wmts&request=GetTile&version=1.0.0&layer=osm&style=default&tilematrixset=GoogleMapsCompatible&tilematrix=3&tilerow=2&tilecol=1&format=image/png").await.unwrap();
+		assert_eq!(response.status(), 200);
+
+		assert_eq!(
+			get("wmts/1.0.0/satellite/default/GoogleMapsCompatible/3/2/1.png")
+				.await
+				.unwrap()
+				.status(),
+			401
+		);
+		assert_eq!(
+			get("wmts/1.0.0/osm/default/EPSG:4326/3/2/1.png")
+				.await
+				.unwrap()
+				.status(),
+			400
+		);
+		let response = get("wmts?SERVICE=WMTS&REQUEST=GetTile&LAYER=unknown").await.unwrap();
+		assert_eq!(response.status(), 400);
+		assert!(response
+			.text()
+			.await
+			.unwrap()
+			.contains("exceptionCode=\"MissingParameterValue\""));
+
+		server.stop().await;
+	}
+
+	#[tokio::test]
+	async fn test_ogc_api() {
+		const PORT: u16 = 3015;
+
+		let mut server = TileServer::new(IP, PORT);
 		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
-		let source = TileContainer::from(reader);
-		server.add_static_source(source);
+		server.add_tile_source("tiles/osm", TileContainer::from(reader));
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PngFast, 8);
+		server.add_tile_source("tiles/satellite", TileContainer::from(reader));
+		server.set_private("tiles/satellite");
+		server.add_access_token("secret", &["tiles/satellite".to_owned()]);
+		server.set_ogc_api(true);
+		server.start().await.unwrap();
 
-		server.start().await;
+		let get = |path: &str| reqwest::get(format!("http://{IP}:{PORT}/{path}"));
+		let get_json = |path: &'static str| async move {
+			let response = get(path).await.unwrap();
+			assert_eq!(response.status(), 200, "{path}");
+			assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+			serde_yaml::from_str::<serde_yaml::Value>(&response.text().await.unwrap()).unwrap()
+		};
 
-		assert_eq!(get("api/status.json").await, "{\"status\":\"ready\"}");
-		assert_eq!(get("api/tiles.json").await, "[\n\t{ \"url\":\"/cheese/\", \"name\":\"dummy name\", \"info\":{ \"container\":\"dummy container\", \"format\":\"pbf\", \"compression\":\"gzip\", \"zoom_min\":0, \"zoom_max\":8, \"bbox\":[-180.0, -85.05113, 180.0, 85.05112] } }\n]");
-		assert!(get("cheese/0/0/0.png").await.starts_with("\u{1a}4\n\u{5}ocean"));
-		assert_eq!(get("cheese/meta.json").await, "dummy meta data");
-		assert_eq!(get("cheese/tiles.json").await, "dummy meta data");
-		assert_eq!(get("cheese/brum.json").await, "Not Found");
-		assert_eq!(get("status").await, "ready!");
+		let landing_page = get_json("ogc").await;
+		assert_eq!(
+			landing_page["links"][2]["href"],
+			format!("http://{IP}:{PORT}/ogc/collections").as_str()
+		);
+		assert!(get_json("ogc/conformance").await["conformsTo"].is_sequence());
+
+		let collections = get_json("ogc/collections").await;
+		assert_eq!(collections["collections"].as_sequence().unwrap().len(), 1);
+		assert_eq!(collections["collections"][0]["id"], "osm");
+		let collections = get_json("ogc/collections?api_key=secret").await;
+		assert_eq!(collections["collections"].as_sequence().unwrap().len(), 2);
+
+		assert_eq!(get_json("ogc/collections/osm").await["id"], "osm");
+		assert_eq!(
+			get_json("ogc/collections/osm/tiles").await["tilesets"][0]["dataType"],
+			"vector"
+		);
+		let tileset = get_json("ogc/collections/osm/tiles/WebMercatorQuad").await;
+		assert_eq!(tileset["tileMatrixSetLimits"][0]["tileMatrix"], "0");
+		assert_eq!(
+			get_json("ogc/tileMatrixSets").await["tileMatrixSets"][0]["id"],
+			"WebMercatorQuad"
+		);
+		assert_eq!(
+			get_json("ogc/tileMatrixSets/WebMercatorQuad").await["crs"],
+			"http://www.opengis.net/def/crs/EPSG/0/3857"
+		);
+
+		let response = get("ogc/collections/osm/tiles/WebMercatorQuad/3/2/1").await.unwrap();
+		assert_eq!(response.status(), 200);
+		assert_eq!(response.headers()[CONTENT_TYPE], "application/x-protobuf");
+
+		assert_eq!(get("ogc/collections/satellite").await.unwrap().status(), 401);
+		assert_eq!(
+			get("ogc/collections/satellite/tiles/WebMercatorQuad/3/2/1?api_key=secret")
+				.await
+				.unwrap()
+				.status(),
+			200
+		);
+		assert_eq!(get("ogc/collections/unknown").await.unwrap().status(), 404);
+		assert_eq!(get("ogc/tileMatrixSets/WorldCRS84Quad").await.unwrap().status(), 404);
+		assert_eq!(
+			get("ogc/collections/osm/tiles/WorldCRS84Quad/3/2/1")
+				.await
+				.unwrap()
+				.status(),
+			404
+		);
+
+		server.stop().await;
+	}
 
+	#[tokio::test]
+	async fn test_occupied_port() {
+		const PORT: u16 = 3018;
+
+		let _listener = std::net::TcpListener::bind((IP, PORT)).unwrap();
+
+		let mut server = TileServer::new(IP, PORT);
+		let message = server.start().await.unwrap_err().to_string();
+		assert!(
+			message.starts_with("can not listen on 127.0.0.1:3018: "),
+			"unexpected error: {message}"
+		);
 		server.stop().await;
 	}
 
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn test_unix_socket() {
+		use crate::server::UnixSocketConfig;
+		use std::{
+			io::{Read, Write},
+			os::unix::{fs::PermissionsExt, net::UnixStream},
+		};
+
+		let dir = TempDir::new().unwrap();
+		let path = dir.path().join("versatiles.sock");
+
+		// a stale socket of a crashed server
+		drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+		let mut server = TileServer::new(IP, PORT);
+		let reader = dummy::TileReader::new_dummy(dummy::ReaderProfile::PbfFast, 8);
+		server.add_tile_source("cheese", TileContainer::from(reader));
+		server.set_socket(UnixSocketConfig::new(path.to_str().unwrap(), Some(0o660)));
+		server.start().await.unwrap();
+
+		assert_eq!(path.metadata().unwrap().permissions().mode() & 0o777, 0o660);
+
+		let request = |path: std::path::PathBuf, url: &'static str| {
+			tokio::task::spawn_blocking(move || {
+				let mut stream = UnixStream::connect(path).unwrap();
+				write!(stream, "GET {url} HTTP/1.0\r\n\r\n").unwrap();
+				let mut response = Vec::new();
+				stream.read_to_end(&mut response).unwrap();
+				String::from_utf8_lossy(&response).into_owned()
+			})
+		};
+
+		let response = request(path.clone(), "/status").await.unwrap();
+		assert!(response.starts_with("HTTP/1.0 200 OK"));
+		assert!(response.ends_with("ready!"));
+
+		let response = request(path.clone(), "/cheese/0/0/0").await.unwrap();
+		assert!(response.starts_with("HTTP/1.0 200 OK"));
+
+		server.shutdown(Duration::from_secs(1)).await;
+		assert!(!path.exists());
+	}
+
 	#[tokio::test]
 	#[should_panic]
 	async fn test_panic() {
@@ -0,0 +1,120 @@
+use crate::shared::Result;
+use axum_server::tls_rustls::RustlsConfig;
+use serde::Deserialize;
+use std::{
+	fs::metadata,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+use tokio::{
+	task::JoinHandle,
+	time::{interval, Duration},
+};
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// TLS settings of the tile server: a certificate (chain) and a private key, both in PEM format.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+	#[serde(rename = "cert")]
+	cert_path: PathBuf,
+	#[serde(rename = "key")]
+	key_path: PathBuf,
+}
+
+impl TlsConfig {
+	pub fn new(cert_path: &str, key_path: &str) -> TlsConfig {
+		TlsConfig {
+			cert_path: PathBuf::from(cert_path),
+			key_path: PathBuf::from(key_path),
+		}
+	}
+	pub fn get_cert_path(&self) -> &Path {
+		&self.cert_path
+	}
+	pub fn get_key_path(&self) -> &Path {
+		&self.key_path
+	}
+
+	/// Reads certificate and key from disk.
+	pub async fn load(&self) -> Result<RustlsConfig> {
+		Ok(RustlsConfig::from_pem_file(&self.cert_path, &self.key_path).await?)
+	}
+
+	/// Replaces certificate and key of a running server. Existing connections are not affected.
+	pub async fn reload(&self, rustls_config: &RustlsConfig) -> Result<()> {
+		rustls_config
+			.reload_from_pem_file(&self.cert_path, &self.key_path)
+			.await?;
+		Ok(())
+	}
+
+	/// Watches certificate and key files and reloads them whenever they are modified.
+	/// If the new files can't be loaded, the old certificate stays in use.
+	pub fn watch(&self, rustls_config: RustlsConfig) -> JoinHandle<()> {
+		let tls = self.clone();
+
+		tokio::spawn(async move {
+			let mut last_modified = tls.get_modified();
+			let mut ticker = interval(WATCH_INTERVAL);
+
+			loop {
+				ticker.tick().await;
+
+				let modified = tls.get_modified();
+				if modified == last_modified {
+					continue;
+				}
+				last_modified = modified;
+
+				match tls.reload(&rustls_config).await {
+					Ok(_) => log::info!("reloaded TLS certificate {:?}", tls.cert_path),
+					Err(err) => log::error!("can not reload TLS certificate {:?}: {}", tls.cert_path, err),
+				}
+			}
+		})
+	}
+
+	fn get_modified(&self) -> Option<(SystemTime, SystemTime)> {
+		let cert = metadata(&self.cert_path).and_then(|m| m.modified()).ok()?;
+		let key = metadata(&self.key_path).and_then(|m| m.modified()).ok()?;
+		Some((cert, key))
+	}
+}
+
+#[cfg(test)]
+pub mod tests {
+	use super::TlsConfig;
+	use assert_fs::TempDir;
+	use std::fs::write;
+
+	/// Writes a self signed certificate for "localhost" and returns the TLS config.
+	pub fn make_test_tls(dir: &TempDir) -> TlsConfig {
+		let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+		let cert_path = dir.path().join("cert.pem");
+		let key_path = dir.path().join("key.pem");
+		write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+		write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+		TlsConfig::new(cert_path.to_str().unwrap(), key_path.to_str().unwrap())
+	}
+
+	#[tokio::test]
+	async fn load_and_reload() {
+		let dir = TempDir::new().unwrap();
+		let tls = make_test_tls(&dir);
+		let rustls_config = tls.load().await.unwrap();
+
+		// replace the certificate
+		make_test_tls(&dir);
+		tls.reload(&rustls_config).await.unwrap();
+
+		// broken files are rejected
+		write(tls.get_key_path(), "broken").unwrap();
+		assert!(tls.reload(&rustls_config).await.is_err());
+
+		let missing = TlsConfig::new("missing/cert.pem", "missing/key.pem");
+		assert!(missing.load().await.is_err());
+	}
+}
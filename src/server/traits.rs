@@ -1,15 +1,48 @@
-use crate::shared::Compression;
+use crate::{
+	containers::TileReaderStats,
+	shared::{Compression, Error, Result, TileReaderParameters},
+};
 use async_trait::async_trait;
 use axum::{
 	body::{Bytes, Full},
 	response::Response,
 };
 use enumset::EnumSet;
-use std::fmt::Debug;
+use std::{fmt::Debug, path::PathBuf};
 
 #[async_trait]
 pub trait ServerSourceTrait: Send + Sync + Debug {
 	fn get_name(&self) -> String;
 	fn get_info_as_json(&self) -> String;
-	async fn get_data(&self, path: &[&str], accept: EnumSet<Compression>) -> Response<Full<Bytes>>;
+	/// `accept_mime` is the "Accept" header of the request, used to pick the tile format,
+	/// `query` is the query string of the request, used to filter vector tiles
+	async fn get_data(
+		&self, path: &[&str], accept: EnumSet<Compression>, accept_mime: Option<&str>, query: Option<&str>,
+	) -> Response<Full<Bytes>>;
+
+	/// local file of this source, used to watch for modifications
+	fn get_path(&self) -> Option<PathBuf> {
+		None
+	}
+
+	/// tile format and bounding boxes of the underlying reader, used by the WMTS and OGC API endpoints
+	fn get_parameters(&self) -> Option<TileReaderParameters> {
+		None
+	}
+
+	/// file path or url of the underlying container, used to serve the raw file
+	fn get_url(&self) -> Option<String> {
+		None
+	}
+
+	/// counters of the underlying reader, reported by the metrics endpoint
+	fn get_reader_stats(&self) -> Option<TileReaderStats> {
+		None
+	}
+
+	/// opens the source again, e.g. after the file was replaced.
+	/// the new source must be fully validated, because it replaces the running one.
+	async fn reload(&self) -> Result<Box<dyn ServerSourceTrait>> {
+		Err(Error::new(&format!("source {} can not be reloaded", self.get_name())))
+	}
 }
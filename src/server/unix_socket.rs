@@ -0,0 +1,142 @@
+use crate::shared::{Error, Result};
+use std::{
+	fs::{remove_file, set_permissions, Permissions},
+	io::ErrorKind,
+	os::unix::{fs::PermissionsExt, net::UnixStream},
+	path::{Path, PathBuf},
+	pin::Pin,
+	task::{Context, Poll},
+};
+use tokio::net::UnixListener;
+
+/// Unix domain socket of the tile server, an alternative to ip and port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnixSocketConfig {
+	path: PathBuf,
+	mode: Option<u32>,
+}
+
+impl UnixSocketConfig {
+	/// `mode` sets the file permissions of the socket, e.g. 0o660.
+	pub fn new(path: &str, mode: Option<u32>) -> UnixSocketConfig {
+		UnixSocketConfig {
+			path: PathBuf::from(path),
+			mode,
+		}
+	}
+
+	pub fn get_path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Binds the socket. A stale socket file of a crashed server is removed first,
+	/// but the socket of a running server is never replaced.
+	pub fn bind(&self) -> Result<UnixListener> {
+		if let Ok(metadata) = self.path.symlink_metadata() {
+			use std::os::unix::fs::FileTypeExt;
+			if !metadata.file_type().is_socket() {
+				return Err(Error::new(&format!("{:?} exists and is not a socket", self.path)));
+			}
+			match UnixStream::connect(&self.path) {
+				Ok(_) => {
+					return Err(Error::new(&format!(
+						"socket {:?} is used by another process",
+						self.path
+					)))
+				}
+				Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+					log::info!("removing stale socket {:?}", self.path);
+					remove_file(&self.path)?;
+				}
+				Err(err) => return Err(Error::new(&format!("can not check socket {:?}: {err}", self.path))),
+			}
+		}
+
+		let listener = UnixListener::bind(&self.path)
+			.map_err(|err| Error::new(&format!("can not bind socket {:?}: {err}", self.path)))?;
+
+		if let Some(mode) = self.mode {
+			set_permissions(&self.path, Permissions::from_mode(mode))?;
+		}
+
+		Ok(listener)
+	}
+
+	/// Removes the socket file after the server has stopped.
+	pub fn remove(&self) {
+		if let Err(err) = remove_file(&self.path) {
+			if err.kind() != ErrorKind::NotFound {
+				log::warn!("can not remove socket {:?}: {err}", self.path);
+			}
+		}
+	}
+}
+
+/// Parses file permissions in octal notation, e.g. "660" or "0o660".
+pub fn parse_socket_mode(text: &str) -> Result<u32> {
+	let digits = text.trim().trim_start_matches("0o");
+	match u32::from_str_radix(digits, 8) {
+		Ok(mode) if mode <= 0o777 => Ok(mode),
+		_ => Err(Error::new(&format!(
+			"invalid socket mode \"{text}\", use octal notation like 660"
+		))),
+	}
+}
+
+/// Lets hyper accept connections from a unix socket.
+pub struct UnixAccept(pub UnixListener);
+
+impl hyper::server::accept::Accept for UnixAccept {
+	type Conn = tokio::net::UnixStream;
+	type Error = std::io::Error;
+
+	fn poll_accept(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<std::io::Result<Self::Conn>>> {
+		match self.0.poll_accept(cx) {
+			Poll::Ready(Ok((stream, _))) => Poll::Ready(Some(Ok(stream))),
+			Poll::Ready(Err(err)) => Poll::Ready(Some(Err(err))),
+			Poll::Pending => Poll::Pending,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assert_fs::TempDir;
+	use std::fs::write;
+
+	#[test]
+	fn test_parse_socket_mode() {
+		assert_eq!(parse_socket_mode("660").unwrap(), 0o660);
+		assert_eq!(parse_socket_mode("0o600").unwrap(), 0o600);
+		assert_eq!(parse_socket_mode("0777").unwrap(), 0o777);
+		assert!(parse_socket_mode("888").is_err());
+		assert!(parse_socket_mode("7777").is_err());
+	}
+
+	#[tokio::test]
+	async fn test_bind() {
+		let dir = TempDir::new().unwrap();
+		let path = dir.path().join("server.sock");
+		let config = UnixSocketConfig::new(path.to_str().unwrap(), Some(0o600));
+
+		// stale sockets are removed
+		drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+		let listener = config.bind().unwrap();
+		assert_eq!(path.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+
+		// sockets in use are not replaced
+		assert!(config
+			.bind()
+			.unwrap_err()
+			.to_string()
+			.contains("used by another process"));
+		drop(listener);
+		config.remove();
+		assert!(!path.exists());
+
+		// other files are not removed
+		write(&path, "data").unwrap();
+		assert!(config.bind().unwrap_err().to_string().contains("is not a socket"));
+	}
+}
@@ -0,0 +1,249 @@
+use super::source::get_tile_mime;
+use crate::shared::TileReaderParameters;
+use std::fmt::Write;
+
+/// identifier of the only supported tile matrix set, the web mercator grid of XYZ tiles
+pub const WMTS_TILE_MATRIX_SET: &str = "GoogleMapsCompatible";
+/// scale denominator of zoom level 0 in web mercator, for 256 pixel tiles and 0.28 mm pixels
+pub const SCALE_DENOMINATOR_0: f64 = 559082264.0287178;
+/// upper left corner of the web mercator grid in meters
+pub const WEB_MERCATOR_MAX: f64 = 20037508.342789244;
+
+/// A tile source, as listed in the capabilities.
+pub struct WmtsLayer {
+	pub id: String,
+	pub title: String,
+	pub parameters: TileReaderParameters,
+}
+
+/// A WMTS request in KVP encoding, e.g. "?SERVICE=WMTS&REQUEST=GetTile&LAYER=osm&...".
+#[derive(Debug, PartialEq, Eq)]
+pub enum WmtsRequest {
+	GetCapabilities,
+	GetTile {
+		layer: String,
+		tile_matrix_set: String,
+		z: u8,
+		row: u64,
+		col: u64,
+	},
+}
+
+/// An error, answered with an OWS exception report.
+#[derive(Debug, PartialEq, Eq)]
+pub struct WmtsException {
+	/// e.g. "MissingParameterValue", "InvalidParameterValue" or "OperationNotSupported"
+	pub code: &'static str,
+	/// the parameter that caused the error
+	pub locator: String,
+}
+
+impl WmtsException {
+	pub fn new(code: &'static str, locator: &str) -> WmtsException {
+		WmtsException {
+			code,
+			locator: locator.to_owned(),
+		}
+	}
+
+	pub fn to_xml(&self) -> String {
+		format!(
+			"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ows:ExceptionReport xmlns:ows=\"http://www.opengis.net/ows/1.1\" version=\"1.1.0\" xml:lang=\"en\">\n\t<ows:Exception exceptionCode=\"{}\" locator=\"{}\"/>\n</ows:ExceptionReport>\n",
+			self.code,
+			escape_xml(&self.locator)
+		)
+	}
+}
+
+/// Parses the query of a KVP request. Parameter names are case insensitive.
+pub fn parse_kvp_request(query: &str) -> Result<WmtsRequest, WmtsException> {
+	let pairs: Vec<(String, &str)> = query
+		.split('&')
+		.filter_map(|pair| pair.split_once('='))
+		.map(|(key, value)| (key.to_uppercase(), value))
+		.collect();
+	let get = |key: &str| -> Result<&str, WmtsException> {
+		pairs
+			.iter()
+			.find(|(name, _)| name == key)
+			.map(|(_, value)| *value)
+			.ok_or_else(|| WmtsException::new("MissingParameterValue", key))
+	};
+	let parse = |key: &str| -> Result<u64, WmtsException> {
+		get(key)?
+			.parse::<u64>()
+			.map_err(|_| WmtsException::new("InvalidParameterValue", key))
+	};
+
+	if !get("SERVICE")?.eq_ignore_ascii_case("WMTS") {
+		return Err(WmtsException::new("InvalidParameterValue", "SERVICE"));
+	}
+
+	match get("REQUEST")? {
+		"GetCapabilities" => Ok(WmtsRequest::GetCapabilities),
+		"GetTile" => {
+			let z = parse("TILEMATRIX")?;
+			if z > 30 {
+				return Err(WmtsException::new("InvalidParameterValue", "TILEMATRIX"));
+			}
+			Ok(WmtsRequest::GetTile {
+				layer: get("LAYER")?.to_owned(),
+				tile_matrix_set: get("TILEMATRIXSET")?.to_owned(),
+				z: z as u8,
+				row: parse("TILEROW")?,
+				col: parse("TILECOL")?,
+			})
+		}
+		_ => Err(WmtsException::new("OperationNotSupported", "REQUEST")),
+	}
+}
+
+/// Builds the capabilities document. `base_url` is the url of the server, e.g. "https://example.org".
+pub fn make_capabilities(base_url: &str, layers: &[WmtsLayer]) -> String {
+	let base_url = escape_xml(base_url);
+	let mut xml = String::new();
+
+	xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+	xml.push_str("<Capabilities xmlns=\"http://www.opengis.net/wmts/1.0\" xmlns:ows=\"http://www.opengis.net/ows/1.1\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" version=\"1.0.0\">\n");
+	xml.push_str("\t<ows:ServiceIdentification>\n\t\t<ows:Title>VersaTiles</ows:Title>\n\t\t<ows:ServiceType>OGC WMTS</ows:ServiceType>\n\t\t<ows:ServiceTypeVersion>1.0.0</ows:ServiceTypeVersion>\n\t</ows:ServiceIdentification>\n");
+
+	xml.push_str("\t<ows:OperationsMetadata>\n");
+	for (operation, restful_url) in [
+		("GetCapabilities", format!("{base_url}/wmts/1.0.0/WMTSCapabilities.xml")),
+		("GetTile", format!("{base_url}/wmts/1.0.0/")),
+	] {
+		writeln!(
+			xml,
+			"\t\t<ows:Operation name=\"{operation}\">\n\t\t\t<ows:DCP>\n\t\t\t\t<ows:HTTP>"
+		)
+		.unwrap();
+		for (url, encoding) in [(format!("{base_url}/wmts?"), "KVP"), (restful_url, "RESTful")] {
+			writeln!(xml, "\t\t\t\t\t<ows:Get xlink:href=\"{url}\">\n\t\t\t\t\t\t<ows:Constraint name=\"GetEncoding\">\n\t\t\t\t\t\t\t<ows:AllowedValues><ows:Value>{encoding}</ows:Value></ows:AllowedValues>\n\t\t\t\t\t\t</ows:Constraint>\n\t\t\t\t\t</ows:Get>").unwrap();
+		}
+		xml.push_str("\t\t\t\t</ows:HTTP>\n\t\t\t</ows:DCP>\n\t\t</ows:Operation>\n");
+	}
+	xml.push_str("\t</ows:OperationsMetadata>\n");
+
+	xml.push_str("\t<Contents>\n");
+	let mut zoom_max = 0;
+	for layer in layers.iter() {
+		let id = escape_xml(&layer.id);
+		let bbox_pyramide = layer.parameters.get_bbox_pyramide();
+		let tile_format = layer.parameters.get_tile_format();
+		let mime = get_tile_mime(tile_format);
+		let extension = format!("{:?}", tile_format).to_lowercase();
+		zoom_max = zoom_max.max(bbox_pyramide.get_zoom_max().unwrap_or(0));
+
+		xml.push_str("\t\t<Layer>\n");
+		writeln!(xml, "\t\t\t<ows:Title>{}</ows:Title>", escape_xml(&layer.title)).unwrap();
+		if !bbox_pyramide.is_empty() {
+			let [west, south, east, north] = bbox_pyramide.get_geo_bbox();
+			writeln!(xml, "\t\t\t<ows:WGS84BoundingBox>\n\t\t\t\t<ows:LowerCorner>{west} {south}</ows:LowerCorner>\n\t\t\t\t<ows:UpperCorner>{east} {north}</ows:UpperCorner>\n\t\t\t</ows:WGS84BoundingBox>").unwrap();
+		}
+		writeln!(xml, "\t\t\t<ows:Identifier>{id}</ows:Identifier>").unwrap();
+		xml.push_str("\t\t\t<Style isDefault=\"true\"><ows:Identifier>default</ows:Identifier></Style>\n");
+		writeln!(xml, "\t\t\t<Format>{mime}</Format>").unwrap();
+		writeln!(xml, "\t\t\t<TileMatrixSetLink>\n\t\t\t\t<TileMatrixSet>{WMTS_TILE_MATRIX_SET}</TileMatrixSet>\n\t\t\t\t<TileMatrixSetLimits>").unwrap();
+		for (level, bbox) in bbox_pyramide.iter_levels() {
+			writeln!(xml, "\t\t\t\t\t<TileMatrixLimits><TileMatrix>{level}</TileMatrix><MinTileRow>{}</MinTileRow><MaxTileRow>{}</MaxTileRow><MinTileCol>{}</MinTileCol><MaxTileCol>{}</MaxTileCol></TileMatrixLimits>", bbox.y_min, bbox.y_max, bbox.x_min, bbox.x_max).unwrap();
+		}
+		xml.push_str("\t\t\t\t</TileMatrixSetLimits>\n\t\t\t</TileMatrixSetLink>\n");
+		writeln!(xml, "\t\t\t<ResourceURL format=\"{mime}\" resourceType=\"tile\" template=\"{base_url}/wmts/1.0.0/{id}/{{Style}}/{{TileMatrixSet}}/{{TileMatrix}}/{{TileRow}}/{{TileCol}}.{extension}\"/>").unwrap();
+		xml.push_str("\t\t</Layer>\n");
+	}
+
+	xml.push_str("\t\t<TileMatrixSet>\n");
+	writeln!(xml, "\t\t\t<ows:Identifier>{WMTS_TILE_MATRIX_SET}</ows:Identifier>\n\t\t\t<ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>\n\t\t\t<WellKnownScaleSet>urn:ogc:def:wkss:OGC:1.0:GoogleMapsCompatible</WellKnownScaleSet>").unwrap();
+	for level in 0..=zoom_max {
+		let size = 2u64.pow(level as u32);
+		writeln!(xml, "\t\t\t<TileMatrix><ows:Identifier>{level}</ows:Identifier><ScaleDenominator>{}</ScaleDenominator><TopLeftCorner>{} {WEB_MERCATOR_MAX}</TopLeftCorner><TileWidth>256</TileWidth><TileHeight>256</TileHeight><MatrixWidth>{size}</MatrixWidth><MatrixHeight>{size}</MatrixHeight></TileMatrix>", SCALE_DENOMINATOR_0 / size as f64, -WEB_MERCATOR_MAX).unwrap();
+	}
+	xml.push_str("\t\t</TileMatrixSet>\n");
+	xml.push_str("\t</Contents>\n");
+
+	writeln!(
+		xml,
+		"\t<ServiceMetadataURL xlink:href=\"{base_url}/wmts/1.0.0/WMTSCapabilities.xml\"/>"
+	)
+	.unwrap();
+	xml.push_str("</Capabilities>\n");
+
+	xml
+}
+
+fn escape_xml(text: &str) -> String {
+	text
+		.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::shared::{Compression, TileBBox, TileBBoxPyramide, TileFormat};
+
+	#[test]
+	fn test_parse_kvp_request() {
+		assert_eq!(
+			parse_kvp_request("service=WMTS&request=GetCapabilities"),
+			Ok(WmtsRequest::GetCapabilities)
+		);
+		assert_eq!(
+			parse_kvp_request("SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0&LAYER=osm&STYLE=default&TILEMATRIXSET=GoogleMapsCompatible&TILEMATRIX=3&TILEROW=2&TILECOL=5&FORMAT=image/png"),
+			Ok(WmtsRequest::GetTile {
+				layer: "osm".to_owned(),
+				tile_matrix_set: "GoogleMapsCompatible".to_owned(),
+				z: 3,
+				row: 2,
+				col: 5
+			})
+		);
+
+		let error = |query: &str| parse_kvp_request(query).unwrap_err();
+		assert_eq!(error("REQUEST=GetCapabilities").locator, "SERVICE");
+		assert_eq!(
+			error("SERVICE=WMS&REQUEST=GetCapabilities").code,
+			"InvalidParameterValue"
+		);
+		assert_eq!(error("SERVICE=WMTS&REQUEST=GetMap").code, "OperationNotSupported");
+		assert_eq!(
+			error("SERVICE=WMTS&REQUEST=GetTile&LAYER=osm&TILEMATRIXSET=x&TILEMATRIX=3&TILEROW=2"),
+			WmtsException::new("MissingParameterValue", "TILECOL")
+		);
+		assert_eq!(
+			error("SERVICE=WMTS&REQUEST=GetTile&LAYER=osm&TILEMATRIXSET=x&TILEMATRIX=a&TILEROW=2&TILECOL=1"),
+			WmtsException::new("InvalidParameterValue", "TILEMATRIX")
+		);
+		assert!(WmtsException::new("MissingParameterValue", "LAYER")
+			.to_xml()
+			.contains("<ows:Exception exceptionCode=\"MissingParameterValue\" locator=\"LAYER\"/>"));
+	}
+
+	#[test]
+	fn test_capabilities() {
+		let mut bbox_pyramide = TileBBoxPyramide::new_empty();
+		bbox_pyramide.include_bbox(2, &TileBBox::new(1, 1, 2, 3));
+		bbox_pyramide.include_bbox(3, &TileBBox::new(2, 3, 5, 6));
+		let layers = [WmtsLayer {
+			id: "osm".to_owned(),
+			title: "OpenStreetMap & friends".to_owned(),
+			parameters: TileReaderParameters::new(TileFormat::PNG, Compression::None, bbox_pyramide),
+		}];
+
+		let xml = make_capabilities("http://localhost:8080", &layers);
+		let contains = |text: &str| assert!(xml.contains(text), "missing: {text}\n{xml}");
+
+		contains("<ows:Title>OpenStreetMap &amp; friends</ows:Title>");
+		contains("<ows:Identifier>osm</ows:Identifier>");
+		contains("<ows:LowerCorner>-90 -79.17134</ows:LowerCorner>");
+		contains("<ows:UpperCorner>90 40.979893</ows:UpperCorner>");
+		contains("<Format>image/png</Format>");
+		contains("<TileMatrixLimits><TileMatrix>2</TileMatrix><MinTileRow>1</MinTileRow><MaxTileRow>3</MaxTileRow><MinTileCol>1</MinTileCol><MaxTileCol>2</MaxTileCol></TileMatrixLimits>");
+		contains("template=\"http://localhost:8080/wmts/1.0.0/osm/{Style}/{TileMatrixSet}/{TileMatrix}/{TileRow}/{TileCol}.png\"");
+		contains("<TileMatrix><ows:Identifier>0</ows:Identifier><ScaleDenominator>559082264.0287178</ScaleDenominator><TopLeftCorner>-20037508.342789244 20037508.342789244</TopLeftCorner>");
+		contains("<ows:Identifier>3</ows:Identifier><ScaleDenominator>69885283.00358972</ScaleDenominator>");
+		assert!(!xml.contains("<ows:Identifier>4</ows:Identifier>"));
+	}
+}
@@ -5,6 +5,9 @@ use enumset::EnumSetType;
 use flate2::bufread::{GzDecoder, GzEncoder};
 use std::io::{Cursor, Read};
 
+/// quality 11 takes tens of milliseconds per tile, which is too slow for every request
+const BROTLI_FAST_QUALITY: i32 = 4;
+
 /// Enum representing possible compression algorithms
 #[derive(Debug, EnumSetType, ValueEnum)]
 pub enum Compression {
@@ -71,8 +74,21 @@ pub fn decompress_gzip(data: Blob) -> Result<Blob> {
 ///
 /// * `data` - The blob of data to compress
 pub fn compress_brotli(data: Blob) -> Result<Blob> {
+	compress_brotli_with_quality(data, 11)
+}
+
+/// Compresses data using Brotli with a low quality, fast enough to compress responses on the fly
+///
+/// # Arguments
+///
+/// * `data` - The blob of data to compress
+pub fn compress_brotli_fast(data: Blob) -> Result<Blob> {
+	compress_brotli_with_quality(data, BROTLI_FAST_QUALITY)
+}
+
+fn compress_brotli_with_quality(data: Blob, quality: i32) -> Result<Blob> {
 	let params = BrotliEncoderParams {
-		quality: 11,
+		quality,
 		size_hint: data.len(),
 		..Default::default()
 	};
@@ -112,6 +128,9 @@ mod tests {
 		// Check that the original and decompressed data match.
 		assert_eq!(data1, data2);
 
+		// The same for the fast compression.
+		assert_eq!(data1, decompress_brotli(compress_brotli_fast(data1.clone())?)?);
+
 		Ok(())
 	}
 
@@ -1,10 +1,12 @@
-use super::{compress::*, image::*, Blob, Compression, Result};
+use super::{compress::*, image::*, vector_tile_to_geojson, Blob, Compression, Error, Result, TileCoord3};
 use clap::ValueEnum;
 use std::fmt::Debug;
 
 /// A structure representing a function that converts a blob to another blob
 struct FnConv {
 	func: fn(Blob) -> Result<Blob>,
+	/// used instead of `func` by conversions, that need the coordinate of the tile
+	func_at: Option<fn(Blob, &TileCoord3) -> Result<Blob>>,
 	name: String,
 }
 
@@ -13,6 +15,7 @@ impl FnConv {
 	fn new(func: fn(Blob) -> Result<Blob>, name: &str) -> FnConv {
 		FnConv {
 			func,
+			func_at: None,
 			name: name.to_owned(),
 		}
 	}
@@ -22,6 +25,15 @@ impl FnConv {
 		Some(FnConv::new(func, name))
 	}
 
+	/// Create an optional `FnConv` from a function, that needs the coordinate of the tile, and a name
+	fn some_at(func_at: fn(Blob, &TileCoord3) -> Result<Blob>, name: &str) -> Option<FnConv> {
+		Some(FnConv {
+			func: |_| Err(Error::new("this conversion needs the coordinate of the tile")),
+			func_at: Some(func_at),
+			name: name.to_owned(),
+		})
+	}
+
 	#[allow(dead_code)]
 	fn get_function(&self) -> fn(Blob) -> Result<Blob> {
 		self.func
@@ -43,7 +55,7 @@ impl Debug for FnConv {
 }
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Clone, Debug, PartialEq, Eq, ValueEnum)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, ValueEnum)]
 pub enum TileFormat {
 	BIN,
 	PNG,
@@ -84,35 +96,19 @@ impl DataConverter {
 
 		// Create a format converter function based on the source and destination formats.
 		let format_converter_option: Option<FnConv> = if (src_form != dst_form) || force_recompress {
-			use TileFormat::*;
-			match (src_form, dst_form) {
-				(PNG, JPG) => FnConv::some(|tile| -> Result<Blob> { img2jpg(&png2img(tile)?) }, "PNG->JPG"),
-				(PNG, PNG) => FnConv::some(|tile| -> Result<Blob> { img2png(&png2img(tile)?) }, "PNG->PNG"),
-				(PNG, WEBP) => FnConv::some(
-					|tile| -> Result<Blob> { img2webplossless(&png2img(tile)?) },
-					"PNG->WEBP",
-				),
-
-				(JPG, PNG) => FnConv::some(|tile| -> Result<Blob> { img2png(&jpg2img(tile)?) }, "JPG->PNG"),
-				(JPG, WEBP) => FnConv::some(|tile| -> Result<Blob> { img2webp(&jpg2img(tile)?) }, "JPG->WEBP"),
-
-				(WEBP, JPG) => FnConv::some(|tile| -> Result<Blob> { img2jpg(&webp2img(tile)?) }, "WEBP->JPG"),
-				(WEBP, PNG) => FnConv::some(|tile| -> Result<Blob> { img2png(&webp2img(tile)?) }, "WEBP->PNG"),
-
-				(_, _) => {
-					if src_form == dst_form {
-						None
-					} else {
-						todo!("convert {:?} -> {:?}", src_form, dst_form)
-					}
-				}
+			let format_converter = get_format_converter(src_form, dst_form);
+			if format_converter.is_none() && src_form != dst_form {
+				todo!("convert {:?} -> {:?}", src_form, dst_form)
 			}
+			format_converter
 		} else {
 			None
 		};
 
 		// Push the necessary conversion functions to the converter pipeline.
-		if (src_comp == dst_comp) && !force_recompress {
+		// Format converters need uncompressed tiles.
+		let is_uncompressed = format_converter_option.is_none() || src_comp == &Compression::None;
+		if (src_comp == dst_comp) && !force_recompress && is_uncompressed {
 			if let Some(format_converter) = format_converter_option {
 				converter.push(format_converter)
 			}
@@ -135,6 +131,11 @@ impl DataConverter {
 
 		converter
 	}
+	/// Return `true` if tiles can be converted from `src_form` to `dst_form` with `new_tile_recompressor`
+	pub fn can_convert_format(src_form: &TileFormat, dst_form: &TileFormat) -> bool {
+		src_form == dst_form || get_format_converter(src_form, dst_form).is_some()
+	}
+
 	/// Constructs a new `DataConverter` instance that compresses data using the specified compression algorithm.
 	/// The `dst_comp` parameter specifies the compression algorithm to use: `Compression::Uncompressed`, `Compression::Gzip`, or `Compression::Brotli`.
 	pub fn new_compressor(dst_comp: &Compression) -> DataConverter {
@@ -181,6 +182,17 @@ impl DataConverter {
 		Ok(data)
 	}
 
+	/// Runs a tile through the pipeline, like `run`, but also supports conversions that need the coordinate of the tile.
+	pub fn run_at(&self, mut data: Blob, coord: &TileCoord3) -> Result<Blob> {
+		for f in self.pipeline.iter() {
+			data = match f.func_at {
+				Some(func_at) => func_at(data, coord)?,
+				None => (f.func)(data)?,
+			};
+		}
+		Ok(data)
+	}
+
 	/// Returns a string describing the pipeline of conversion functions.
 	pub fn description(&self) -> String {
 		let names: Vec<String> = self.pipeline.iter().map(|e| e.name.clone()).collect();
@@ -188,6 +200,32 @@ impl DataConverter {
 	}
 }
 
+/// Returns the function that converts a tile from `src_form` to `dst_form`, if this conversion is supported
+fn get_format_converter(src_form: &TileFormat, dst_form: &TileFormat) -> Option<FnConv> {
+	use TileFormat::*;
+	match (src_form, dst_form) {
+		(PNG, JPG) => FnConv::some(|tile| -> Result<Blob> { img2jpg(&png2img(tile)?) }, "PNG->JPG"),
+		(PNG, PNG) => FnConv::some(|tile| -> Result<Blob> { img2png(&png2img(tile)?) }, "PNG->PNG"),
+		(PNG, WEBP) => FnConv::some(
+			|tile| -> Result<Blob> { img2webplossless(&png2img(tile)?) },
+			"PNG->WEBP",
+		),
+
+		(JPG, PNG) => FnConv::some(|tile| -> Result<Blob> { img2png(&jpg2img(tile)?) }, "JPG->PNG"),
+		(JPG, WEBP) => FnConv::some(|tile| -> Result<Blob> { img2webp(&jpg2img(tile)?) }, "JPG->WEBP"),
+
+		(WEBP, JPG) => FnConv::some(|tile| -> Result<Blob> { img2jpg(&webp2img(tile)?) }, "WEBP->JPG"),
+		(WEBP, PNG) => FnConv::some(|tile| -> Result<Blob> { img2png(&webp2img(tile)?) }, "WEBP->PNG"),
+
+		(PBF, GEOJSON) => FnConv::some_at(
+			|tile, coord| -> Result<Blob> { vector_tile_to_geojson(&tile, coord) },
+			"PBF->GEOJSON",
+		),
+
+		(_, _) => None,
+	}
+}
+
 /// Implements the `PartialEq` trait for the `DataConverter` struct.
 /// This function returns true if the `description` method of both `DataConverter` instances returns the same value.
 impl PartialEq for DataConverter {
@@ -234,6 +272,37 @@ mod tests {
 		assert_eq!(data_converter.pipeline.len(), 3);
 	}
 
+	#[test]
+	fn test_can_convert_format() {
+		assert!(DataConverter::can_convert_format(&TileFormat::PNG, &TileFormat::WEBP));
+		assert!(DataConverter::can_convert_format(&TileFormat::WEBP, &TileFormat::JPG));
+		assert!(DataConverter::can_convert_format(&TileFormat::PBF, &TileFormat::PBF));
+		assert!(!DataConverter::can_convert_format(&TileFormat::PBF, &TileFormat::PNG));
+		assert!(!DataConverter::can_convert_format(&TileFormat::PNG, &TileFormat::AVIF));
+		assert!(DataConverter::can_convert_format(
+			&TileFormat::PBF,
+			&TileFormat::GEOJSON
+		));
+	}
+
+	#[test]
+	fn test_run_at() {
+		let tile = Blob::from(include_bytes!("../containers/dummy/dummy.pbf").to_vec());
+		let converter = DataConverter::new_tile_recompressor(
+			&TileFormat::PBF,
+			&Compression::Gzip,
+			&TileFormat::GEOJSON,
+			&Compression::Gzip,
+			false,
+		);
+		assert_eq!(converter.description(), "decompress_gzip, PBF->GEOJSON, compress_gzip");
+
+		let compressed = compress_gzip(tile).unwrap();
+		assert!(converter.run(compressed.clone()).is_err());
+		let geojson = decompress_gzip(converter.run_at(compressed, &TileCoord3::new(1, 2, 3)).unwrap()).unwrap();
+		assert!(geojson.as_str().starts_with("{\"type\":\"FeatureCollection\""));
+	}
+
 	// Test function for the `FnConv` struct
 	#[test]
 	fn test_fn_conv() {
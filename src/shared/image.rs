@@ -1,7 +1,8 @@
-use super::{Blob, Error, Result};
+use super::{Blob, Error, Result, TileFormat};
 use image::{
 	codecs::{jpeg, png},
-	load_from_memory_with_format, DynamicImage, ImageEncoder, ImageFormat,
+	imageops::{overlay, FilterType},
+	load_from_memory_with_format, DynamicImage, ImageEncoder, ImageFormat, RgbaImage,
 };
 use webp::{Decoder, Encoder};
 
@@ -141,6 +142,117 @@ pub fn webp2img(data: Blob) -> Result<DynamicImage> {
 	}
 }
 
+/// Decodes a raster tile.
+///
+/// # Arguments
+///
+/// * `data` - A `Blob` containing the encoded tile.
+/// * `format` - The format of the tile: PNG, JPG or WEBP.
+///
+/// # Returns
+///
+/// A `DynamicImage` containing the decoded tile.
+pub fn decode_image(data: Blob, format: &TileFormat) -> Result<DynamicImage> {
+	match format {
+		TileFormat::PNG => png2img(data),
+		TileFormat::JPG => jpg2img(data),
+		TileFormat::WEBP => webp2img(data),
+		_ => Err(Error::new(&format!("can not decode {format:?} as an image"))),
+	}
+}
+
+/// Encodes an image as a raster tile. Images are converted to RGBA, if WebP does not support their color type.
+///
+/// # Arguments
+///
+/// * `image` - A reference to the `DynamicImage` to encode.
+/// * `format` - The format of the tile: PNG, JPG or WEBP.
+///
+/// # Returns
+///
+/// A `Blob` containing the encoded tile.
+pub fn encode_image(image: &DynamicImage, format: &TileFormat) -> Result<Blob> {
+	match format {
+		TileFormat::PNG => img2png(image),
+		TileFormat::JPG => img2jpg(image),
+		TileFormat::WEBP => match image.color() {
+			image::ColorType::Rgb8 | image::ColorType::Rgba8 => img2webp(image),
+			_ => img2webp(&DynamicImage::ImageRgba8(image.to_rgba8())),
+		},
+		_ => Err(Error::new(&format!("can not encode an image as {format:?}"))),
+	}
+}
+
+/// Crops the part of a tile, that is covered by one of its descendants, and scales it up to the size of the tile.
+///
+/// # Arguments
+///
+/// * `image` - The tile.
+/// * `depth` - The number of zoom levels between the tile and the descendant.
+/// * `x`, `y` - The position of the descendant among the `2^depth * 2^depth` descendants.
+///
+/// # Returns
+///
+/// A `DynamicImage` of the same size as `image`.
+pub fn crop_descendant(image: &DynamicImage, depth: u8, x: u64, y: u64) -> Result<DynamicImage> {
+	let (width, height) = (image.width(), image.height());
+	let crop_width = width.checked_shr(depth as u32).unwrap_or(0);
+	let crop_height = height.checked_shr(depth as u32).unwrap_or(0);
+	if crop_width == 0 || crop_height == 0 {
+		return Err(Error::new(&format!(
+			"a tile of {width}x{height} pixels can not be scaled up by {depth} zoom levels"
+		)));
+	}
+
+	let crop = image.crop_imm(x as u32 * crop_width, y as u32 * crop_height, crop_width, crop_height);
+	Ok(crop.resize_exact(width, height, FilterType::CatmullRom))
+}
+
+/// Combines four tiles of the same size to one tile of twice the size.
+///
+/// # Arguments
+///
+/// * `images` - The tiles in the order: top left, top right, bottom left, bottom right.
+///
+/// # Returns
+///
+/// A `DynamicImage` in RGBA, or in RGB if the first tile has no alpha channel.
+pub fn stitch_images(images: [&DynamicImage; 4]) -> Result<DynamicImage> {
+	let (width, height) = (images[0].width(), images[0].height());
+	if images
+		.iter()
+		.any(|image| image.width() != width || image.height() != height)
+	{
+		return Err(Error::new("can not stitch tiles of different sizes"));
+	}
+
+	let mut result = RgbaImage::new(width * 2, height * 2);
+	for (index, image) in images.iter().enumerate() {
+		let (x, y) = ((index % 2) as i64 * width as i64, (index / 2) as i64 * height as i64);
+		overlay(&mut result, &image.to_rgba8(), x, y);
+	}
+
+	let result = DynamicImage::ImageRgba8(result);
+	if images[0].color().has_alpha() {
+		Ok(result)
+	} else {
+		Ok(DynamicImage::ImageRgb8(result.to_rgb8()))
+	}
+}
+
+/// Scales a tile up to twice its size.
+///
+/// # Arguments
+///
+/// * `image` - The tile.
+///
+/// # Returns
+///
+/// A `DynamicImage` of twice the width and height.
+pub fn double_size(image: &DynamicImage) -> DynamicImage {
+	image.resize_exact(image.width() * 2, image.height() * 2, FilterType::CatmullRom)
+}
+
 /// This module contains test functions for encoding and decoding images
 #[cfg(test)]
 mod tests {
@@ -208,6 +320,44 @@ mod tests {
 		Ok(())
 	}
 
+	/// Test the conversion by tile format and the cropping of descendants
+	#[test]
+	fn tile_images() -> Result<()> {
+		let image = get_image_rgb();
+		for format in [TileFormat::PNG, TileFormat::JPG, TileFormat::WEBP] {
+			let decoded = decode_image(encode_image(&image, &format)?, &format)?;
+			compare_images(decoded, image.clone(), 4);
+		}
+		assert!(encode_image(&get_image_grey(), &TileFormat::WEBP).is_ok());
+		assert!(encode_image(&image, &TileFormat::PBF).is_err());
+		assert!(decode_image(Blob::empty(), &TileFormat::SVG).is_err());
+
+		// the bottom right quarter of a gradient from black to white
+		let descendant = crop_descendant(&get_image_grey(), 1, 1, 1)?;
+		assert_eq!((descendant.width(), descendant.height()), (256, 256));
+		let pixels = descendant.to_luma8();
+		assert!(pixels.get_pixel(0, 0).0[0].abs_diff(128) <= 1);
+		assert!(pixels.get_pixel(254, 0).0[0].abs_diff(255) <= 1);
+
+		assert!(crop_descendant(&image, 8, 0, 0).is_ok());
+		assert!(crop_descendant(&image, 9, 0, 0).is_err());
+
+		let grey = get_image_grey();
+		let stitched = stitch_images([&image, &grey, &grey, &image])?;
+		assert_eq!((stitched.width(), stitched.height()), (512, 512));
+		assert_eq!(stitched.color(), image::ColorType::Rgb8);
+		let pixels = stitched.to_rgba8();
+		assert_eq!(pixels.get_pixel(10, 20).0, [10, 245, 20, 255]);
+		assert_eq!(pixels.get_pixel(266, 20).0, [10, 10, 10, 255]);
+		assert_eq!(pixels.get_pixel(266, 276).0, [10, 245, 20, 255]);
+		assert!(stitch_images([&image, &image, &image, &descendant.crop_imm(0, 0, 10, 10)]).is_err());
+
+		let doubled = double_size(&image);
+		assert_eq!((doubled.width(), doubled.height()), (512, 512));
+
+		Ok(())
+	}
+
 	/// Generate a DynamicImage with RGBA colors
 	fn get_image_rgba() -> DynamicImage {
 		DynamicImage::ImageRgba8(RgbaImage::from_fn(256, 256, |x, y| -> Rgba<u8> {
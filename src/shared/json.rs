@@ -0,0 +1,26 @@
+/// Encodes a string as a JSON string literal, including the quotes.
+pub fn json_string(text: &str) -> String {
+	let mut result = String::with_capacity(text.len() + 2);
+	result.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => result.push_str("\\\""),
+			'\\' => result.push_str("\\\\"),
+			c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+			c => result.push(c),
+		}
+	}
+	result.push('"');
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_json_string() {
+		assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+		assert_eq!(json_string("Käse"), "\"Käse\"");
+	}
+}
@@ -3,6 +3,7 @@ mod compress;
 mod convert;
 mod error;
 mod image;
+mod json;
 mod progress;
 mod status_image;
 mod tile_bbox;
@@ -10,12 +11,14 @@ mod tile_bbox_pyramide;
 mod tile_converter_config;
 mod tile_coords;
 mod tile_reader_parameters;
+mod vector_tile;
 
 pub use self::blob::*;
 pub use self::compress::*;
 pub use self::convert::*;
 pub use self::error::*;
 pub use self::image::*;
+pub use self::json::*;
 pub use self::progress::*;
 pub use self::status_image::*;
 pub use self::tile_bbox::*;
@@ -23,3 +26,4 @@ pub use self::tile_bbox_pyramide::*;
 pub use self::tile_converter_config::*;
 pub use self::tile_coords::*;
 pub use self::tile_reader_parameters::*;
+pub use self::vector_tile::*;
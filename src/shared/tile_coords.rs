@@ -1,5 +1,6 @@
 use std::{
 	f32::consts::PI as PI32,
+	f64::consts::PI,
 	fmt::{self, Debug},
 };
 
@@ -76,6 +77,15 @@ impl TileCoord3 {
 			((PI32 * (1.0 - 2.0 * (self.y as f32) / zoom)).exp().atan() / PI32 - 0.25) * 360.0,
 		]
 	}
+	/// Returns longitude and latitude of a position in the tile, from (0, 0) at the top left to (1, 1) at the bottom right.
+	pub fn to_geo_at(&self, x: f64, y: f64) -> [f64; 2] {
+		let zoom: f64 = 2.0f64.powi(self.z as i32);
+
+		[
+			((self.x as f64 + x) / zoom - 0.5) * 360.0,
+			((PI * (1.0 - 2.0 * (self.y as f64 + y) / zoom)).exp().atan() / PI - 0.25) * 360.0,
+		]
+	}
 }
 
 impl Debug for TileCoord3 {
@@ -134,6 +144,14 @@ mod tests {
 		assert_eq!(coord, TileCoord3::new(1, 2, 3));
 	}
 
+	#[test]
+	fn to_geo_at() {
+		let coord = TileCoord3::new(1, 2, 3);
+		assert_eq!(coord.to_geo_at(0.0, 0.0), [-135.0, 66.51326044311185]);
+		assert_eq!(coord.to_geo_at(1.0, 1.0), TileCoord3::new(2, 3, 3).to_geo_at(0.0, 0.0));
+		assert_eq!(TileCoord3::new(0, 0, 0).to_geo_at(0.5, 0.5), [0.0, 0.0]);
+	}
+
 	#[test]
 	fn debug() {
 		assert_eq!(format!("{:?}", TileCoord2::new(1, 2)), "TileCoord2(1, 2)");
@@ -63,6 +63,19 @@ impl TileReaderParameters {
 	}
 }
 
+impl Clone for TileReaderParameters {
+	fn clone(&self) -> TileReaderParameters {
+		// the decompressor can not be cloned, so it is created again
+		let mut parameters = TileReaderParameters::new(
+			self.tile_format.clone(),
+			self.tile_compression,
+			self.bbox_pyramide.clone(),
+		);
+		parameters.flip_vertically = self.flip_vertically;
+		parameters
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -77,6 +90,7 @@ mod tests {
 				assert_eq!(p.get_tile_compression(), &tile_compression);
 				assert_eq!(p.get_bbox_pyramide(), &bbox_pyramide);
 				assert_eq!(p.get_vertical_flip(), flip);
+				assert_eq!(p.clone(), p);
 
 				p.set_tile_format(TileFormat::PNG);
 				p.set_tile_compression(Compression::Gzip);
@@ -0,0 +1,1030 @@
+use super::{json_string, Blob, Error, Result, TileCoord3};
+
+/// field number of the layers in a tile
+const TILE_LAYERS: u64 = 3;
+/// field numbers in a layer
+const LAYER_NAME: u64 = 1;
+const LAYER_FEATURES: u64 = 2;
+const LAYER_KEYS: u64 = 3;
+const LAYER_VALUES: u64 = 4;
+const LAYER_EXTENT: u64 = 5;
+/// field numbers in a feature, the tags are pairs of key and value indexes
+const FEATURE_ID: u64 = 1;
+const FEATURE_TAGS: u64 = 2;
+const FEATURE_TYPE: u64 = 3;
+const FEATURE_GEOMETRY: u64 = 4;
+
+/// geometry types of features
+const POINT: u64 = 1;
+const LINESTRING: u64 = 2;
+const POLYGON: u64 = 3;
+
+/// geometry commands
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+const DEFAULT_EXTENT: u64 = 4096;
+/// geometries are clipped with a buffer of 1/64 of the extent around the tile, so that lines and polygon outlines don't end at the edges
+const BUFFER_RATIO: f64 = 1.0 / 64.0;
+
+/// protobuf wire types
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LENGTH_DELIMITED: u64 = 2;
+const FIXED32: u64 = 5;
+
+/// A layer of a Mapbox Vector Tile. Only the name is decoded, the rest of the layer
+/// is kept as its encoded protobuf message, so that layers can be moved between tiles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VectorTileLayer {
+	pub name: String,
+	data: Vec<u8>,
+}
+
+impl VectorTileLayer {
+	/// Changes the name of the layer.
+	pub fn rename(&mut self, name: &str) -> Result<()> {
+		let mut data: Vec<u8> = Vec::with_capacity(self.data.len() + name.len());
+		write_bytes_field(&mut data, LAYER_NAME, name.as_bytes());
+
+		let mut pos = 0;
+		while pos < self.data.len() {
+			let start = pos;
+			let (number, _) = read_field(&self.data, &mut pos)?;
+			if number != LAYER_NAME {
+				data.extend_from_slice(&self.data[start..pos]);
+			}
+		}
+
+		self.name = name.to_owned();
+		self.data = data;
+		Ok(())
+	}
+
+	/// Removes all properties of the features, except the given ones.
+	pub fn retain_fields(&mut self, fields: &[String]) -> Result<()> {
+		let mut keys: Vec<&[u8]> = Vec::new();
+		let mut values: Vec<&[u8]> = Vec::new();
+		let mut pos = 0;
+		while pos < self.data.len() {
+			match read_field(&self.data, &mut pos)? {
+				(LAYER_KEYS, Some(key)) => keys.push(key),
+				(LAYER_VALUES, Some(value)) => values.push(value),
+				_ => {}
+			}
+		}
+
+		// old index -> new index of the remaining keys and values
+		let mut key_map: Vec<Option<u64>> = vec![None; keys.len()];
+		let mut value_map: Vec<Option<u64>> = vec![None; values.len()];
+		let mut new_keys: Vec<&[u8]> = Vec::new();
+		let mut new_values: Vec<&[u8]> = Vec::new();
+		for (index, key) in keys.iter().enumerate() {
+			if fields.iter().any(|field| field.as_bytes() == *key) {
+				key_map[index] = Some(new_keys.len() as u64);
+				new_keys.push(key);
+			}
+		}
+
+		let mut data: Vec<u8> = Vec::with_capacity(self.data.len());
+		let mut pos = 0;
+		while pos < self.data.len() {
+			let start = pos;
+			match read_field(&self.data, &mut pos)? {
+				(LAYER_FEATURES, Some(feature)) => {
+					let mut tags: Vec<u64> = Vec::new();
+					for pair in read_feature_tags(feature)?.chunks(2) {
+						let (key, value) = match pair {
+							[key, value] => (*key as usize, *value as usize),
+							_ => return Err(Error::new("vector tile feature has an odd number of tags")),
+						};
+						let new_key = match key_map.get(key) {
+							Some(new_key) => *new_key,
+							None => return Err(Error::new("vector tile feature refers to an unknown key")),
+						};
+						let new_key = match new_key {
+							Some(new_key) => new_key,
+							None => continue,
+						};
+						let new_value = match value_map.get_mut(value) {
+							Some(Some(new_value)) => *new_value,
+							Some(new_value) => {
+								*new_value = Some(new_values.len() as u64);
+								new_values.push(values[value]);
+								new_values.len() as u64 - 1
+							}
+							None => return Err(Error::new("vector tile feature refers to an unknown value")),
+						};
+						tags.push(new_key);
+						tags.push(new_value);
+					}
+					write_bytes_field(&mut data, LAYER_FEATURES, &write_feature_tags(feature, &tags)?);
+				}
+				(LAYER_KEYS, _) | (LAYER_VALUES, _) => {}
+				_ => data.extend_from_slice(&self.data[start..pos]),
+			}
+		}
+
+		for key in new_keys {
+			write_bytes_field(&mut data, LAYER_KEYS, key);
+		}
+		for value in new_values {
+			write_bytes_field(&mut data, LAYER_VALUES, value);
+		}
+
+		self.data = data;
+		Ok(())
+	}
+}
+
+impl VectorTileLayer {
+	/// Cuts the part of the layer, that is covered by a descendant tile, and scales it up to a full tile.
+	/// `depth` is the number of zoom levels between the tiles, `x` and `y` the position of the descendant
+	/// among the `2^depth * 2^depth` descendants. Returns false if no feature is left.
+	pub fn crop_descendant(&mut self, depth: u8, x: u64, y: u64) -> Result<bool> {
+		let mut extent = DEFAULT_EXTENT;
+		let mut pos = 0;
+		while pos < self.data.len() {
+			let start = pos;
+			if read_field(&self.data, &mut pos)?.0 == LAYER_EXTENT {
+				extent = read_varint_value(&self.data, start)?;
+			}
+		}
+
+		let scale = (1u64 << depth) as f64;
+		let extent = extent as f64;
+		let transform = |(px, py): (f64, f64)| (px * scale - x as f64 * extent, py * scale - y as f64 * extent);
+		let clip_box = (-extent * BUFFER_RATIO, extent * (1.0 + BUFFER_RATIO));
+
+		let mut data: Vec<u8> = Vec::with_capacity(self.data.len());
+		let mut has_features = false;
+		let mut pos = 0;
+		while pos < self.data.len() {
+			let start = pos;
+			match read_field(&self.data, &mut pos)? {
+				(LAYER_FEATURES, Some(feature)) => {
+					let mut geometry_type = 0;
+					let mut commands: &[u8] = &[];
+					let mut feature_pos = 0;
+					while feature_pos < feature.len() {
+						let field_start = feature_pos;
+						match read_field(feature, &mut feature_pos)? {
+							(FEATURE_TYPE, None) => geometry_type = read_varint_value(feature, field_start)?,
+							(FEATURE_GEOMETRY, Some(packed)) => commands = packed,
+							_ => {}
+						}
+					}
+
+					let parts: Vec<Vec<(f64, f64)>> = decode_geometry(commands)?
+						.into_iter()
+						.map(|part| part.into_iter().map(transform).collect())
+						.collect();
+					let parts = match geometry_type {
+						POINT => clip_points(parts, clip_box),
+						LINESTRING => parts.iter().flat_map(|part| clip_line(part, clip_box)).collect(),
+						POLYGON => clip_polygon(parts, clip_box),
+						_ => continue,
+					};
+
+					let commands = encode_geometry(&parts, geometry_type);
+					if commands.is_empty() {
+						continue;
+					}
+					has_features = true;
+
+					let mut new_feature: Vec<u8> = Vec::with_capacity(feature.len());
+					let mut feature_pos = 0;
+					while feature_pos < feature.len() {
+						let field_start = feature_pos;
+						if read_field(feature, &mut feature_pos)?.0 != FEATURE_GEOMETRY {
+							new_feature.extend_from_slice(&feature[field_start..feature_pos]);
+						}
+					}
+					write_bytes_field(&mut new_feature, FEATURE_GEOMETRY, &commands);
+					write_bytes_field(&mut data, LAYER_FEATURES, &new_feature);
+				}
+				_ => data.extend_from_slice(&self.data[start..pos]),
+			}
+		}
+
+		self.data = data;
+		Ok(has_features)
+	}
+}
+
+/// Decodes the commands of a geometry into points, lines or polygon rings.
+/// Every point of a multi point is a part of its own, rings are not closed.
+fn decode_geometry(packed: &[u8]) -> Result<Vec<Vec<(f64, f64)>>> {
+	let mut parts: Vec<Vec<(f64, f64)>> = Vec::new();
+	let (mut x, mut y) = (0i64, 0i64);
+	let mut pos = 0;
+	while pos < packed.len() {
+		let command = read_varint(packed, &mut pos)? as u32;
+		for _ in 0..(command >> 3) {
+			match command & 7 {
+				MOVE_TO | LINE_TO => {
+					x += decode_zigzag(read_varint(packed, &mut pos)?);
+					y += decode_zigzag(read_varint(packed, &mut pos)?);
+					if command & 7 == MOVE_TO {
+						parts.push(Vec::new());
+					}
+					match parts.last_mut() {
+						Some(part) => part.push((x as f64, y as f64)),
+						None => return Err(Error::new("vector tile geometry starts without MoveTo")),
+					}
+				}
+				CLOSE_PATH => {}
+				id => return Err(Error::new(&format!("unknown vector tile geometry command {id}"))),
+			}
+		}
+	}
+	Ok(parts)
+}
+
+/// Encodes points, lines or polygon rings as geometry commands. Returns nothing if no valid part is left.
+fn encode_geometry(parts: &[Vec<(f64, f64)>], geometry_type: u64) -> Vec<u8> {
+	let min_points = match geometry_type {
+		LINESTRING => 2,
+		POLYGON => 3,
+		_ => 1,
+	};
+
+	let mut commands: Vec<u32> = Vec::new();
+	let mut points: Vec<(i64, i64)> = Vec::new();
+	let (mut x, mut y) = (0i64, 0i64);
+	let mut write_point = |commands: &mut Vec<u32>, (px, py): (i64, i64)| {
+		commands.push(encode_zigzag(px - x));
+		commands.push(encode_zigzag(py - y));
+		(x, y) = (px, py);
+	};
+
+	for part in parts.iter() {
+		let mut part_points: Vec<(i64, i64)> = part.iter().map(|(x, y)| (x.round() as i64, y.round() as i64)).collect();
+		if geometry_type == POINT {
+			points.extend(part_points);
+			continue;
+		}
+		part_points.dedup();
+		if geometry_type == POLYGON && part_points.len() > 1 && part_points.first() == part_points.last() {
+			part_points.pop();
+		}
+		if part_points.len() < min_points {
+			continue;
+		}
+
+		commands.push((1 << 3) | MOVE_TO);
+		write_point(&mut commands, part_points[0]);
+		commands.push(((part_points.len() as u32 - 1) << 3) | LINE_TO);
+		for point in part_points[1..].iter() {
+			write_point(&mut commands, *point);
+		}
+		if geometry_type == POLYGON {
+			commands.push((1 << 3) | CLOSE_PATH);
+		}
+	}
+
+	if !points.is_empty() {
+		commands.push(((points.len() as u32) << 3) | MOVE_TO);
+		for point in points {
+			write_point(&mut commands, point);
+		}
+	}
+
+	let mut packed: Vec<u8> = Vec::new();
+	for command in commands {
+		write_varint(&mut packed, command as u64);
+	}
+	packed
+}
+
+fn decode_zigzag(value: u64) -> i64 {
+	((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn encode_zigzag(value: i64) -> u32 {
+	((value << 1) ^ (value >> 63)) as u32
+}
+
+fn is_inside((x, y): (f64, f64), (min, max): (f64, f64)) -> bool {
+	x >= min && x <= max && y >= min && y <= max
+}
+
+fn clip_points(parts: Vec<Vec<(f64, f64)>>, clip_box: (f64, f64)) -> Vec<Vec<(f64, f64)>> {
+	parts
+		.into_iter()
+		.map(|part| {
+			part
+				.into_iter()
+				.filter(|point| is_inside(*point, clip_box))
+				.collect::<Vec<_>>()
+		})
+		.filter(|part| !part.is_empty())
+		.collect()
+}
+
+/// Clips a line with the Liang-Barsky algorithm. A line that leaves and enters the box again is split.
+fn clip_line(line: &[(f64, f64)], (min, max): (f64, f64)) -> Vec<Vec<(f64, f64)>> {
+	let mut lines: Vec<Vec<(f64, f64)>> = Vec::new();
+	let mut current: Vec<(f64, f64)> = Vec::new();
+
+	for segment in line.windows(2) {
+		let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+		let (dx, dy) = (x1 - x0, y1 - y0);
+		let (mut t0, mut t1) = (0.0f64, 1.0f64);
+		let mut visible = true;
+		for (p, q) in [(-dx, x0 - min), (dx, max - x0), (-dy, y0 - min), (dy, max - y0)] {
+			if p == 0.0 {
+				if q < 0.0 {
+					visible = false;
+				}
+			} else if p < 0.0 {
+				t0 = t0.max(q / p);
+			} else {
+				t1 = t1.min(q / p);
+			}
+		}
+		if !visible || t0 > t1 {
+			if current.len() > 1 {
+				lines.push(std::mem::take(&mut current));
+			}
+			current.clear();
+			continue;
+		}
+
+		let start = (x0 + t0 * dx, y0 + t0 * dy);
+		let end = (x0 + t1 * dx, y0 + t1 * dy);
+		if current.last() != Some(&start) {
+			if current.len() > 1 {
+				lines.push(std::mem::take(&mut current));
+			}
+			current = vec![start];
+		}
+		current.push(end);
+		if t1 < 1.0 {
+			lines.push(std::mem::take(&mut current));
+		}
+	}
+	if current.len() > 1 {
+		lines.push(current);
+	}
+	lines
+}
+
+/// Clips polygon rings with the Sutherland-Hodgman algorithm, which keeps the winding order.
+/// Exterior rings have a positive area, the interior rings that follow are dropped with their exterior ring.
+fn clip_polygon(rings: Vec<Vec<(f64, f64)>>, (min, max): (f64, f64)) -> Vec<Vec<(f64, f64)>> {
+	let mut result: Vec<Vec<(f64, f64)>> = Vec::new();
+	let mut keep_interior = false;
+
+	for ring in rings {
+		let is_exterior = get_area(&ring) > 0.0;
+		if !is_exterior && !keep_interior {
+			continue;
+		}
+
+		let mut clipped = ring;
+		clipped = clip_ring(&clipped, |p| p.0 >= min, |a, b| intersect_x(a, b, min));
+		clipped = clip_ring(&clipped, |p| p.0 <= max, |a, b| intersect_x(a, b, max));
+		clipped = clip_ring(&clipped, |p| p.1 >= min, |a, b| intersect_y(a, b, min));
+		clipped = clip_ring(&clipped, |p| p.1 <= max, |a, b| intersect_y(a, b, max));
+
+		let is_valid = clipped.len() >= 3 && get_area(&clipped) != 0.0;
+		if is_exterior {
+			keep_interior = is_valid;
+		}
+		if is_valid {
+			result.push(clipped);
+		}
+	}
+	result
+}
+
+/// Clips a ring at one edge of the box.
+fn clip_ring(
+	ring: &[(f64, f64)], inside: impl Fn(&(f64, f64)) -> bool,
+	intersect: impl Fn(&(f64, f64), &(f64, f64)) -> (f64, f64),
+) -> Vec<(f64, f64)> {
+	let mut clipped = Vec::with_capacity(ring.len());
+	for (index, point) in ring.iter().enumerate() {
+		let previous = &ring[(index + ring.len() - 1) % ring.len()];
+		match (inside(previous), inside(point)) {
+			(true, true) => clipped.push(*point),
+			(true, false) => clipped.push(intersect(previous, point)),
+			(false, true) => {
+				clipped.push(intersect(previous, point));
+				clipped.push(*point);
+			}
+			(false, false) => {}
+		}
+	}
+	clipped
+}
+
+fn intersect_x(a: &(f64, f64), b: &(f64, f64), x: f64) -> (f64, f64) {
+	(x, a.1 + (b.1 - a.1) * (x - a.0) / (b.0 - a.0))
+}
+
+fn intersect_y(a: &(f64, f64), b: &(f64, f64), y: f64) -> (f64, f64) {
+	(a.0 + (b.0 - a.0) * (y - a.1) / (b.1 - a.1), y)
+}
+
+/// the signed area of a ring, positive for exterior rings of vector tiles
+fn get_area(ring: &[(f64, f64)]) -> f64 {
+	let mut area = 0.0;
+	for (index, (x0, y0)) in ring.iter().enumerate() {
+		let (x1, y1) = ring[(index + 1) % ring.len()];
+		area += x0 * y1 - x1 * y0;
+	}
+	area / 2.0
+}
+
+/// Reads the value of a varint field, that starts at `start`.
+fn read_varint_value(data: &[u8], start: usize) -> Result<u64> {
+	let mut pos = start;
+	read_varint(data, &mut pos)?;
+	read_varint(data, &mut pos)
+}
+
+/// Reads the tags of a feature, either packed or as single values.
+fn read_feature_tags(feature: &[u8]) -> Result<Vec<u64>> {
+	let mut tags = Vec::new();
+	let mut pos = 0;
+	while pos < feature.len() {
+		let start = pos;
+		match read_field(feature, &mut pos)? {
+			(FEATURE_TAGS, Some(packed)) => {
+				let mut packed_pos = 0;
+				while packed_pos < packed.len() {
+					tags.push(read_varint(packed, &mut packed_pos)?);
+				}
+			}
+			(FEATURE_TAGS, None) => tags.push(read_varint_value(feature, start)?),
+			_ => {}
+		}
+	}
+	Ok(tags)
+}
+
+/// Copies a feature and replaces its tags.
+fn write_feature_tags(feature: &[u8], tags: &[u64]) -> Result<Vec<u8>> {
+	let mut data: Vec<u8> = Vec::with_capacity(feature.len());
+	let mut pos = 0;
+	while pos < feature.len() {
+		let start = pos;
+		let (number, _) = read_field(feature, &mut pos)?;
+		if number != FEATURE_TAGS {
+			data.extend_from_slice(&feature[start..pos]);
+		}
+	}
+
+	if !tags.is_empty() {
+		let mut packed: Vec<u8> = Vec::new();
+		for tag in tags.iter() {
+			write_varint(&mut packed, *tag);
+		}
+		write_bytes_field(&mut data, FEATURE_TAGS, &packed);
+	}
+	Ok(data)
+}
+
+/// Decodes the layers of an uncompressed vector tile.
+pub fn decode_vector_tile_layers(tile: &Blob) -> Result<Vec<VectorTileLayer>> {
+	let tile = tile.as_slice();
+	let mut layers = Vec::new();
+
+	let mut pos = 0;
+	while pos < tile.len() {
+		let (number, payload) = read_field(tile, &mut pos)?;
+		if number != TILE_LAYERS {
+			continue;
+		}
+		let data = payload.ok_or_else(|| Error::new("vector tile layer has the wrong wire type"))?;
+
+		let mut name: Option<String> = None;
+		let mut layer_pos = 0;
+		while layer_pos < data.len() {
+			if let (LAYER_NAME, Some(value)) = read_field(data, &mut layer_pos)? {
+				name =
+					Some(String::from_utf8(value.to_vec()).map_err(|_| Error::new("vector tile layer name is not UTF-8"))?);
+			}
+		}
+
+		layers.push(VectorTileLayer {
+			name: name.ok_or_else(|| Error::new("vector tile layer has no name"))?,
+			data: data.to_vec(),
+		});
+	}
+
+	Ok(layers)
+}
+
+/// Keeps only the given layers and fields of an uncompressed vector tile. `None` keeps all of them.
+pub fn filter_vector_tile(tile: &Blob, layers: Option<&[String]>, fields: Option<&[String]>) -> Result<Blob> {
+	let mut tile_layers = decode_vector_tile_layers(tile)?;
+	if let Some(layers) = layers {
+		tile_layers.retain(|layer| layers.contains(&layer.name));
+	}
+	if let Some(fields) = fields {
+		for layer in tile_layers.iter_mut() {
+			layer.retain_fields(fields)?;
+		}
+	}
+	Ok(encode_vector_tile(&tile_layers))
+}
+
+/// Cuts the part of an uncompressed vector tile, that is covered by a descendant tile, and scales it up to a full tile.
+/// `depth` is the number of zoom levels between the tiles, `x` and `y` the position of the descendant
+/// among the `2^depth * 2^depth` descendants.
+pub fn crop_vector_tile(tile: &Blob, depth: u8, x: u64, y: u64) -> Result<Blob> {
+	let mut layers = Vec::new();
+	for mut layer in decode_vector_tile_layers(tile)? {
+		if layer.crop_descendant(depth, x, y)? {
+			layers.push(layer);
+		}
+	}
+	Ok(encode_vector_tile(&layers))
+}
+
+/// Converts an uncompressed vector tile to a GeoJSON FeatureCollection with WGS84 coordinates.
+/// The name of its layer is added to every feature as property "layer".
+pub fn vector_tile_to_geojson(tile: &Blob, coord: &TileCoord3) -> Result<Blob> {
+	let mut features: Vec<String> = Vec::new();
+	for layer in decode_vector_tile_layers(tile)? {
+		layer.add_geojson_features(coord, &mut features)?;
+	}
+	Ok(Blob::from(format!(
+		"{{\"type\":\"FeatureCollection\",\"features\":[{}]}}",
+		features.join(",")
+	)))
+}
+
+impl VectorTileLayer {
+	fn add_geojson_features(&self, coord: &TileCoord3, features: &mut Vec<String>) -> Result<()> {
+		let mut extent = DEFAULT_EXTENT;
+		let mut keys: Vec<String> = Vec::new();
+		let mut values: Vec<String> = Vec::new();
+		let mut pos = 0;
+		while pos < self.data.len() {
+			let start = pos;
+			match read_field(&self.data, &mut pos)? {
+				(LAYER_EXTENT, None) => extent = read_varint_value(&self.data, start)?,
+				(LAYER_KEYS, Some(key)) => keys.push(String::from_utf8_lossy(key).into_owned()),
+				(LAYER_VALUES, Some(value)) => values.push(decode_value(value)?),
+				_ => {}
+			}
+		}
+
+		let extent = extent as f64;
+		let to_geo = |(x, y): &(f64, f64)| -> String {
+			let [lon, lat] = coord.to_geo_at(x / extent, y / extent);
+			format!("[{},{}]", round_coordinate(lon), round_coordinate(lat))
+		};
+		let to_line = |points: &[(f64, f64)]| -> String {
+			let points: Vec<String> = points.iter().map(to_geo).collect();
+			format!("[{}]", points.join(","))
+		};
+
+		let mut pos = 0;
+		while pos < self.data.len() {
+			let feature = match read_field(&self.data, &mut pos)? {
+				(LAYER_FEATURES, Some(feature)) => feature,
+				_ => continue,
+			};
+
+			let mut id: Option<u64> = None;
+			let mut geometry_type = 0;
+			let mut commands: &[u8] = &[];
+			let mut feature_pos = 0;
+			while feature_pos < feature.len() {
+				let start = feature_pos;
+				match read_field(feature, &mut feature_pos)? {
+					(FEATURE_ID, None) => id = Some(read_varint_value(feature, start)?),
+					(FEATURE_TYPE, None) => geometry_type = read_varint_value(feature, start)?,
+					(FEATURE_GEOMETRY, Some(packed)) => commands = packed,
+					_ => {}
+				}
+			}
+
+			let parts = decode_geometry(commands)?;
+			let geometry = match geometry_type {
+				POINT => {
+					let points: Vec<String> = parts.iter().flatten().map(to_geo).collect();
+					match points.len() {
+						0 => continue,
+						1 => format!("{{\"type\":\"Point\",\"coordinates\":{}}}", points[0]),
+						_ => format!("{{\"type\":\"MultiPoint\",\"coordinates\":[{}]}}", points.join(",")),
+					}
+				}
+				LINESTRING => {
+					let lines: Vec<String> = parts
+						.iter()
+						.filter(|part| part.len() > 1)
+						.map(|part| to_line(part))
+						.collect();
+					match lines.len() {
+						0 => continue,
+						1 => format!("{{\"type\":\"LineString\",\"coordinates\":{}}}", lines[0]),
+						_ => format!("{{\"type\":\"MultiLineString\",\"coordinates\":[{}]}}", lines.join(",")),
+					}
+				}
+				POLYGON => {
+					// every exterior ring starts a new polygon, followed by its interior rings
+					let mut polygons: Vec<Vec<String>> = Vec::new();
+					for mut ring in parts.into_iter().filter(|ring| ring.len() > 2) {
+						let area = get_area(&ring);
+						ring.push(ring[0]);
+						if area > 0.0 {
+							polygons.push(vec![to_line(&ring)]);
+						} else if let (true, Some(polygon)) = (area < 0.0, polygons.last_mut()) {
+							polygon.push(to_line(&ring));
+						}
+					}
+					let polygons: Vec<String> = polygons.iter().map(|rings| format!("[{}]", rings.join(","))).collect();
+					match polygons.len() {
+						0 => continue,
+						1 => format!("{{\"type\":\"Polygon\",\"coordinates\":{}}}", polygons[0]),
+						_ => format!("{{\"type\":\"MultiPolygon\",\"coordinates\":[{}]}}", polygons.join(",")),
+					}
+				}
+				_ => continue,
+			};
+
+			let mut properties: Vec<String> = vec![format!("\"layer\":{}", json_string(&self.name))];
+			for pair in read_feature_tags(feature)?.chunks(2) {
+				if let [key, value] = pair {
+					let key = keys.get(*key as usize);
+					let value = values.get(*value as usize);
+					match (key, value) {
+						(Some(key), _) if key == "layer" => {}
+						(Some(key), Some(value)) => properties.push(format!("{}:{value}", json_string(key))),
+						_ => return Err(Error::new("vector tile feature refers to an unknown key or value")),
+					}
+				}
+			}
+
+			let id = id.map_or(String::new(), |id| format!("\"id\":{id},"));
+			features.push(format!(
+				"{{\"type\":\"Feature\",{id}\"geometry\":{geometry},\"properties\":{{{}}}}}",
+				properties.join(",")
+			));
+		}
+
+		Ok(())
+	}
+}
+
+/// Decodes a property value of a vector tile as JSON.
+fn decode_value(value: &[u8]) -> Result<String> {
+	let mut result = String::from("null");
+	let mut pos = 0;
+	while pos < value.len() {
+		let start = pos;
+		let wire_type = read_varint(value, &mut pos.clone())? & 7;
+		let (number, payload) = read_field(value, &mut pos)?;
+		let bytes = &value[start..pos];
+		result = match (number, wire_type, payload) {
+			(1, LENGTH_DELIMITED, Some(text)) => json_string(&String::from_utf8_lossy(text)),
+			(2, FIXED32, _) => json_number(f32::from_le_bytes(bytes[bytes.len() - 4..].try_into().unwrap()) as f64),
+			(3, FIXED64, _) => json_number(f64::from_le_bytes(bytes[bytes.len() - 8..].try_into().unwrap())),
+			(4, VARINT, _) => (read_varint_value(value, start)? as i64).to_string(),
+			(5, VARINT, _) => read_varint_value(value, start)?.to_string(),
+			(6, VARINT, _) => decode_zigzag(read_varint_value(value, start)?).to_string(),
+			(7, VARINT, _) => (read_varint_value(value, start)? != 0).to_string(),
+			(1..=7, _, _) => {
+				return Err(Error::new(&format!(
+					"value field {number} has the wrong wire type {wire_type}"
+				)))
+			}
+			_ => continue,
+		};
+	}
+	Ok(result)
+}
+
+fn json_number(value: f64) -> String {
+	if value.is_finite() {
+		value.to_string()
+	} else {
+		String::from("null")
+	}
+}
+
+/// 7 decimal places are about 1 cm
+fn round_coordinate(value: f64) -> f64 {
+	(value * 1e7).round() / 1e7
+}
+
+/// Encodes layers as an uncompressed vector tile.
+pub fn encode_vector_tile(layers: &[VectorTileLayer]) -> Blob {
+	let mut tile: Vec<u8> = Vec::new();
+	for layer in layers.iter() {
+		write_bytes_field(&mut tile, TILE_LAYERS, &layer.data);
+	}
+	Blob::from(tile)
+}
+
+/// Reads a field, returns its number and the payload of length delimited fields.
+fn read_field<'a>(data: &'a [u8], pos: &mut usize) -> Result<(u64, Option<&'a [u8]>)> {
+	let key = read_varint(data, pos)?;
+	let length = match key & 7 {
+		VARINT => {
+			read_varint(data, pos)?;
+			return Ok((key >> 3, None));
+		}
+		FIXED64 => 8,
+		FIXED32 => 4,
+		LENGTH_DELIMITED => read_varint(data, pos)? as usize,
+		wire_type => return Err(Error::new(&format!("unsupported protobuf wire type {wire_type}"))),
+	};
+
+	let end = pos
+		.checked_add(length)
+		.filter(|end| *end <= data.len())
+		.ok_or_else(|| Error::new("protobuf field exceeds the data"))?;
+	let payload = &data[*pos..end];
+	*pos = end;
+
+	Ok((
+		key >> 3,
+		if key & 7 == LENGTH_DELIMITED {
+			Some(payload)
+		} else {
+			None
+		},
+	))
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64> {
+	let mut value: u64 = 0;
+	for shift in (0..64).step_by(7) {
+		let byte = *data
+			.get(*pos)
+			.ok_or_else(|| Error::new("unexpected end of protobuf data"))?;
+		*pos += 1;
+		value |= ((byte & 0x7f) as u64) << shift;
+		if byte & 0x80 == 0 {
+			return Ok(value);
+		}
+	}
+	Err(Error::new("protobuf varint is too long"))
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: u64) {
+	while value >= 0x80 {
+		data.push((value as u8) | 0x80);
+		value >>= 7;
+	}
+	data.push(value as u8);
+}
+
+fn write_bytes_field(data: &mut Vec<u8>, number: u64, bytes: &[u8]) {
+	write_varint(data, (number << 3) | LENGTH_DELIMITED);
+	write_varint(data, bytes.len() as u64);
+	data.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_varint() {
+		for value in [0, 1, 127, 128, 300, 16384, u32::MAX as u64, u64::MAX] {
+			let mut data = Vec::new();
+			write_varint(&mut data, value);
+			let mut pos = 0;
+			assert_eq!(read_varint(&data, &mut pos).unwrap(), value);
+			assert_eq!(pos, data.len());
+		}
+		assert!(read_varint(&[0x80], &mut 0).is_err());
+	}
+
+	#[test]
+	fn test_dummy_tile() {
+		let tile = Blob::from(include_bytes!("../containers/dummy/dummy.pbf").to_vec());
+
+		let layers = decode_vector_tile_layers(&tile).unwrap();
+		assert_eq!(layers[0].name, "ocean");
+
+		// encoding the decoded layers gives the same tile
+		assert_eq!(encode_vector_tile(&layers).as_vec(), tile.as_vec());
+	}
+
+	#[test]
+	fn test_rename() {
+		let tile = Blob::from(include_bytes!("../containers/dummy/dummy.pbf").to_vec());
+		let mut layers = decode_vector_tile_layers(&tile).unwrap();
+		let length = layers[0].data.len();
+
+		layers[0].rename("sea").unwrap();
+		assert_eq!(layers[0].name, "sea");
+		assert_eq!(layers[0].data.len(), length - 2);
+
+		let layers2 = decode_vector_tile_layers(&encode_vector_tile(&layers)).unwrap();
+		assert_eq!(layers2, layers);
+	}
+
+	/// returns the keys of a layer and the number of keys in every feature
+	fn get_keys(layer: &VectorTileLayer) -> (Vec<String>, Vec<usize>) {
+		let mut keys = Vec::new();
+		let mut tag_counts = Vec::new();
+		let mut pos = 0;
+		while pos < layer.data.len() {
+			match read_field(&layer.data, &mut pos).unwrap() {
+				(LAYER_KEYS, Some(key)) => keys.push(String::from_utf8(key.to_vec()).unwrap()),
+				(LAYER_FEATURES, Some(feature)) => tag_counts.push(read_feature_tags(feature).unwrap().len() / 2),
+				_ => {}
+			}
+		}
+		(keys, tag_counts)
+	}
+
+	#[test]
+	fn test_retain_fields() {
+		// a layer "roads" with two features: {name: "A", class: "main"} and {class: "main"}
+		let mut layer: Vec<u8> = Vec::new();
+		write_bytes_field(&mut layer, LAYER_NAME, b"roads");
+		write_bytes_field(
+			&mut layer,
+			LAYER_FEATURES,
+			&[0x12, 0x04, 0x00, 0x00, 0x01, 0x01, 0x18, 0x02],
+		);
+		write_bytes_field(&mut layer, LAYER_FEATURES, &[0x10, 0x01, 0x10, 0x01, 0x18, 0x02]);
+		write_bytes_field(&mut layer, LAYER_KEYS, b"name");
+		write_bytes_field(&mut layer, LAYER_KEYS, b"class");
+		write_bytes_field(&mut layer, LAYER_VALUES, &[0x0a, 0x01, b'A']);
+		write_bytes_field(&mut layer, LAYER_VALUES, &[0x0a, 0x04, b'm', b'a', b'i', b'n']);
+		let mut tile: Vec<u8> = Vec::new();
+		write_bytes_field(&mut tile, TILE_LAYERS, &layer);
+		write_bytes_field(&mut tile, TILE_LAYERS, &[0x0a, 0x05, b'w', b'a', b't', b'e', b'r']);
+		let tile = Blob::from(tile);
+
+		let layers = decode_vector_tile_layers(&tile).unwrap();
+		assert_eq!(
+			get_keys(&layers[0]),
+			(vec!["name".to_owned(), "class".to_owned()], vec![2, 1])
+		);
+
+		let mut roads = layers[0].clone();
+		roads.retain_fields(&["class".to_owned()]).unwrap();
+		assert_eq!(get_keys(&roads), (vec!["class".to_owned()], vec![1, 1]));
+		assert_eq!(roads.data, {
+			let mut data: Vec<u8> = Vec::new();
+			write_bytes_field(&mut data, LAYER_NAME, b"roads");
+			write_bytes_field(&mut data, LAYER_FEATURES, &[0x18, 0x02, 0x12, 0x02, 0x00, 0x00]);
+			write_bytes_field(&mut data, LAYER_FEATURES, &[0x18, 0x02, 0x12, 0x02, 0x00, 0x00]);
+			write_bytes_field(&mut data, LAYER_KEYS, b"class");
+			write_bytes_field(&mut data, LAYER_VALUES, &[0x0a, 0x04, b'm', b'a', b'i', b'n']);
+			data
+		});
+
+		let filtered = filter_vector_tile(&tile, Some(&["roads".to_owned()]), Some(&[])).unwrap();
+		let filtered = decode_vector_tile_layers(&filtered).unwrap();
+		assert_eq!(filtered.len(), 1);
+		assert_eq!(get_keys(&filtered[0]), (vec![], vec![0, 0]));
+
+		let unfiltered = filter_vector_tile(&tile, Option::None, Option::None).unwrap();
+		assert_eq!(unfiltered.as_vec(), tile.as_vec());
+	}
+
+	#[test]
+	fn test_geometry() {
+		// a line with MoveTo(2,2), LineTo(8,2), LineTo(8,10)
+		let commands = [9, 4, 4, 18, 12, 0, 0, 16];
+		let mut packed = Vec::new();
+		for command in commands {
+			write_varint(&mut packed, command);
+		}
+		let parts = decode_geometry(&packed).unwrap();
+		assert_eq!(parts, vec![vec![(2.0, 2.0), (8.0, 2.0), (8.0, 10.0)]]);
+		assert_eq!(encode_geometry(&parts, LINESTRING), packed);
+
+		assert_eq!(decode_zigzag(3), -2);
+		assert_eq!(encode_zigzag(-2), 3);
+		assert!(decode_geometry(&[0x12, 0x02, 0x02]).is_err());
+	}
+
+	#[test]
+	fn test_clip() {
+		let clip_box = (0.0, 100.0);
+
+		let points = vec![vec![(50.0, 50.0)], vec![(150.0, 50.0)]];
+		assert_eq!(clip_points(points, clip_box), vec![vec![(50.0, 50.0)]]);
+
+		// a line that leaves and enters the box is split
+		let line = [(-100.0, 50.0), (200.0, 50.0), (200.0, 80.0), (50.0, 80.0)];
+		assert_eq!(
+			clip_line(&line, clip_box),
+			vec![vec![(0.0, 50.0), (100.0, 50.0)], vec![(100.0, 80.0), (50.0, 80.0)]]
+		);
+
+		// an exterior ring with a hole, that is outside of the box, and another exterior ring outside with its hole
+		let square = |min: f64, max: f64| vec![(min, min), (max, min), (max, max), (min, max)];
+		let hole = |min: f64, max: f64| vec![(min, min), (min, max), (max, max), (max, min)];
+		let rings = vec![
+			square(50.0, 200.0),
+			hole(150.0, 180.0),
+			square(300.0, 400.0),
+			hole(10.0, 20.0),
+		];
+		let clipped = clip_polygon(rings, clip_box);
+		assert_eq!(clipped.len(), 1);
+		assert_eq!(get_area(&clipped[0]), 2500.0);
+		assert!(clipped[0].iter().all(|point| is_inside(*point, clip_box)));
+	}
+
+	#[test]
+	fn test_crop_vector_tile() {
+		let tile = Blob::from(include_bytes!("../containers/dummy/dummy.pbf").to_vec());
+		let cropped = crop_vector_tile(&tile, 2, 1, 3).unwrap();
+		let layers = decode_vector_tile_layers(&cropped).unwrap();
+		assert!(!layers.is_empty());
+
+		let max = DEFAULT_EXTENT as f64 * (1.0 + BUFFER_RATIO) + 1.0;
+		for layer in layers.iter() {
+			let mut pos = 0;
+			while pos < layer.data.len() {
+				if let (LAYER_FEATURES, Some(feature)) = read_field(&layer.data, &mut pos).unwrap() {
+					let mut feature_pos = 0;
+					while feature_pos < feature.len() {
+						if let (FEATURE_GEOMETRY, Some(commands)) = read_field(feature, &mut feature_pos).unwrap() {
+							for (x, y) in decode_geometry(commands).unwrap().concat() {
+								assert!(x >= -max && x <= max && y >= -max && y <= max, "({x}, {y})");
+							}
+						}
+					}
+				}
+			}
+		}
+
+		// cropping by 0 levels keeps the geometries
+		let same = crop_vector_tile(&tile, 0, 0, 0).unwrap();
+		assert_eq!(
+			decode_vector_tile_layers(&same).unwrap().len(),
+			decode_vector_tile_layers(&tile).unwrap().len()
+		);
+	}
+
+	#[test]
+	fn test_geojson() {
+		// a layer "poi" with the point (2048, 1024) and the properties {name: "A", rank: 3, layer: 1.5}
+		let mut feature: Vec<u8> = vec![0x08, 0x07, 0x18, 0x01];
+		write_bytes_field(&mut feature, FEATURE_TAGS, &[0, 0, 1, 1, 2, 2]);
+		let mut geometry: Vec<u8> = Vec::new();
+		for command in [9, 4096, 2048] {
+			write_varint(&mut geometry, command);
+		}
+		write_bytes_field(&mut feature, FEATURE_GEOMETRY, &geometry);
+
+		let mut layer: Vec<u8> = Vec::new();
+		write_bytes_field(&mut layer, LAYER_NAME, b"poi");
+		write_bytes_field(&mut layer, LAYER_FEATURES, &feature);
+		write_bytes_field(&mut layer, LAYER_KEYS, b"name");
+		write_bytes_field(&mut layer, LAYER_KEYS, b"rank");
+		write_bytes_field(&mut layer, LAYER_KEYS, b"layer");
+		write_bytes_field(&mut layer, LAYER_VALUES, &[0x0a, 0x01, b'A']);
+		write_bytes_field(&mut layer, LAYER_VALUES, &[0x28, 0x03]);
+		write_bytes_field(&mut layer, LAYER_VALUES, &[0x19, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f]);
+		let mut tile: Vec<u8> = Vec::new();
+		write_bytes_field(&mut tile, TILE_LAYERS, &layer);
+
+		let geojson = vector_tile_to_geojson(&Blob::from(tile), &TileCoord3::new(0, 0, 0)).unwrap();
+		assert_eq!(
+			geojson.as_str(),
+			"{\"type\":\"FeatureCollection\",\"features\":[{\"type\":\"Feature\",\"id\":7,\"geometry\":{\"type\":\"Point\",\"coordinates\":[0,66.5132604]},\"properties\":{\"layer\":\"poi\",\"name\":\"A\",\"rank\":3}}]}"
+		);
+
+		// polygons of the dummy tile
+		let tile = Blob::from(include_bytes!("../containers/dummy/dummy.pbf").to_vec());
+		let geojson = vector_tile_to_geojson(&tile, &TileCoord3::new(1, 2, 3)).unwrap();
+		assert!(geojson
+			.as_str()
+			.starts_with("{\"type\":\"FeatureCollection\",\"features\":[{\"type\":\"Feature\","));
+		assert!(geojson.as_str().contains("\"properties\":{\"layer\":\"ocean\""));
+		assert!(geojson.as_str().contains("Polygon"));
+
+		assert_eq!(decode_value(&[0x15, 0, 0, 0xc0, 0x7f]).unwrap(), "null");
+		assert_eq!(decode_value(&[0x30, 0x03]).unwrap(), "-2");
+		assert_eq!(decode_value(&[0x38, 0x01]).unwrap(), "true");
+	}
+
+	#[test]
+	fn test_invalid_tile() {
+		assert!(decode_vector_tile_layers(&Blob::from(vec![0x1a, 0x05, 0x00])).is_err());
+		assert!(decode_vector_tile_layers(&Blob::from(vec![0x1b])).is_err());
+		assert!(decode_vector_tile_layers(&Blob::from(vec![0x1a, 0x02, 0x78, 0x01])).is_err());
+		assert!(decode_vector_tile_layers(&Blob::empty()).unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_invalid_value() {
+		// fields sent with the wire type of another field
+		assert!(decode_value(&[0x10, 0x00]).is_err());
+		assert!(decode_value(&[0x18, 0x00]).is_err());
+		assert!(decode_value(&[0x0d, 0, 0, 0, 0]).is_err());
+		assert!(decode_value(&[0x25, 0, 0, 0, 0]).is_err());
+		assert!(decode_value(&[0x39, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+		assert!(decode_value(&[0x10]).is_err());
+	}
+}
@@ -1,9 +1,12 @@
 use crate::{
-	containers::get_reader,
-	server::{source, TileServer},
+	server::{
+		AccessLogFormat, CorsConfig, RateLimitConfig, ServerConfig, SourceConfig, SourceDirConfig, TileServer, TlsConfig,
+	},
+	shared::{Error, Result},
 };
 use clap::Args;
 use regex::Regex;
+use std::path::PathBuf;
 use tokio::time::{sleep, Duration};
 
 #[derive(Args, Debug)]
@@ -17,30 +20,221 @@ pub struct Subcommand {
 	///    e.g. ".../ukraine.versatiles" will be served at url "/tiles/ukraine/..."
 	/// You can also configure a different name for each file using:
 	///    "[name]file", "file[name]" or "file#name"
-	#[arg(num_args = 1.., required = true, verbatim_doc_comment)]
+	#[arg(num_args = 1.., required_unless_present_any = ["config", "source_dir"], verbatim_doc_comment)]
 	pub sources: Vec<String>,
 
-	/// Serve via socket ip.
-	#[arg(short = 'i', long, default_value = "127.0.0.1")]
-	pub ip: String,
+	/// Serve all tile containers in this directory, named by their file names like the sources above.
+	/// Unreadable containers are skipped. Together with --watch, new containers are served automatically.
+	/// Can be used multiple times.
+	#[arg(long, value_name = "path", verbatim_doc_comment)]
+	pub source_dir: Vec<String>,
 
-	/// Serve via port.
-	#[arg(short, long, default_value = "8080")]
-	pub port: u16,
+	/// Also serve the containers in subdirectories of --source-dir.
+	#[arg(long, requires = "source_dir")]
+	pub source_dir_recursive: bool,
+
+	/// Only serve containers in --source-dir whose file names match this pattern,
+	/// e.g. "osm-*.versatiles". "*" matches any text, "?" a single character.
+	#[arg(long, value_name = "pattern", requires = "source_dir", verbatim_doc_comment)]
+	pub source_dir_glob: Option<String>,
+
+	/// Read the server configuration from a YAML or TOML file.
+	/// Sources and options given on the command line are added to the configuration.
+	#[arg(short, long, value_name = "file", verbatim_doc_comment)]
+	pub config: Option<String>,
+
+	/// Serve via socket ip. [default: 127.0.0.1]
+	#[arg(short = 'i', long)]
+	pub ip: Option<String>,
+
+	/// Serve via port. [default: 8080]
+	#[arg(short, long)]
+	pub port: Option<u16>,
+
+	/// Serve via a unix domain socket instead of ip and port, e.g. "/run/versatiles.sock".
+	/// A stale socket file is removed on startup.
+	#[arg(long, value_name = "path", conflicts_with_all = ["ip", "port"], verbatim_doc_comment)]
+	pub socket: Option<String>,
+
+	/// File permissions of the unix domain socket in octal notation, e.g. "660".
+	#[arg(long, value_name = "mode", requires = "socket")]
+	pub socket_mode: Option<String>,
 
 	/// Serve static content at "http:/.../" from a local folder or tar.
 	/// If multiple static sources are defined, the first hit will be served.
 	#[arg(short = 's', long = "static", verbatim_doc_comment)]
 	pub static_content: Vec<String>,
 
+	/// Allow cross-origin requests (CORS) from this origin.
+	/// Use "*" to allow all origins or "https://*.example.org" to allow all subdomains.
+	/// Can be used multiple times.
+	#[arg(long = "cors", value_name = "origin", verbatim_doc_comment)]
+	pub cors_origins: Vec<String>,
+
+	/// Comma separated list of methods allowed for cross-origin requests. [default: GET,HEAD,OPTIONS]
+	#[arg(long, value_name = "methods", value_delimiter = ',')]
+	pub cors_methods: Vec<String>,
+
+	/// Comma separated list of request headers allowed for cross-origin requests. [default: all]
+	#[arg(long, value_name = "headers", value_delimiter = ',')]
+	pub cors_headers: Vec<String>,
+
+	/// Serve via HTTPS using this certificate (chain) in PEM format.
+	/// Certificate and key are reloaded automatically when the files change.
+	#[arg(long, value_name = "file", requires = "tls_key", verbatim_doc_comment)]
+	pub tls_cert: Option<String>,
+
+	/// Private key in PEM format, used together with --tls-cert.
+	#[arg(long, value_name = "file", requires = "tls_cert")]
+	pub tls_key: Option<String>,
+
+	/// Reload tile sources automatically when their files are modified.
+	/// Sources are also reloaded when the server receives SIGHUP.
+	#[arg(long, verbatim_doc_comment)]
+	pub watch: bool,
+
+	/// Enable the admin API, e.g. "POST /api/admin/reload" to reload all sources
+	/// or "POST /api/admin/sources" and "DELETE /api/admin/sources/{prefix}" to add and remove sources.
+	/// Requests must send the header "Authorization: Bearer <token>".
+	#[arg(long, value_name = "token", verbatim_doc_comment)]
+	pub admin_token: Option<String>,
+
+	/// Serve the container files of all sources at "/files/{name}.{extension}",
+	/// e.g. for clients that read containers themselves via range requests.
+	#[arg(long, verbatim_doc_comment)]
+	pub expose_files: bool,
+
+	/// Enable the endpoint "/metrics" in the Prometheus text format.
+	#[arg(long)]
+	pub metrics: bool,
+
+	/// Enable a map preview of every source at "/preview/{name}", e.g. "/preview/osm".
+	#[arg(long)]
+	pub preview: bool,
+
+	/// Enable an OGC WMTS endpoint for all sources, with the capabilities at "/wmts/1.0.0/WMTSCapabilities.xml".
+	#[arg(long)]
+	pub wmts: bool,
+
+	/// Enable OGC API - Tiles for all sources, with the landing page at "/ogc".
+	#[arg(long)]
+	pub ogc_api: bool,
+
+	/// Public url of the server, e.g. "https://tiles.example.org", used for absolute urls in WMTS and OGC API.
+	/// Without it the "Host" header is used, if it matches a configured host or the listen address.
+	#[arg(long, value_name = "url", verbatim_doc_comment)]
+	pub base_url: Option<String>,
+
+	/// Log every request to this file, or to stdout if "-".
+	/// The file is reopened when the server receives SIGHUP.
+	#[arg(long, value_name = "file", verbatim_doc_comment)]
+	pub access_log: Option<String>,
+
+	/// Format of the access log.
+	#[arg(long, value_enum, requires = "access_log")]
+	pub access_log_format: Option<AccessLogFormat>,
+
+	/// Rotate the access log file when it grows beyond this number of bytes.
+	#[arg(long, value_name = "bytes", requires = "access_log")]
+	pub access_log_max_size: Option<u64>,
+
+	/// Limit the requests per second of each client.
+	#[arg(long, value_name = "requests")]
+	pub rate_limit: Option<u32>,
+
+	/// Number of requests a client may send in a burst. [default: rate limit]
+	#[arg(long, value_name = "requests", requires = "rate_limit")]
+	pub rate_limit_burst: Option<u32>,
+
+	/// IP address or network (e.g. 10.0.0.0/8) of a reverse proxy.
+	/// For requests from trusted proxies the client is identified by the header "X-Forwarded-For".
+	#[arg(long, value_name = "ip", verbatim_doc_comment)]
+	pub trusted_proxy: Vec<String>,
+
+	/// On SIGTERM or SIGINT, keep accepting requests for this many seconds, while "/status" responds with 503,
+	/// so that load balancers can take the server out of rotation. [default: 0]
+	#[arg(long, value_name = "seconds", verbatim_doc_comment)]
+	pub drain_period: Option<u64>,
+
+	/// On SIGTERM or SIGINT, wait this many seconds for active requests
+	/// to finish before shutting down. [default: 10]
+	#[arg(long, value_name = "seconds", verbatim_doc_comment)]
+	pub shutdown_timeout: Option<u64>,
+
 	/// Shutdown server automatically after x milliseconds.
 	#[arg(long)]
 	pub auto_shutdown: Option<u64>,
 }
 
 #[tokio::main]
-pub async fn run(arguments: &Subcommand) {
-	let mut server: TileServer = TileServer::new(&arguments.ip, arguments.port);
+pub async fn run(arguments: &Subcommand) -> Result<()> {
+	let config = get_config(arguments).map_err(|err| Error::new(&format!("invalid server configuration: {err}")))?;
+
+	if let Some(level) = config.logging.get_level_filter()? {
+		log::set_max_level(level);
+	}
+
+	let mut server: TileServer = config.build_server().await?;
+
+	let mut list: Vec<(String, String)> = server.iter_url_mapping().collect();
+	list.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+	list
+		.iter()
+		.for_each(|(url, source)| println!("   {:30}  <-  {}", url.to_owned() + "*", source));
+
+	server.start().await?;
+
+	if let Some(milliseconds) = arguments.auto_shutdown {
+		tokio::select! {
+			_ = sleep(Duration::from_millis(milliseconds)) => {},
+			_ = wait_for_shutdown_signal() => {},
+		}
+	} else {
+		wait_for_shutdown_signal().await;
+	}
+
+	server.shutdown(config.get_shutdown_timeout()).await;
+
+	Ok(())
+}
+
+/// Waits for SIGINT (Ctrl+C) or, on unix, SIGTERM.
+async fn wait_for_shutdown_signal() {
+	#[cfg(unix)]
+	{
+		use tokio::signal::unix::{signal, SignalKind};
+		let mut terminate = signal(SignalKind::terminate()).unwrap();
+		tokio::select! {
+			_ = tokio::signal::ctrl_c() => log::info!("received SIGINT"),
+			_ = terminate.recv() => log::info!("received SIGTERM"),
+		}
+	}
+	#[cfg(not(unix))]
+	{
+		tokio::signal::ctrl_c().await.unwrap();
+		log::info!("received Ctrl+C");
+	}
+}
+
+/// Merges the config file (if any) with the command line arguments and validates the result.
+fn get_config(arguments: &Subcommand) -> Result<ServerConfig> {
+	let mut config = match &arguments.config {
+		Some(filename) => ServerConfig::from_file(filename)?,
+		None => ServerConfig::default(),
+	};
+
+	if arguments.ip.is_some() {
+		config.ip = arguments.ip.clone();
+	}
+
+	if arguments.port.is_some() {
+		config.port = arguments.port;
+	}
+
+	if arguments.socket.is_some() {
+		config.socket = arguments.socket.clone();
+		config.socket_mode = arguments.socket_mode.clone();
+	}
 
 	let patterns: Vec<Regex> = [
 		r"^\[(?P<name>[^\]]+?)\](?P<url>.*)$",
@@ -57,46 +251,106 @@ pub async fn run(arguments: &Subcommand) {
 		let c = pattern.captures(arg).unwrap();
 
 		let url: &str = c.name("url").unwrap().as_str();
-		let name: &str = match c.name("name") {
-			None => {
-				let filename = url.split(&['/', '\\']).last().unwrap();
-				filename.split('.').next().unwrap()
-			}
-			Some(m) => m.as_str(),
-		};
-
-		let reader = get_reader(url).await.unwrap();
-		server.add_tile_source(&format!("/tiles/{name}/"), source::TileContainer::from(reader));
-	}
-
-	for filename in arguments.static_content.iter() {
-		if filename.ends_with(".tar") {
-			server.add_static_source(source::TarFile::from(filename));
-		} else {
-			server.add_static_source(source::Folder::from(filename));
+		let name: Option<&str> = c.name("name").map(|m| m.as_str());
+
+		config.sources.push(SourceConfig::new(url, name));
+	}
+
+	for path in arguments.source_dir.iter() {
+		config.source_dirs.push(SourceDirConfig::new(
+			path,
+			arguments.source_dir_recursive,
+			arguments.source_dir_glob.as_deref(),
+		));
+	}
+
+	if arguments.expose_files {
+		for source in config.sources.iter_mut() {
+			source.expose_file = true;
 		}
 	}
 
-	let mut list: Vec<(String, String)> = server.iter_url_mapping().collect();
-	list.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-	list
-		.iter()
-		.for_each(|(url, source)| println!("   {:30}  <-  {}", url.to_owned() + "*", source));
+	config.static_sources.extend(arguments.static_content.iter().cloned());
 
-	server.start().await;
+	if !arguments.cors_origins.is_empty() {
+		let mut cors = CorsConfig::new(&arguments.cors_origins);
+		cors.set_methods(&arguments.cors_methods);
+		cors.set_headers(&arguments.cors_headers);
+		config.cors = Some(cors);
+	}
 
-	if arguments.auto_shutdown.is_some() {
-		sleep(Duration::from_millis(arguments.auto_shutdown.unwrap())).await
-	} else {
-		loop {
-			sleep(Duration::from_secs(60)).await
+	if let (Some(cert), Some(key)) = (&arguments.tls_cert, &arguments.tls_key) {
+		config.tls = Some(TlsConfig::new(cert, key));
+	}
+
+	if arguments.watch {
+		config.watch = true;
+	}
+
+	if arguments.admin_token.is_some() {
+		config.admin_token = arguments.admin_token.clone();
+	}
+
+	if arguments.metrics {
+		config.metrics = true;
+	}
+
+	if arguments.preview {
+		config.preview = true;
+	}
+
+	if arguments.wmts {
+		config.wmts = true;
+	}
+
+	if arguments.ogc_api {
+		config.ogc_api = true;
+	}
+
+	if arguments.base_url.is_some() {
+		config.base_url = arguments.base_url.clone();
+	}
+
+	if let Some(rate) = arguments.rate_limit {
+		config.rate_limit = Some(RateLimitConfig::new(rate, arguments.rate_limit_burst));
+	}
+
+	config.trusted_proxies.extend(arguments.trusted_proxy.iter().cloned());
+
+	if arguments.drain_period.is_some() {
+		config.drain_period = arguments.drain_period;
+	}
+
+	if arguments.shutdown_timeout.is_some() {
+		config.shutdown_timeout = arguments.shutdown_timeout;
+	}
+
+	if let Some(path) = &arguments.access_log {
+		let mut access_log = config.logging.access.take().unwrap_or_default();
+		access_log.path = Some(PathBuf::from(path));
+		if let Some(format) = arguments.access_log_format {
+			access_log.format = format;
 		}
+		if arguments.access_log_max_size.is_some() {
+			access_log.max_size = arguments.access_log_max_size;
+		}
+		config.logging.access = Some(access_log);
 	}
+
+	config.validate()?;
+
+	Ok(config)
 }
 
 #[cfg(test)]
 mod tests {
-	use crate::tests::run_command;
+	use crate::{
+		containers::tests::make_test_file,
+		shared::{Compression, TileFormat},
+		tests::run_command,
+	};
+	use assert_fs::TempDir;
+	use std::fs::{copy, write};
 
 	#[test]
 	fn test_local() {
@@ -125,4 +379,65 @@ mod tests {
 		])
 		.unwrap();
 	}
+
+	#[tokio::test]
+	async fn test_config() {
+		let dir = TempDir::new().unwrap();
+		let file = make_test_file(TileFormat::PBF, Compression::Gzip, 3, "versatiles").await;
+		copy(file.path(), dir.path().join("osm.versatiles")).unwrap();
+
+		let config = dir.path().join("server.yaml");
+		write(&config, "sources:\n  - path: osm.versatiles\n    name: cheese\n").unwrap();
+
+		std::thread::spawn(move || {
+			run_command(vec![
+				"versatiles",
+				"serve",
+				"-p",
+				"65003",
+				"--auto-shutdown",
+				"500",
+				"--config",
+				config.to_str().unwrap(),
+			])
+			.unwrap();
+		})
+		.join()
+		.unwrap();
+	}
+
+	#[test]
+	fn test_invalid_config() {
+		let dir = TempDir::new().unwrap();
+		let config = dir.path().join("server.yaml");
+		write(&config, "sources: []\nunknown: true\n").unwrap();
+
+		let err = run_command(vec!["versatiles", "serve", "--config", config.to_str().unwrap()]).unwrap_err();
+		assert!(err.starts_with("invalid server configuration: "), "{err}");
+	}
+
+	#[tokio::test]
+	async fn test_source_dir() {
+		let dir = TempDir::new().unwrap();
+		let file = make_test_file(TileFormat::PBF, Compression::Gzip, 3, "versatiles").await;
+		copy(file.path(), dir.path().join("osm.versatiles")).unwrap();
+
+		std::thread::spawn(move || {
+			run_command(vec![
+				"versatiles",
+				"serve",
+				"-p",
+				"65004",
+				"--auto-shutdown",
+				"500",
+				"--source-dir",
+				dir.path().to_str().unwrap(),
+				"--source-dir-glob",
+				"*.versatiles",
+			])
+			.unwrap();
+		})
+		.join()
+		.unwrap();
+	}
 }